@@ -0,0 +1,142 @@
+use std::{
+    fs::{read, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use serde_yaml::to_writer;
+
+use crate::{
+    answers, load_applied, plan_entries, render_entry, resolve_context, saved, usage, Coverage,
+    EntryKind, Options, Repo, Usage, Warnings,
+};
+
+// Written into an adopted project, naming the template it follows and the answers it was
+// generated with
+pub const PROVENANCE_FILE: &str = ".generator.yml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdoptReport {
+    // Files the project has as the template would generate them
+    pub matching: Vec<PathBuf>,
+    // Files the project has, but that differ from what the template generates
+    pub modified: Vec<PathBuf>,
+    // Files the template generates that the project doesn't have
+    pub missing: Vec<PathBuf>,
+    // Files the project has that the template doesn't generate, other than ignored ones
+    pub extra: Vec<PathBuf>,
+}
+
+impl AdoptReport {
+    pub fn drifted(&self) -> bool {
+        !self.modified.is_empty() || !self.missing.is_empty()
+    }
+}
+
+// Renders `template` in memory as if generating `destination`, an existing project, and compares
+// the two without changing anything there but writing the provenance file. `source` is the
+// template as given, rather than where it was fetched to, so it can be fetched again.
+pub fn adopt(
+    template: impl AsRef<Path>,
+    source: &str,
+    destination: impl AsRef<Path>,
+    options: &Options,
+) -> Result<AdoptReport> {
+    let template = template.as_ref();
+    let destination = destination.as_ref();
+    ensure!(
+        destination.is_dir(),
+        "{} is not an existing directory",
+        destination.display()
+    );
+    let def = load_applied(&[template.to_owned()])?;
+    let mut warnings = Warnings::new(def.allow.iter().chain(&options.allow).copied());
+    let undeclared = Usage::collect(&def, template)?.undeclared(&def, template)?;
+    let def = usage::declaring(&def, undeclared);
+    let mut coverage = Coverage::new(&def);
+    let layers = def.layers(template);
+    let def = def.for_generation(&layers)?;
+    let context = resolve_context(
+        &def,
+        &layers,
+        destination,
+        options,
+        &Default::default(),
+        &mut coverage,
+        &mut warnings,
+    )?;
+    let plan = plan_entries(
+        &def,
+        &layers,
+        &context,
+        options,
+        &mut coverage,
+        &mut warnings,
+        &mut vec![],
+    )?;
+    let mut report = AdoptReport::default();
+    for entry in plan.iter().filter(|o| o.kind != EntryKind::Directory) {
+        let existing = destination.join(&entry.destination);
+        if !existing.is_file() {
+            report.missing.push(entry.destination.clone());
+            continue;
+        }
+        let input = entry.root.join(&entry.source);
+        let contents =
+            read(&input).with_context(|| format!("Failed to read file {}", input.display()))?;
+        // Files that aren't UTF-8 are copied, as when generating
+        let generated = match String::from_utf8(contents) {
+            std::result::Result::Ok(text) if entry.kind == EntryKind::Templated => {
+                render_entry(&def, entry, &text, &context)?.into_bytes()
+            }
+            std::result::Result::Ok(text) => text.into_bytes(),
+            Err(e) => e.into_bytes(),
+        };
+        let current = read(&existing)
+            .with_context(|| format!("Failed to read file {}", existing.display()))?;
+        if current == generated {
+            report.matching.push(entry.destination.clone());
+        } else {
+            report.modified.push(entry.destination.clone());
+        }
+    }
+    // Whatever the project ignores, like build output, isn't drift
+    for file in ignore::WalkBuilder::new(destination)
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|o| o.file_name() != ".git")
+        .build()
+    {
+        let file = file?;
+        if !file.file_type().is_some_and(|o| o.is_file()) {
+            continue;
+        }
+        let relative = file
+            .path()
+            .strip_prefix(destination)
+            .expect("Walked paths are in the destination")
+            .to_owned();
+        if relative != Path::new(PROVENANCE_FILE) && !plan.iter().any(|o| o.destination == relative)
+        {
+            report.extra.push(relative);
+        }
+    }
+    report.extra.sort();
+    let mut provenance = serde_json::json!({
+        "template": source,
+        "answers": answers(&def, &context),
+    });
+    if template.join(".git").exists() {
+        provenance["commit"] = Repo::open(template)?.head()?.into();
+    }
+    let path = destination.join(PROVENANCE_FILE);
+    to_writer(
+        File::create(&path).with_context(|| format!("Failed to write {}", path.display()))?,
+        &provenance,
+    )
+    .with_context(|| format!("Failed to write {}", path.display()))?;
+    if let Some(key) = &options.remember {
+        saved::save(key, answers(&def, &context))?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,66 @@
+use std::{
+    fs::{read_dir, remove_dir_all, rename},
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use flate2::read::GzDecoder;
+
+use crate::index::open;
+
+const EXTENSIONS: &[&str] = &[".tar.gz", ".tgz", ".tar", ".zip"];
+
+pub(crate) fn is_archive(source: &str) -> bool {
+    let source = source.to_lowercase();
+    EXTENSIONS.iter().any(|o| source.ends_with(o))
+}
+
+// Archives of a single directory, as release tarballs usually are, hold the template inside it
+pub(crate) fn template_dir(extracted: &Path) -> Result<PathBuf> {
+    let entries = read_dir(extracted)?
+        .filter_map(|o| o.ok())
+        .map(|o| o.path())
+        .collect::<Vec<_>>();
+    Ok(match entries.as_slice() {
+        [only] if only.is_dir() => only.clone(),
+        _ => extracted.to_owned(),
+    })
+}
+
+// Downloads or reads the archive at `source` and extracts it to `destination`, replacing what
+// was there once extraction has succeeded. Returns the template's directory inside it.
+pub(crate) fn extract(source: &str, destination: &Path) -> Result<PathBuf> {
+    let parent = destination
+        .parent()
+        .context("Archive destination has no parent")?;
+    std::fs::create_dir_all(parent)?;
+    let staging = tempfile::Builder::new()
+        .prefix(".generator-extract")
+        .tempdir_in(parent)
+        .context("Failed to create temporary directory")?;
+    let mut contents = vec![];
+    open(source)?
+        .read_to_end(&mut contents)
+        .with_context(|| format!("Failed to read archive {}", source))?;
+    // Detected from the contents as snapshot URLs don't always have an extension
+    if contents.starts_with(b"PK\x03\x04") {
+        zip::ZipArchive::new(Cursor::new(contents))
+            .and_then(|mut o| o.extract(staging.path()))
+            .with_context(|| format!("Failed to extract zip archive {}", source))?;
+    } else if contents.starts_with(&[0x1f, 0x8b]) {
+        tar::Archive::new(GzDecoder::new(Cursor::new(contents)))
+            .unpack(staging.path())
+            .with_context(|| format!("Failed to extract tar archive {}", source))?;
+    } else {
+        tar::Archive::new(Cursor::new(contents))
+            .unpack(staging.path())
+            .with_context(|| format!("Failed to extract tar archive {}", source))?;
+    }
+    if destination.exists() {
+        remove_dir_all(destination)
+            .with_context(|| format!("Failed to remove old {}", destination.display()))?;
+    }
+    rename(staging.path(), destination)?;
+    template_dir(destination)
+}
@@ -0,0 +1,91 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use serde_yaml::{from_reader, Value};
+
+use crate::{get_string, load_definition, run_loaded, Options, Report};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchEntry {
+    pub destination: PathBuf,
+    pub answers: BTreeMap<String, tera::Value>,
+}
+
+// A list of projects to generate from one template:
+//
+//     template: gh:user/service
+//     projects:
+//       - destination: users
+//         answers: { port: 8001 }
+//       - destination: billing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub template: Option<String>,
+    pub entries: Vec<BatchEntry>,
+}
+
+impl Manifest {
+    // Relative destinations are taken from the directory containing the manifest
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let value: Value = from_reader(
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+        )
+        .with_context(|| format!("Invalid yaml in {}", path.display()))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let entries = match value.get("projects") {
+            Some(Value::Sequence(s)) => s
+                .iter()
+                .map(|o| parse_entry(o, base))
+                .collect::<Result<_>>()?,
+            None => bail!("Manifest is missing `projects`"),
+            Some(_) => bail!("Expected `projects` to be a sequence"),
+        };
+        Ok(Self {
+            template: get_string(&value, "template")?,
+            entries,
+        })
+    }
+}
+
+fn parse_entry(value: &Value, base: &Path) -> Result<BatchEntry> {
+    let destination =
+        get_string(value, "destination")?.context("Project is missing a `destination`")?;
+    let answers = match value.get("answers") {
+        Some(answers) => serde_json::from_value(serde_json::to_value(answers)?)
+            .with_context(|| format!("Expected answers for {} to be a mapping", destination))?,
+        None => BTreeMap::new(),
+    };
+    Ok(BatchEntry {
+        destination: base.join(destination),
+        answers,
+    })
+}
+
+// Generates every entry from the same template, parsing its definition once.
+// Entry answers are layered over `options.answers`; one failing entry does not stop the others
+pub fn generate_batch(
+    template: impl AsRef<Path>,
+    entries: &[BatchEntry],
+    options: &Options,
+) -> Result<Vec<(PathBuf, Result<Report>)>> {
+    let templates = [template.as_ref().to_owned()];
+    let def = load_definition(&templates[0])?;
+    Ok(entries
+        .iter()
+        .map(|entry| {
+            let mut options = options.clone();
+            options.answers.extend(entry.answers.clone());
+            let report = if entry.destination.exists() {
+                Err(anyhow!("Destination path exists"))
+            } else {
+                run_loaded(&templates, &def, &entry.destination, &options, None)
+            };
+            (entry.destination.clone(), report)
+        })
+        .collect())
+}
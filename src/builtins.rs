@@ -0,0 +1,151 @@
+use std::{collections::HashMap, process::Command};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local, TimeZone, Utc,
+};
+use once_cell::sync::Lazy;
+
+// When generation started, so that every file and every call to `now()` agree on the time.
+// `SOURCE_DATE_EPOCH` fixes it, for output that's the same however often it's generated
+pub(crate) static GENERATED_AT: Lazy<DateTime<Local>> = Lazy::new(|| {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|o| o.trim().parse().ok())
+        .and_then(|o| Local.timestamp_opt(o, 0).single())
+        .unwrap_or_else(Local::now)
+});
+
+fn command_output(program: &str) -> Option<String> {
+    let output = Command::new(program).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+}
+
+fn username() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .or_else(|| command_output("whoami"))
+        .filter(|o| !o.is_empty())
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| command_output("hostname"))
+        .filter(|o| !o.is_empty())
+}
+
+// Variables available to every template, overridden by anything the template or user defines
+pub(crate) fn context() -> tera::Context {
+    let now = *GENERATED_AT;
+    let mut context = tera::Context::new();
+    context.insert("now", &now.to_rfc3339());
+    context.insert("timestamp", &now.timestamp());
+    context.insert("date", &now.format("%Y-%m-%d").to_string());
+    context.insert("year", &now.format("%Y").to_string());
+    context.insert("os", std::env::consts::OS);
+    context.insert("username", &username().unwrap_or_default());
+    context.insert("hostname", &hostname().unwrap_or_default());
+    context
+}
+
+// Splits on anything not an ASCII letter or digit and at case changes, so `My Project`,
+// `my-project` and `myProject` all give `my`, `project`, and `HTTPServer` gives `http`, `server`
+fn words(name: &str) -> Vec<String> {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut words = vec![];
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let previous = i.checked_sub(1).map(|o| chars[o]);
+        let next = chars.get(i + 1);
+        let boundary = c.is_ascii_uppercase()
+            && match previous {
+                Some(p) if p.is_ascii_lowercase() || p.is_ascii_digit() => true,
+                Some(p) if p.is_ascii_uppercase() => next.is_some_and(char::is_ascii_lowercase),
+                _ => false,
+            };
+        if boundary && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        current.push(c.to_ascii_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+pub(crate) fn names(basename: &str) -> [(&'static str, String); 3] {
+    let words = words(basename);
+    let snake = words.join("_");
+    let crate_name = match snake.chars().next() {
+        None => "_".to_owned(),
+        Some(c) if c.is_ascii_digit() => format!("_{}", snake),
+        Some(_) => snake.clone(),
+    };
+    [
+        ("basename_kebab", words.join("-")),
+        ("basename_snake", snake),
+        ("crate_name", crate_name),
+    ]
+}
+
+fn flag(function: &str, args: &HashMap<String, tera::Value>, key: &str) -> tera::Result<bool> {
+    match args.get(key) {
+        None => Ok(false),
+        Some(tera::Value::Bool(b)) => Ok(*b),
+        Some(_) => Err(format!("`{}` expects `{}` to be a boolean", function, key).into()),
+    }
+}
+
+// The generation time in `format`, checked first as chrono panics formatting with a bad one
+fn format_time(function: &str, format: &str, utc: bool) -> tera::Result<String> {
+    if StrftimeItems::new(format).any(|o| o == Item::Error) {
+        return Err(format!("`{}` was given an invalid format {}", function, format).into());
+    }
+    Ok(if utc {
+        GENERATED_AT.with_timezone(&Utc).format(format).to_string()
+    } else {
+        GENERATED_AT.format(format).to_string()
+    })
+}
+
+fn format_arg(function: &str, args: &HashMap<String, tera::Value>) -> tera::Result<Option<String>> {
+    match args.get("format") {
+        None => Ok(None),
+        Some(tera::Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("`{}` expects `format` to be a string", function).into()),
+    }
+}
+
+// Replaces Tera's own `now(utc=false, timestamp=false)` with one giving the generation time,
+// also taking a `format`
+pub(crate) fn now(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let utc = flag("now", args, "utc")?;
+    if flag("now", args, "timestamp")? {
+        return Ok(GENERATED_AT.timestamp().into());
+    }
+    Ok(match format_arg("now", args)? {
+        Some(format) => format_time("now", &format, utc)?,
+        None if utc => GENERATED_AT.with_timezone(&Utc).to_rfc3339(),
+        None => GENERATED_AT.to_rfc3339(),
+    }
+    .into())
+}
+
+// `date(format="%Y-%m-%d", utc=false)`, the generation date or time in a strftime format
+pub(crate) fn date(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let format = format_arg("date", args)?.unwrap_or_else(|| "%Y-%m-%d".to_owned());
+    Ok(format_time("date", &format, flag("date", args, "utc")?)?.into())
+}
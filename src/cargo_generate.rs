@@ -0,0 +1,197 @@
+use std::{fs::read_to_string, path::Path};
+
+use anyhow::*;
+use regex::Regex;
+use toml::Value;
+
+use crate::{
+    cookiecutter::fnmatch_to_regex, default_files, FileDef, Permissions, RuleOrigin, TemplateDef,
+    VariableDef, WarningKind,
+};
+
+pub(crate) fn detect(template: &Path) -> bool {
+    template.join("cargo-generate.toml").exists()
+        || read_to_string(template.join("Cargo.toml"))
+            .map(|o| o.contains("project-name") || o.contains("crate_name"))
+            .unwrap_or(false)
+}
+
+fn patterns(table: Option<&Value>, key: &str) -> Result<Option<Vec<Regex>>> {
+    table
+        .and_then(|o| o.get(key))
+        .map(|o| {
+            o.as_array()
+                .with_context(|| format!("Expected `template.{}` to be an array", key))?
+                .iter()
+                .map(|o| {
+                    fnmatch_to_regex(o.as_str().with_context(|| {
+                        format!("Expected `template.{}` to contain strings", key)
+                    })?)
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+fn placeholder_default(name: &str, value: &Value) -> Result<Option<String>> {
+    let default = match value.get("default") {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(v @ Value::Boolean(_)) | Some(v @ Value::Integer(_)) => Some(v.to_string()),
+        Some(v) => bail!("Unsupported default {} for placeholder {}", v, name),
+        None => None,
+    };
+    Ok(default.or_else(|| {
+        value
+            .get("choices")
+            .and_then(Value::as_array)
+            .and_then(|o| o.first())
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+    }))
+}
+
+pub(crate) fn parse_definition(template: &Path) -> Result<TemplateDef> {
+    let config = template.join("cargo-generate.toml");
+    let value = if config.exists() {
+        read_to_string(&config)
+            .context("Failed to read cargo-generate.toml")?
+            .parse::<Value>()
+            .context("Invalid TOML in cargo-generate.toml")?
+    } else {
+        Value::Table(Default::default())
+    };
+    let table = value.get("template");
+
+    let mut variables = vec![
+        VariableDef {
+            name: "project_name".to_owned(),
+            default: Some("{{ basename }}".to_owned()),
+            implicit: true,
+            ..VariableDef::default()
+        },
+        VariableDef {
+            name: "crate_name".to_owned(),
+            default: Some("{{ project_name | lower | replace(from='-', to='_') }}".to_owned()),
+            implicit: true,
+            ..VariableDef::default()
+        },
+    ];
+    let mut aliases = vec![("project-name".to_owned(), "project_name".to_owned())];
+    if let Some(placeholders) = value.get("placeholders") {
+        for (name, value) in placeholders
+            .as_table()
+            .context("Expected `placeholders` to be a table")?
+        {
+            let safe = name.replace('-', "_");
+            if safe != *name {
+                aliases.push((name.clone(), safe.clone()));
+            }
+            variables.push(VariableDef {
+                default: placeholder_default(name, value)?,
+                description: value
+                    .get("prompt")
+                    .and_then(Value::as_str)
+                    .map(str::to_owned),
+                choices: value
+                    .get("choices")
+                    .and_then(Value::as_array)
+                    .map(|o| {
+                        o.iter()
+                            .filter_map(Value::as_str)
+                            .map(str::to_owned)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                name: safe,
+                ..VariableDef::default()
+            });
+        }
+    }
+    // Longer names first, so `foo-bar-baz` isn't partially rewritten by `foo-bar`
+    aliases.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+
+    let mut files = vec![FileDef {
+        sources: vec![Regex::new("^cargo-generate\\.toml$").unwrap()],
+        template: false,
+        include: false,
+        rename: None,
+        eol: None,
+        skip_if: None,
+        delimiters: None,
+        permissions: Permissions::default(),
+        origin: RuleOrigin::Template,
+    }];
+    if let Some(sources) = patterns(table, "ignore")? {
+        files.push(FileDef {
+            sources,
+            template: false,
+            include: false,
+            rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Template,
+        });
+    }
+    if let Some(sources) = patterns(table, "exclude")? {
+        files.push(FileDef {
+            sources,
+            template: false,
+            include: true,
+            rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Template,
+        });
+    }
+    // Only the suffix goes, the directories above are named as any others are
+    files.push(FileDef {
+        sources: vec![Regex::new("^(.*)\\.liquid$").unwrap()],
+        template: true,
+        include: true,
+        rename: Some("$1".to_owned()),
+        eol: None,
+        skip_if: None,
+        delimiters: None,
+        permissions: Permissions::default(),
+        origin: RuleOrigin::Template,
+    });
+    if let Some(sources) = patterns(table, "include")? {
+        files.push(FileDef {
+            sources,
+            template: true,
+            include: true,
+            rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Template,
+        });
+        // The built in exclusions, like `.git`, still come first
+        files.extend(default_files().into_iter().filter(|o| !o.include));
+        files.push(FileDef {
+            sources: vec![Regex::new(".*").unwrap()],
+            template: false,
+            include: true,
+            rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Template,
+        });
+    }
+    files.append(&mut default_files());
+    Ok(TemplateDef {
+        files,
+        variables,
+        template_paths: true,
+        allow: vec![WarningKind::DefaultRule],
+        aliases,
+        ..TemplateDef::default()
+    })
+}
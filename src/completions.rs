@@ -0,0 +1,149 @@
+use std::str::FromStr;
+
+use anyhow::*;
+
+use crate::{cached_templates, Registry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => bail!("Unknown shell {}, expected `bash`, `zsh` or `fish`", s),
+        }
+    }
+}
+
+const SUBCOMMANDS: &[&str] = &[
+    "adopt",
+    "alias",
+    "apply",
+    "batch",
+    "cache",
+    "completions",
+    "config",
+    "digest",
+    "graph",
+    "info",
+    "init",
+    "publish",
+    "render",
+    "resume",
+    "search",
+    "smoke",
+    "test",
+    "tui",
+    "undo",
+    "update-lock",
+    "validate",
+];
+
+const FLAGS: &[&str] = &[
+    "--allow",
+    "--answers",
+    "--cache-dir",
+    "--config",
+    "--conflict",
+    "--git-config",
+    "--index",
+    "--keep-partial",
+    "--keep-vcs",
+    "--locked",
+    "--name",
+    "--no-cache",
+    "--no-color",
+    "--profile",
+    "--prompt-timeout",
+    "--push",
+    "--quiet",
+    "--strict",
+    "--vcs",
+    "--yes",
+];
+
+// The scripts ask `generator completions --templates` for template names when completing,
+// so aliases added later are offered without regenerating them
+pub fn completions(shell: Shell) -> String {
+    let subcommands = SUBCOMMANDS.join(" ");
+    let flags = FLAGS.join(" ");
+    match shell {
+        Shell::Bash => format!(
+            r#"_generator() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{subcommands} $(generator completions --templates 2>/dev/null)" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "$(generator completions --templates 2>/dev/null)" -- "$cur") $(compgen -f -- "$cur"))
+    fi
+}}
+complete -o filenames -F _generator generator
+"#,
+            flags = flags,
+            subcommands = subcommands
+        ),
+        Shell::Zsh => format!(
+            r#"#compdef generator
+
+_generator() {{
+    local -a templates
+    templates=(${{(f)"$(generator completions --templates 2>/dev/null)"}})
+    if [[ $words[CURRENT] == -* ]]; then
+        compadd -- {flags}
+    elif (( CURRENT == 2 )); then
+        compadd -- {subcommands} $templates
+    else
+        compadd -- $templates
+        _files
+    fi
+}}
+
+compdef _generator generator
+"#,
+            flags = flags,
+            subcommands = subcommands
+        ),
+        Shell::Fish => {
+            let mut script = format!(
+                "complete -c generator -n __fish_use_subcommand -a '{}'\n",
+                subcommands
+            );
+            script +=
+                "complete -c generator -a '(generator completions --templates 2>/dev/null)'\n";
+            for flag in FLAGS {
+                script += &format!(
+                    "complete -c generator -l {}\n",
+                    flag.trim_start_matches('-')
+                );
+            }
+            script += "complete -c generator -s y\n";
+            script
+        }
+    }
+}
+
+// Alias names followed by cached templates without an alias
+pub fn template_names() -> Result<Vec<String>> {
+    let registry = Registry::load()?;
+    let mut names = registry
+        .aliases()
+        .map(|(name, _)| name.to_owned())
+        .collect::<Vec<_>>();
+    for cached in cached_templates()? {
+        if !registry.aliases().any(|(_, url)| url == cached) {
+            names.push(cached);
+        }
+    }
+    Ok(names)
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::BTreeMap,
+    fs::{read_dir, read_to_string, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_yaml::{from_str, to_writer, Value};
+
+use crate::{config_dir, Profile, DIRECTORY_OVERRIDE};
+
+// Variable names, dotted for nested variables, as defaults are keyed by
+static NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[^.\s]+(\.[^.\s]+)*$").unwrap());
+
+// Default values by variable name
+pub type Values = BTreeMap<String, Value>;
+
+// Default variable values, from the system wide config overridden by the user's own and then by
+// a profile if one is chosen. Only the last of those is changed and saved
+#[derive(Debug, Clone)]
+pub struct Config {
+    path: PathBuf,
+    below: Values,
+    values: Values,
+}
+
+fn check_name(name: &str) -> Result<()> {
+    ensure!(
+        NAME.is_match(name),
+        "Expected a variable name, like `name` or `author.email`, not `{}`",
+        name
+    );
+    Ok(())
+}
+
+// Keys that aren't variable names fail here, rather than being ignored when generating
+fn load_values(path: &Path) -> Result<Values> {
+    if !path.exists() {
+        return Ok(Values::new());
+    }
+    let text =
+        read_to_string(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    if text.trim().is_empty() {
+        return Ok(Values::new());
+    }
+    let values = match from_str(&text)
+        .with_context(|| format!("While parsing default variables in {}", path.display()))?
+    {
+        Value::Null => Values::new(),
+        value @ Value::Mapping(_) => {
+            serde_yaml::from_value::<Values>(value).with_context(|| {
+                format!(
+                    "Expected default variables in {} to be keyed by name",
+                    path.display()
+                )
+            })?
+        }
+        _ => bail!(
+            "Expected default variables in {} to be a mapping at top level",
+            path.display()
+        ),
+    };
+    for name in values.keys() {
+        check_name(name).with_context(|| format!("Invalid default in {}", path.display()))?;
+    }
+    Ok(values)
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        config_dir().join("defaults.yml")
+    }
+
+    // Shared by every user, and left out when the directories are overridden for a sandbox
+    pub fn system_path() -> Option<PathBuf> {
+        if cfg!(unix) && DIRECTORY_OVERRIDE.with(|o| o.borrow().is_none()) {
+            Some(PathBuf::from("/etc/generator/defaults.yml"))
+        } else {
+            None
+        }
+    }
+
+    // A named set of defaults, like `work` or `oss`, kept beside and layered over the usual ones
+    pub fn profile_path(profile: &str) -> PathBuf {
+        config_dir().join(format!("defaults.{}.yml", profile))
+    }
+
+    // Names of the defaults profiles in the config directory
+    pub fn profiles() -> Result<Vec<String>> {
+        let dir = config_dir();
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut profiles = read_dir(&dir)
+            .with_context(|| format!("Failed to read {}", dir.display()))?
+            .filter_map(|o| o.ok()?.file_name().into_string().ok())
+            .filter_map(|o| {
+                let name = o.strip_prefix("defaults.")?.strip_suffix(".yml")?;
+                Some(name.to_owned()).filter(|o| !o.is_empty())
+            })
+            .collect::<Vec<_>>();
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    // The values a profile sets itself, without those it's layered over
+    pub fn profile_values(profile: &str) -> Result<Values> {
+        load_values(&Self::profile_path(profile))
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_profile(None)
+    }
+
+    pub fn load_profile(profile: Option<&str>) -> Result<Self> {
+        let mut below = match Self::system_path() {
+            Some(system) => load_values(&system)?,
+            None => Values::new(),
+        };
+        let path = match profile {
+            Some(profile) => {
+                // `--profile` takes these for how generation runs
+                ensure!(
+                    profile.parse::<Profile>().is_err(),
+                    "Defaults profiles can't be named `{}`, which is a generation profile",
+                    profile
+                );
+                below.extend(load_values(&Self::path())?);
+                Self::profile_path(profile)
+            }
+            None => Self::path(),
+        };
+        let values = load_values(&path)?;
+        Ok(Self {
+            path,
+            below,
+            values,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        to_writer(
+            File::create(&self.path).context("Failed to write defaults")?,
+            &self.values,
+        )
+        .context("Failed to write defaults")
+    }
+
+    pub fn values(&self) -> Values {
+        let mut values = self.below.clone();
+        values.extend(self.values.clone());
+        values
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name).or_else(|| self.below.get(name))
+    }
+
+    // Values are parsed as yaml so that `true` or `[a, b]` keep their type
+    pub fn set(&mut self, name: impl Into<String>, value: &str) -> Result<()> {
+        let name = name.into();
+        check_name(&name)?;
+        let value = from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+        self.values.insert(name, value);
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> bool {
+        self.values.remove(name).is_some()
+    }
+}
@@ -0,0 +1,118 @@
+use std::{io::Read, path::Path};
+
+use anyhow::*;
+use regex::Regex;
+use serde_json::{from_reader, Value};
+
+use crate::{
+    default_files, FileDef, Permissions, RuleOrigin, TemplateDef, VariableDef, WarningKind,
+};
+
+pub(crate) fn fnmatch_to_regex(pattern: &str) -> Result<Regex> {
+    let mut re = String::from("^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            '[' => {
+                re.push('[');
+                for c in chars.by_ref() {
+                    match c {
+                        '!' if re.ends_with('[') => re.push('^'),
+                        ']' => break,
+                        '\\' => re.push_str("\\\\"),
+                        c => re.push(c),
+                    }
+                }
+                re.push(']');
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).with_context(|| format!("Invalid pattern {}", pattern))
+}
+
+fn find_root(template: &Path) -> Result<String> {
+    std::fs::read_dir(template)
+        .with_context(|| format!("Failed to read template {}", template.display()))?
+        .filter_map(|o| o.ok())
+        .filter(|o| o.path().is_dir())
+        .filter_map(|o| o.file_name().into_string().ok())
+        .find(|o| o.contains("{{") && o.contains("cookiecutter"))
+        .context("Expected a `{{cookiecutter.*}}` directory next to cookiecutter.json")
+}
+
+pub(crate) fn parse_definition(def: impl Read, template: &Path) -> Result<TemplateDef> {
+    let value: Value = from_reader(def).context("Invalid JSON in cookiecutter.json")?;
+    let map = value
+        .as_object()
+        .context("Expected cookiecutter.json to be an object at top level")?;
+    let variables = map
+        .iter()
+        .filter(|(k, _)| !k.starts_with('_'))
+        .map(|(k, v)| {
+            Ok(VariableDef {
+                name: k.clone(),
+                default: Some(match v {
+                    Value::String(s) => s.clone(),
+                    Value::Array(a) => match a.first() {
+                        Some(Value::String(s)) => s.clone(),
+                        Some(v) => v.to_string(),
+                        None => bail!("Expected choices for variable {}", k),
+                    },
+                    Value::Bool(_) | Value::Number(_) => v.to_string(),
+                    v => bail!(
+                        "Unsupported value {} for variable {}, expected string or list",
+                        v,
+                        k
+                    ),
+                }),
+                // A list is the choices, the first being the default
+                choices: match v {
+                    Value::Array(a) => a
+                        .iter()
+                        .map(|o| o.as_str().map_or_else(|| o.to_string(), str::to_owned))
+                        .collect(),
+                    _ => vec![],
+                },
+                ..VariableDef::default()
+            })
+        })
+        .collect::<Result<_>>()?;
+    let mut files = vec![];
+    if let Some(patterns) = map.get("_copy_without_render") {
+        files.push(FileDef {
+            sources: patterns
+                .as_array()
+                .context("Expected `_copy_without_render` to be a list")?
+                .iter()
+                .map(|o| {
+                    fnmatch_to_regex(
+                        o.as_str()
+                            .context("Expected `_copy_without_render` to contain strings")?,
+                    )
+                })
+                .collect::<Result<_>>()?,
+            template: false,
+            include: true,
+            rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Template,
+        });
+    }
+    files.append(&mut default_files());
+    Ok(TemplateDef {
+        files,
+        variables,
+        template_paths: true,
+        root: Some(find_root(template)?.into()),
+        namespace: Some("cookiecutter".to_owned()),
+        allow: vec![WarningKind::DefaultRule],
+        ..TemplateDef::default()
+    })
+}
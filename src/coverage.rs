@@ -0,0 +1,209 @@
+use crate::{Profile, TemplateDef};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleCoverage {
+    pub rule: String,
+    pub hits: usize,
+    pub builtin: bool,
+    // Whether the rule has a `skip_if`, and how often it was true and false for matched paths
+    pub conditional: bool,
+    pub skipped: usize,
+    pub kept: usize,
+}
+
+impl RuleCoverage {
+    // Branches are only counted where there's a condition to take them
+    fn branches(&self) -> usize {
+        if self.conditional {
+            2
+        } else {
+            0
+        }
+    }
+
+    fn branches_covered(&self) -> usize {
+        if self.conditional {
+            usize::from(self.skipped > 0) + usize::from(self.kept > 0)
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableCoverage {
+    pub name: String,
+    pub has_default: bool,
+    pub defaulted: bool,
+    pub provided: bool,
+}
+
+impl VariableCoverage {
+    pub fn covered(&self) -> bool {
+        self.provided && (self.defaulted || !self.has_default)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileCoverage {
+    pub profile: Profile,
+    // Whether the template does anything differently under the profile, so that a test case
+    // should use it. Only `safe` does, for templates with `verify` commands to run
+    pub expected: bool,
+    pub cases: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub rules: Vec<RuleCoverage>,
+    pub variables: Vec<VariableCoverage>,
+    pub profiles: Vec<ProfileCoverage>,
+}
+
+impl Coverage {
+    pub(crate) fn new(def: &TemplateDef) -> Self {
+        Self {
+            rules: def
+                .files
+                .iter()
+                .map(|o| RuleCoverage {
+                    rule: o.describe(),
+                    hits: 0,
+                    builtin: !o.explicit(),
+                    conditional: o.skip_if.is_some(),
+                    skipped: 0,
+                    kept: 0,
+                })
+                .collect(),
+            variables: def
+                .variables
+                .iter()
+                .map(|o| VariableCoverage {
+                    name: o.name.clone(),
+                    has_default: o.default.is_some(),
+                    defaulted: false,
+                    provided: false,
+                })
+                .collect(),
+            profiles: [Profile::Default, Profile::Fast, Profile::Safe]
+                .iter()
+                .map(|&o| ProfileCoverage {
+                    profile: o,
+                    expected: o == Profile::Default
+                        || (o == Profile::Safe && !def.verify.is_empty()),
+                    cases: 0,
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn hit_rule(&mut self, index: usize) {
+        self.rules[index].hits += 1;
+    }
+
+    pub(crate) fn branch(&mut self, index: usize, skipped: bool) {
+        let rule = &mut self.rules[index];
+        if skipped {
+            rule.skipped += 1;
+        } else {
+            rule.kept += 1;
+        }
+    }
+
+    pub(crate) fn variable(&mut self, name: &str) -> Option<&mut VariableCoverage> {
+        self.variables.iter_mut().find(|o| o.name == name)
+    }
+
+    pub(crate) fn profile(&mut self, profile: Profile) {
+        if let Some(o) = self.profiles.iter_mut().find(|o| o.profile == profile) {
+            o.cases += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for rule in &other.rules {
+            match self.rules.iter_mut().find(|o| o.rule == rule.rule) {
+                Some(o) => {
+                    o.hits += rule.hits;
+                    o.skipped += rule.skipped;
+                    o.kept += rule.kept;
+                }
+                None => self.rules.push(rule.clone()),
+            }
+        }
+        for var in &other.variables {
+            match self.variable(&var.name) {
+                Some(o) => {
+                    o.defaulted |= var.defaulted;
+                    o.provided |= var.provided;
+                }
+                None => self.variables.push(var.clone()),
+            }
+        }
+        for profile in &other.profiles {
+            match self
+                .profiles
+                .iter_mut()
+                .find(|o| o.profile == profile.profile)
+            {
+                Some(o) => {
+                    o.expected |= profile.expected;
+                    o.cases += profile.cases;
+                }
+                None => self.profiles.push(profile.clone()),
+            }
+        }
+    }
+
+    pub fn uncovered(&self) -> Vec<String> {
+        let explicit = self.rules.iter().filter(|o| !o.builtin);
+        explicit
+            .clone()
+            .filter(|o| o.hits == 0)
+            .map(|o| format!("file rule `{}` never matched a path", o.rule))
+            // A rule that never matched has already been reported, whatever its condition
+            .chain(
+                explicit
+                    .filter(|o| o.conditional && o.hits > 0)
+                    .flat_map(|o| {
+                        let never =
+                            |taken| format!("`skip_if` of file rule `{}` never {}", o.rule, taken);
+                        (o.skipped == 0)
+                            .then(|| never("true"))
+                            .into_iter()
+                            .chain((o.kept == 0).then(|| never("false")))
+                    }),
+            )
+            .chain(self.variables.iter().filter(|o| !o.covered()).map(|o| {
+                if !o.provided {
+                    format!("variable `{}` never given an explicit value", o.name)
+                } else {
+                    format!("variable `{}` never left at its default", o.name)
+                }
+            }))
+            .chain(
+                self.profiles
+                    .iter()
+                    .filter(|o| o.expected && o.cases == 0)
+                    .map(|o| format!("profile `{}` never used by a test case", o.profile.name())),
+            )
+            .collect()
+    }
+
+    pub fn percent(&self) -> f64 {
+        let rules = self.rules.iter().filter(|o| !o.builtin);
+        let profiles = self.profiles.iter().filter(|o| o.expected);
+        let total = rules.clone().map(|o| 1 + o.branches()).sum::<usize>()
+            + self.variables.len()
+            + profiles.clone().count();
+        if total == 0 {
+            return 100.0;
+        }
+        let covered = rules
+            .map(|o| usize::from(o.hits > 0) + o.branches_covered())
+            .sum::<usize>()
+            + self.variables.iter().filter(|o| o.covered()).count()
+            + profiles.filter(|o| o.cases > 0).count();
+        covered as f64 * 100.0 / total as f64
+    }
+}
@@ -0,0 +1,503 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use anyhow::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_yaml::Value;
+use tera::{
+    ast::{Expr, ExprVal, Node},
+    Context, Template, Tera,
+};
+
+use crate::{builtins, integrity, license, plugin::Plugin, random, slash_path};
+
+static LOCATION: Lazy<Regex> = Lazy::new(|| Regex::new(r"--> (\d+):(\d+)").unwrap());
+// Sources other than files are registered under their hash, which means nothing to the user
+static TEMPLATE_NAME: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r" while rendering '([0-9a-f]{64}|__tera_one_off)'").unwrap());
+static UNDEFINED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"Variable `([A-Za-z_][A-Za-z0-9_]*)[^`]*` not found").unwrap());
+
+pub trait RenderEngine {
+    fn render(&self, source: &str, context: &Context) -> Result<String>;
+}
+
+// The line and column, both from 1, of the tag an error from rendering `source` is about. Tera
+// only gives a location for syntax errors, so others are found by the variable they name
+pub(crate) fn locate(source: &str, message: &str) -> Option<(usize, Option<usize>)> {
+    if let Some(c) = LOCATION.captures(message) {
+        return Some((c[1].parse().ok()?, c[2].parse().ok()));
+    }
+    let variable = &UNDEFINED.captures(message)?[1];
+    source.lines().enumerate().find_map(|(i, o)| {
+        if o.contains("{{") || o.contains("{%") {
+            Some((i + 1, Some(o.find(variable)? + 1)))
+        } else {
+            None
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct RenderError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub snippet: Option<String>,
+    pub message: String,
+}
+
+impl RenderError {
+    pub(crate) fn new(path: &Path, source: &str, error: &Error) -> Self {
+        // The innermost error is the specific one, without the template's name, unless it's
+        // another file's that this one includes
+        let message = TEMPLATE_NAME
+            .replace_all(&error.root_cause().to_string(), "")
+            .replace(
+                &format!(
+                    " while rendering '{}'",
+                    slash_path(path).unwrap_or_default()
+                ),
+                "",
+            );
+        let location = locate(source, &message);
+        match LOCATION.find(&message) {
+            // Syntax errors come with their own snippet, after the location
+            Some(m) => Self {
+                path: path.to_owned(),
+                line: location.map(|o| o.0),
+                column: location.and_then(|o| o.1),
+                snippet: None,
+                message: format!("syntax error{}", &message[m.end()..]),
+            },
+            None => Self {
+                path: path.to_owned(),
+                line: location.map(|o| o.0),
+                column: location.and_then(|o| o.1),
+                snippet: location.and_then(|o| source.lines().nth(o.0 - 1).map(str::to_owned)),
+                message,
+            },
+        }
+    }
+}
+
+impl RenderError {
+    // The message with the snippet below it, without the location
+    pub(crate) fn detail(&self) -> String {
+        let mut detail = self.message.clone();
+        if let (Some(line), Some(snippet)) = (self.line, &self.snippet) {
+            let gutter = " ".repeat(line.to_string().len());
+            detail.push_str(&format!("\n{} |\n{} | {}", gutter, line, snippet));
+            if let Some(column) = self.column {
+                detail.push_str(&format!("\n{} | {}^", gutter, " ".repeat(column - 1)));
+            }
+        }
+        detail
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": {}", self.detail())
+    }
+}
+
+// Variables, as written like `a.b[0]`, that a Tera template reads without setting them itself
+#[derive(Default)]
+struct References {
+    read: BTreeSet<String>,
+    set: BTreeSet<String>,
+}
+
+impl References {
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::VariableBlock(_, expr) => self.expr(expr),
+                Node::MacroDefinition(_, definition, _) => {
+                    self.set.extend(definition.args.keys().cloned());
+                    self.nodes(&definition.body);
+                }
+                Node::Set(_, set) => {
+                    self.set.insert(set.key.clone());
+                    self.expr(&set.value);
+                }
+                Node::FilterSection(_, section, _) => {
+                    section.filter.args.values().for_each(|o| self.expr(o));
+                    self.nodes(&section.body);
+                }
+                Node::Block(_, block, _) => self.nodes(&block.body),
+                Node::Forloop(_, forloop, _) => {
+                    self.set.extend(forloop.key.clone());
+                    self.set.insert(forloop.value.clone());
+                    self.expr(&forloop.container);
+                    self.nodes(&forloop.body);
+                    if let Some(empty) = &forloop.empty_body {
+                        self.nodes(empty);
+                    }
+                }
+                Node::If(conditions, _) => {
+                    for (_, condition, body) in &conditions.conditions {
+                        self.expr(condition);
+                        self.nodes(body);
+                    }
+                    if let Some((_, body)) = &conditions.otherwise {
+                        self.nodes(body);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        self.value(&expr.val);
+        for filter in &expr.filters {
+            filter.args.values().for_each(|o| self.expr(o));
+        }
+    }
+
+    fn value(&mut self, value: &ExprVal) {
+        match value {
+            ExprVal::Ident(ident) => {
+                self.read.insert(ident.clone());
+            }
+            ExprVal::Math(math) => {
+                self.expr(&math.lhs);
+                self.expr(&math.rhs);
+            }
+            ExprVal::Logic(logic) => {
+                self.expr(&logic.lhs);
+                self.expr(&logic.rhs);
+            }
+            ExprVal::Test(test) => {
+                self.read.insert(test.ident.clone());
+                test.args.iter().for_each(|o| self.expr(o));
+            }
+            ExprVal::MacroCall(call) => call.args.values().for_each(|o| self.expr(o)),
+            ExprVal::FunctionCall(call) => call.args.values().for_each(|o| self.expr(o)),
+            ExprVal::Array(values) => values.iter().for_each(|o| self.expr(o)),
+            ExprVal::StringConcat(concat) => concat.values.iter().for_each(|o| self.value(o)),
+            ExprVal::In(contains) => {
+                self.expr(&contains.lhs);
+                self.expr(&contains.rhs);
+            }
+            ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+        }
+    }
+}
+
+// The variables a Tera template refers to, or nothing if it doesn't parse
+pub(crate) fn references(source: &str) -> Option<BTreeSet<String>> {
+    let template = tera::Template::new("__references", None, source).ok()?;
+    let mut references = References::default();
+    references.nodes(&template.ast);
+    for definition in template.macros.values() {
+        references.set.extend(definition.args.keys().cloned());
+        references.nodes(&definition.body);
+    }
+    let References { read, set } = references;
+    Some(
+        read.into_iter()
+            .filter(|o| {
+                let root = o.split(['.', '[']).next().unwrap_or_default();
+                !set.contains(root) && root != "loop" && root != "__tera_context"
+            })
+            .collect(),
+    )
+}
+
+// Tera with generator's functions, and no templates. Generated files are never HTML escaped,
+// whatever their extension
+fn tera() -> Tera {
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec![]);
+    tera.register_function("license", license::function);
+    tera.register_function("now", builtins::now);
+    tera.register_function("date", builtins::date);
+    tera.register_function("uuid", random::uuid);
+    tera.register_function("random_hex", random::random_hex);
+    tera.register_function("random_password", random::random_password);
+    tera
+}
+
+thread_local! {
+    // For `TeraEngine`, which renders each source on its own. Built once per thread rather than
+    // per render, as setting up Tera's builtin filters and functions costs more than rendering
+    // most sources
+    static TERA: RefCell<Tera> = RefCell::new(tera());
+}
+
+// The Tera a generation renders with, which has the template's plugins' filters and functions.
+// The template's files are registered by their paths, so that they can include and extend each
+// other, and anything else rendered, like names and defaults, is parsed the first time and kept
+// by its hash. Everything goes with the generation
+pub(crate) struct Templates(RwLock<Registered>);
+
+struct Registered {
+    tera: Tera,
+    // The name each source is registered under, by its hash, so none is parsed twice
+    names: HashMap<String, String>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Templates(RwLock::new(Registered {
+            tera: tera(),
+            names: HashMap::new(),
+        }))
+    }
+}
+
+impl fmt::Debug for Templates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Templates").finish_non_exhaustive()
+    }
+}
+
+impl Templates {
+    // `files` by their paths. Those that don't parse, or extend or import what isn't there, are
+    // left out, to fail when they're rendered as they would on their own rather than failing the
+    // generation even if they're skipped
+    pub(crate) fn new(files: BTreeMap<String, String>, plugins: &[Arc<Plugin>]) -> Result<Self> {
+        let mut tera = tera();
+        for plugin in plugins {
+            plugin.register(&mut tera);
+        }
+        let mut names = HashMap::new();
+        for (name, source) in files {
+            if let std::result::Result::Ok(template) = Template::new(&name, None, &source) {
+                names.insert(integrity::sha256_hex(source.as_bytes()), name.clone());
+                tera.templates.insert(name, template);
+            }
+        }
+        // Leaving one out can leave others extending or importing it broken in turn
+        loop {
+            let broken = broken(&tera.templates);
+            if broken.is_empty() {
+                break;
+            }
+            for name in broken {
+                tera.templates.remove(&name);
+            }
+        }
+        tera.build_inheritance_chains()?;
+        tera.check_macro_files()?;
+        Ok(Templates(RwLock::new(Registered { tera, names })))
+    }
+
+    pub(crate) fn render(&self, source: &str, context: &Context) -> Result<String> {
+        let hash = integrity::sha256_hex(source.as_bytes());
+        {
+            let registered = self.0.read().unwrap_or_else(PoisonError::into_inner);
+            if let Some(name) = registered.names.get(&hash) {
+                if registered.tera.templates.contains_key(name) {
+                    return Ok(registered.tera.render(name, context)?);
+                }
+            }
+        }
+        let mut registered = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        // Files left out for what they extend or import are added again under their paths, to
+        // fail with those. Another thread may have added it while this one waited
+        let name = registered.names.get(&hash).cloned().unwrap_or(hash.clone());
+        if !registered.tera.templates.contains_key(&name) {
+            if let Err(e) = registered.tera.add_raw_template(&name, source) {
+                registered.tera.templates.remove(&name);
+                return Err(e.into());
+            }
+            registered.names.insert(hash, name.clone());
+        }
+        Ok(registered.tera.render(&name, context)?)
+    }
+}
+
+// Templates whose parents, or files they import macros from, are missing, or whose parents
+// extend them in turn
+fn broken(templates: &HashMap<String, Template>) -> Vec<String> {
+    templates
+        .values()
+        .filter(|template| {
+            let mut seen = HashSet::new();
+            let mut current = *template;
+            while let Some(parent) = &current.parent {
+                match templates.get(parent) {
+                    Some(parent) if seen.insert(parent.name.as_str()) => current = parent,
+                    _ => return true,
+                }
+            }
+            template
+                .imported_macro_files
+                .iter()
+                .any(|(name, _)| !templates.contains_key(name))
+        })
+        .map(|o| o.name.clone())
+        .collect()
+}
+
+static HANDLEBARS: Lazy<handlebars::Handlebars<'static>> = Lazy::new(|| {
+    let mut handlebars = handlebars::Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    handlebars
+});
+
+pub struct TeraEngine;
+
+impl RenderEngine for TeraEngine {
+    fn render(&self, source: &str, context: &Context) -> Result<String> {
+        Ok(TERA.with(|tera| tera.borrow_mut().render_str(source, context))?)
+    }
+}
+
+pub struct HandlebarsEngine;
+
+impl RenderEngine for HandlebarsEngine {
+    fn render(&self, source: &str, context: &Context) -> Result<String> {
+        Ok(HANDLEBARS.render_template(source, &context.clone().into_json())?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    #[default]
+    Tera,
+    Handlebars,
+}
+
+impl Engine {
+    pub fn get(self) -> &'static dyn RenderEngine {
+        match self {
+            Engine::Tera => &TeraEngine,
+            Engine::Handlebars => &HandlebarsEngine,
+        }
+    }
+}
+
+impl FromStr for Engine {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tera" => Ok(Engine::Tera),
+            "handlebars" => Ok(Engine::Handlebars),
+            _ => bail!("Unknown engine {}, expected `tera` or `handlebars`", s),
+        }
+    }
+}
+
+// Tags marked by something other than Tera's `{{ }}`, `{% %}` and `{# #}`, for templates of
+// files that use those themselves
+#[derive(Debug, Clone)]
+pub struct Delimiters {
+    pub variable: (String, String),
+    pub block: (String, String),
+    pub comment: (String, String),
+    tags: Regex,
+}
+
+impl PartialEq for Delimiters {
+    fn eq(&self, other: &Self) -> bool {
+        self.variable == other.variable
+            && self.block == other.block
+            && self.comment == other.comment
+    }
+}
+
+impl Eq for Delimiters {}
+
+impl Delimiters {
+    pub fn new(
+        variable: (String, String),
+        block: (String, String),
+        comment: (String, String),
+    ) -> Self {
+        let tags = Regex::new(&format!(
+            "(?s){}(.*?){}|{}(.*?){}|{}.*?{}",
+            regex::escape(&variable.0),
+            regex::escape(&variable.1),
+            regex::escape(&block.0),
+            regex::escape(&block.1),
+            regex::escape(&comment.0),
+            regex::escape(&comment.1),
+        ))
+        .expect("Escaped delimiters make a valid regex");
+        Self {
+            variable,
+            block,
+            comment,
+            tags,
+        }
+    }
+
+    // A mapping of `variable`, `block` and `comment` to pairs of opening and closing delimiters,
+    // each defaulting to Tera's
+    pub(crate) fn parse(value: &Value) -> Result<Self> {
+        let pair = |key: &str, default: (&str, &str)| -> Result<(String, String)> {
+            match value.get(key) {
+                None => Ok((default.0.to_owned(), default.1.to_owned())),
+                Some(Value::Sequence(s)) => match s.as_slice() {
+                    [Value::String(open), Value::String(close)]
+                        if !open.is_empty() && !close.is_empty() =>
+                    {
+                        Ok((open.clone(), close.clone()))
+                    }
+                    _ => bail!(
+                        "Expected `{}` delimiters to be an opening and closing string",
+                        key
+                    ),
+                },
+                Some(_) => bail!("Expected `{}` delimiters to be a sequence", key),
+            }
+        };
+        ensure!(value.is_mapping(), "Expected `delimiters` to be a mapping");
+        Ok(Self::new(
+            pair("variable", ("{{", "}}"))?,
+            pair("block", ("{%", "%}"))?,
+            pair("comment", ("{#", "#}"))?,
+        ))
+    }
+
+    // The template in Tera's own delimiters, with text that looks like Tera tags kept as it is
+    pub(crate) fn translate(&self, source: &str) -> String {
+        let mut out = String::with_capacity(source.len());
+        let text = |out: &mut String, text: &str| {
+            if text.contains("{{") || text.contains("{%") || text.contains("{#") {
+                out.push_str("{% raw %}");
+                out.push_str(text);
+                out.push_str("{% endraw %}");
+            } else {
+                out.push_str(text);
+            }
+        };
+        let mut last = 0;
+        for tag in self.tags.captures_iter(source) {
+            let whole = tag.get(0).expect("Captures always have the whole match");
+            text(&mut out, &source[last..whole.start()]);
+            last = whole.end();
+            if let Some(expression) = tag.get(1) {
+                out.push_str("{{");
+                out.push_str(expression.as_str());
+                out.push_str("}}");
+            } else if let Some(statement) = tag.get(2) {
+                out.push_str("{%");
+                out.push_str(statement.as_str());
+                out.push_str("%}");
+            }
+        }
+        text(&mut out, &source[last..]);
+        out
+    }
+}
@@ -0,0 +1,752 @@
+use std::{
+    ffi::OsStr,
+    fs::{
+        create_dir_all, read_dir, read_to_string, remove_dir, remove_dir_all, remove_file, rename,
+        write, File, OpenOptions,
+    },
+    io::Read,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use walkdir::WalkDir;
+
+use crate::{
+    archive, cache_dir, index, integrity::sha256_hex, quiet, Integrity, Lockfile, NetworkError,
+    Registry, Repo, Style,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    Local(PathBuf),
+    // A local or downloaded archive
+    Archive(String),
+    // A single local or downloaded file, generated on its own
+    File(String),
+    Git(String),
+}
+
+// A git URL without the branch, tag or commit given after it as `url#ref`, and that ref. Without
+// one, the remote's HEAD is used
+fn split_ref(url: &str) -> (&str, Option<&str>) {
+    match url.split_once('#') {
+        Some((url, reference)) if !reference.is_empty() => (url, Some(reference)),
+        Some((url, _)) => (url, None),
+        None => (url, None),
+    }
+}
+
+// The directory of a fetched template. A git template is checked out into a worktree of its
+// own, which is removed when this is dropped
+#[derive(Debug)]
+pub struct Fetched {
+    path: PathBuf,
+    // Held locked while the worktree is in use, so that other runs don't prune it
+    worktree_lock: Option<tempfile::NamedTempFile>,
+}
+
+impl Fetched {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            worktree_lock: None,
+        }
+    }
+}
+
+impl Deref for Fetched {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for Fetched {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Fetched {
+    fn drop(&mut self) {
+        // The mirror forgets the worktree the next time one is added
+        if self.worktree_lock.is_some() {
+            let _ = remove_dir_all(&self.path);
+        }
+    }
+}
+
+fn is_http(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+// Hosts serving snapshots at URLs without an extension, like
+// https://codeload.github.com/<user>/<repo>/tar.gz/<ref>
+fn is_snapshot(url: &str) -> bool {
+    is_http(url) && (url.contains("/tar.gz/") || url.contains("/zip/"))
+}
+
+// Where a host serves the default branch of the repository at `url` as an archive, for fetching
+// it without git
+fn snapshot_url(url: &str) -> Option<String> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("http://github.com/"))?;
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    match path.split('/').collect::<Vec<_>>().as_slice() {
+        [user, repo] if !user.is_empty() && !repo.is_empty() => Some(format!(
+            "https://github.com/{}/{}/archive/HEAD.tar.gz",
+            user, repo
+        )),
+        _ => None,
+    }
+}
+
+// Without git, a repository can still be downloaded from hosts serving it as an archive
+fn without_git(url: &str) -> Result<TemplateSource> {
+    match split_ref(url) {
+        (url, None) => snapshot_url(url)
+            .map(TemplateSource::Archive)
+            .with_context(|| format!("git is required to fetch {}", url)),
+        _ => bail!("git is required to fetch {}", url),
+    }
+}
+
+// Hosts serving the raw content of files in repositories, rather than a page about them
+const RAW_HOSTS: &[&str] = &["raw.githubusercontent.com", "gist.githubusercontent.com"];
+
+// The URL of a single file, either given explicitly as `file+https://...` or served by one of the
+// raw content hosts. Any other URL is a repository, as only its host knows whether
+// `https://github.com/vercel/next.js` is a file
+fn file_url(url: &str) -> Option<&str> {
+    if let Some(url) = url.strip_prefix("file+") {
+        return Some(url).filter(|o| is_http(o));
+    }
+    let host = url.split_once("://").map_or("", |o| o.1).split('/').next();
+    Some(url).filter(|_| is_http(url) && RAW_HOSTS.iter().any(|o| host == Some(o)))
+}
+
+// Name of the file at `source`, its last path or URL segment
+fn file_name(source: &str) -> Result<&str> {
+    source
+        .split(['?', '#'])
+        .next()
+        .and_then(|o| o.rsplit(['/', '\\']).next())
+        .filter(|o| !o.is_empty())
+        .with_context(|| format!("{} has no file name", source))
+}
+
+// Reads the file at `source` into a template at `destination` of just that file, kept under
+// `file/` so that it can't be mistaken for the definition or anything else generator reads,
+// replacing what was there once it has been read
+fn wrap_file(source: &str, destination: &Path) -> Result<PathBuf> {
+    let parent = destination
+        .parent()
+        .context("File destination has no parent")?;
+    create_dir_all(parent)?;
+    let staging = tempfile::Builder::new()
+        .prefix(".generator-file")
+        .tempdir_in(parent)
+        .context("Failed to create temporary directory")?;
+    let mut contents = vec![];
+    index::open(source)?
+        .read_to_end(&mut contents)
+        .with_context(|| format!("Failed to read file {}", source))?;
+    create_dir_all(staging.path().join("file"))?;
+    write(
+        staging.path().join("file").join(file_name(source)?),
+        contents,
+    )?;
+    write(
+        staging.path().join("template.yml"),
+        "single_file: true\nroot: file\n",
+    )?;
+    if destination.exists() {
+        remove_dir_all(destination)
+            .with_context(|| format!("Failed to remove old {}", destination.display()))?;
+    }
+    rename(staging.path(), destination)?;
+    Ok(destination.to_owned())
+}
+
+// Names a URL's entries in the cache, by a hash so that any URL makes a valid path
+fn url_key(url: &str) -> String {
+    sha256_hex(url.as_bytes())[..32].to_owned()
+}
+
+// Where older versions cloned a git URL to, before clones were mirrors
+fn repo_path(caches: &Path, url: &str) -> PathBuf {
+    caches.join("repos").join(url_key(url))
+}
+
+fn mirror_path(caches: &Path, url: &str) -> PathBuf {
+    caches.join("mirrors").join(format!("{}.git", url_key(url)))
+}
+
+// Written to the cache once it holds bare mirrors in `mirrors/`, rather than clones in `repos/`,
+// and archives named by a hash of their URL
+const LAYOUT_VERSION: &str = "4";
+
+// The URL an older cache cloned to `relative`, which used the URL as a path, with `:` escaped on
+// Windows
+fn legacy_url(relative: &Path) -> Option<String> {
+    let segments = relative
+        .components()
+        .map(|o| o.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    // Joining `https://host/...` into segments drops the double slash
+    Some(
+        segments
+            .join("/")
+            .replace("%3A", ":")
+            .replacen(":/", "://", 1),
+    )
+}
+
+// Holds an exclusive lock on a cache entry until dropped, so that concurrent runs wait for each
+// other rather than cloning, pulling or extracting into the same directory
+fn lock_entry(entry: &Path) -> Result<File> {
+    let mut name = entry
+        .file_name()
+        .expect("Cache entries have a file name")
+        .to_owned();
+    name.push(".lock");
+    let path = entry.with_file_name(name);
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open lock {}", path.display()))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", entry.display()))?;
+    Ok(file)
+}
+
+// Moves clones from older layouts to `mirrors/`
+fn migrate(caches: &Path) -> Result<()> {
+    let marker = caches.join("layout");
+    let version = || read_to_string(&marker).ok();
+    if version().as_deref() == Some(LAYOUT_VERSION) {
+        return Ok(());
+    }
+    let _lock = lock_entry(&marker)?;
+    // Another run may have migrated while this one waited
+    match version().as_deref() {
+        Some(LAYOUT_VERSION) => return Ok(()),
+        Some("3") => {}
+        Some("2") => migrate_clones(caches)?,
+        _ => {
+            migrate_paths(caches)?;
+            migrate_clones(caches)?;
+        }
+    }
+    migrate_archives(caches)?;
+    write(marker, LAYOUT_VERSION).context("Failed to write cache layout")
+}
+
+// Moves clones from the first layout, which used the URL as a path, to `repos/`
+fn migrate_paths(caches: &Path) -> Result<()> {
+    let mut legacy = vec![];
+    let mut walk = WalkDir::new(caches).min_depth(1).into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(caches)
+            .expect("Walked paths are children of the cache");
+        if entry.depth() == 1
+            && [
+                "repos",
+                "locked",
+                "archives",
+                "files",
+                "mirrors",
+                "worktrees",
+                "records",
+            ]
+            .iter()
+            .any(|o| relative == Path::new(o))
+        {
+            walk.skip_current_dir();
+        } else if entry.path().join(".git").exists() {
+            walk.skip_current_dir();
+            // The path loses parts of some URLs, like the empty host of `file:///`
+            let url = Repo::open(entry.path())
+                .and_then(|o| o.remote_url())
+                .ok()
+                .or_else(|| legacy_url(relative));
+            if let Some(url) = url {
+                legacy.push((entry.path().to_owned(), url));
+            }
+        }
+    }
+    for (path, url) in legacy {
+        let new = repo_path(caches, &url);
+        if new.exists() {
+            remove_dir_all(&path)?;
+        } else {
+            create_dir_all(caches.join("repos"))?;
+            rename(&path, &new)
+                .with_context(|| format!("Failed to move cached template {}", path.display()))?;
+        }
+        // Directories for the URL's other segments are left empty
+        let mut parent = path.parent();
+        while let Some(dir) = parent {
+            if dir == caches || remove_dir(dir).is_err() {
+                break;
+            }
+            parent = dir.parent();
+        }
+    }
+    Ok(())
+}
+
+// Turns the clones in `repos/` into mirrors, keeping what they've fetched so they work offline,
+// and drops checkouts of locked commits, which worktrees replace
+fn migrate_clones(caches: &Path) -> Result<()> {
+    let repos = caches.join("repos");
+    if repos.exists() {
+        for entry in read_dir(&repos)? {
+            let path = entry?.path();
+            // A clone interrupted before it finished may not be a repository
+            let url = match Repo::open(&path).and_then(|o| o.remote_url()) {
+                std::result::Result::Ok(url) => url,
+                Err(_) => continue,
+            };
+            let mirror = mirror_path(caches, &url);
+            if !mirror.exists() {
+                create_dir_all(caches.join("mirrors"))?;
+                rename(path.join(".git"), &mirror).with_context(|| {
+                    format!("Failed to move cached template {}", path.display())
+                })?;
+                Repo::convert_to_mirror(&mirror)?;
+            }
+        }
+        remove_dir_all(&repos).context("Failed to remove old cached templates")?;
+    }
+    let locked = caches.join("locked");
+    if locked.exists() {
+        remove_dir_all(&locked).context("Failed to remove old locked templates")?;
+    }
+    Ok(())
+}
+
+// Removes archives named by their URL with unsafe characters replaced, as older versions did. That
+// can't be turned back into the URL, so they're downloaded again when next used
+fn migrate_archives(caches: &Path) -> Result<()> {
+    let archives = caches.join("archives");
+    if !archives.exists() {
+        return Ok(());
+    }
+    for entry in read_dir(&archives)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        let key = name.strip_suffix(".lock").unwrap_or(name);
+        // Hidden entries are extractions in progress
+        if name.starts_with('.') || key.len() == 32 && key.chars().all(|o| o.is_ascii_hexdigit()) {
+            continue;
+        }
+        if path.is_dir() {
+            remove_dir_all(&path)
+        } else {
+            remove_file(&path)
+        }
+        .with_context(|| format!("Failed to remove old cached archive {}", path.display()))?;
+    }
+    Ok(())
+}
+
+// Checks out `commit` of the mirror of `url` into a worktree for this run alone
+fn checkout(caches: &Path, mirror: &mut Repo, url: &str, commit: &str) -> Result<Fetched> {
+    let worktrees = caches.join("worktrees");
+    prune_worktrees(&worktrees)?;
+    let dir = worktrees.join(url_key(url));
+    create_dir_all(&dir)?;
+    // Locked before the worktree exists, so that pruning never finds it unlocked
+    let lock = tempfile::Builder::new()
+        .prefix(&format!("{}-", &commit[..commit.len().min(12)]))
+        .suffix(".lock")
+        .tempfile_in(&dir)
+        .context("Failed to create lock for cached template")?;
+    lock.as_file()
+        .lock()
+        .context("Failed to lock cached template")?;
+    let fetched = Fetched {
+        path: lock.path().with_extension(""),
+        worktree_lock: Some(lock),
+    };
+    // Dropping `fetched` leaves nothing half checked out if this fails
+    mirror
+        .add_worktree(&fetched.path, commit)
+        .and_then(|mut o| checkout_submodules(&mut o))?;
+    Ok(fetched)
+}
+
+// What to fetch `template` as to generate from it again in a later run. Worktrees are removed
+// once the run that checked them out ends, so they're fetched again at the same commit
+pub(crate) fn refetchable(template: &Path) -> Result<String> {
+    let template = std::fs::canonicalize(template)
+        .with_context(|| format!("Failed to find template {}", template.display()))?;
+    let worktrees = std::fs::canonicalize(cache_dir())
+        .map(|o| o.join("worktrees"))
+        .unwrap_or_default();
+    if template.starts_with(&worktrees) && worktrees.exists() {
+        let repo = Repo::open(&template)?;
+        return Ok(format!("{}#{}", repo.remote_url()?, repo.head()?));
+    }
+    template
+        .to_str()
+        .map(str::to_owned)
+        .context("Template path is not a string")
+}
+
+// Removes worktrees left by runs that didn't finish, which no longer hold their locks
+fn prune_worktrees(worktrees: &Path) -> Result<()> {
+    if !worktrees.exists() {
+        return Ok(());
+    }
+    for dir in read_dir(worktrees)? {
+        let dir = dir?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in read_dir(&dir)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let mut name = path.file_name().expect("Entries have a name").to_owned();
+            name.push(".lock");
+            let lock_path = path.with_file_name(name);
+            // Locks are made before their worktrees, so one without a lock is left over too
+            let lock = File::open(&lock_path).ok();
+            if let Some(lock) = &lock {
+                if lock.try_lock().is_err() {
+                    continue;
+                }
+            }
+            // Another run may have pruned it since it was listed
+            if !path.exists() {
+                continue;
+            }
+            remove_dir_all(&path)
+                .with_context(|| format!("Failed to remove cached template {}", path.display()))?;
+            let _ = remove_file(&lock_path);
+        }
+    }
+    Ok(())
+}
+
+fn checkout_submodules(repo: &mut Repo) -> Result<()> {
+    if Registry::load()?.submodules()? {
+        repo.update_submodules()?;
+    }
+    Ok(())
+}
+
+fn locked_commit<'a>(lock: &'a Lockfile, url: &str) -> Result<&'a str> {
+    lock.get(url).with_context(|| {
+        format!(
+            "{} is not in the lockfile, run `generator update-lock {}`",
+            url, url
+        )
+    })
+}
+
+impl TemplateSource {
+    pub fn parse(template: &str) -> Self {
+        if archive::is_archive(template) || is_snapshot(template) {
+            TemplateSource::Archive(template.to_owned())
+        } else if Path::new(template).is_file() {
+            TemplateSource::File(template.to_owned())
+        } else if let Some(url) = file_url(template) {
+            TemplateSource::File(url.to_owned())
+        } else if Path::new(template).exists() {
+            TemplateSource::Local(template.into())
+        } else {
+            TemplateSource::Git(template.to_owned())
+        }
+    }
+
+    // The local directory holding the template, downloading or updating it in the cache if needed
+    pub fn fetch(&self) -> Result<Fetched> {
+        let caches = cache_dir();
+        if !caches.exists() {
+            create_dir_all(&caches)?
+        }
+        migrate(&caches)?;
+        match self {
+            TemplateSource::Local(path) => Ok(Fetched::new(path.clone())),
+            TemplateSource::Archive(source) => {
+                let cached_path = caches.join("archives").join(url_key(source));
+                let _lock = lock_entry(&cached_path)?;
+                archive::extract(source, &cached_path).map(Fetched::new)
+            }
+            TemplateSource::File(source) => {
+                let cached_path = caches.join("files").join(url_key(source));
+                let _lock = lock_entry(&cached_path)?;
+                wrap_file(source, &cached_path).map(Fetched::new)
+            }
+            TemplateSource::Git(url) if !Repo::available() => without_git(url)?.fetch(),
+            TemplateSource::Git(url) => {
+                let (url, reference) = split_ref(url);
+                let path = mirror_path(&caches, url);
+                let _lock = lock_entry(&path)?;
+                let mut mirror = if !path.exists() {
+                    Repo::mirror(url, &path)?
+                } else {
+                    let mut mirror = Repo::open_bare(&path)?;
+                    if let Err(e) = mirror.update_mirror() {
+                        match e.downcast_ref::<NetworkError>() {
+                            Some(_) if Registry::load()?.offline_fallback()? => {
+                                if !quiet() {
+                                    eprintln!(
+                                        "{} using the cached copy of {}, which couldn't be updated: {:#}",
+                                        Style::Warning.paint_err("Warning:"),
+                                        url,
+                                        e
+                                    )
+                                }
+                            }
+                            _ => return Err(e),
+                        }
+                    }
+                    mirror
+                };
+                let commit = match reference {
+                    Some(reference) => mirror.resolve(reference)?,
+                    None => mirror.head()?,
+                };
+                checkout(&caches, &mut mirror, url, &commit)
+            }
+        }
+    }
+
+    // Downloads the template into `dir` without reading or writing the cache, checked out at its
+    // commit in `lock` if given
+    pub fn fetch_fresh(&self, dir: &Path, lock: Option<&Lockfile>) -> Result<PathBuf> {
+        match self {
+            TemplateSource::Local(path) => Ok(path.clone()),
+            TemplateSource::Archive(source) if lock.is_some() => {
+                bail!("{} is an archive, only git templates can be locked", source)
+            }
+            TemplateSource::Archive(source) => {
+                archive::extract(source, &dir.join(&sha256_hex(source.as_bytes())[..32]))
+            }
+            TemplateSource::File(source) if lock.is_some() && is_http(source) => {
+                bail!(
+                    "{} is a single file, only git templates can be locked",
+                    source
+                )
+            }
+            TemplateSource::File(source) => {
+                wrap_file(source, &dir.join(&sha256_hex(source.as_bytes())[..32]))
+            }
+            TemplateSource::Git(url) if !Repo::available() && lock.is_none() => {
+                without_git(url)?.fetch_fresh(dir, lock)
+            }
+            TemplateSource::Git(source) => {
+                let (url, reference) = split_ref(source);
+                let path = dir.join(&sha256_hex(url.as_bytes())[..32]);
+                let mut repo = Repo::clone(url, &path)?;
+                let locked = lock.map(|o| locked_commit(o, source)).transpose()?;
+                if let Some(revision) = locked.or(reference) {
+                    repo.checkout(revision)?;
+                }
+                checkout_submodules(&mut repo)?;
+                Ok(path)
+            }
+        }
+    }
+
+    // A checkout of the commit pinned in `lock`, which only fetches if the cached mirror doesn't
+    // have it yet. Local templates are used as they are
+    pub fn fetch_locked(&self, lock: &Lockfile) -> Result<Fetched> {
+        match self {
+            TemplateSource::Local(path) => Ok(Fetched::new(path.clone())),
+            TemplateSource::Archive(source) => {
+                bail!("{} is an archive, only git templates can be locked", source)
+            }
+            // Local files are used as they are, like local templates
+            TemplateSource::File(source) if !is_http(source) => self.fetch(),
+            TemplateSource::File(source) => {
+                bail!(
+                    "{} is a single file, only git templates can be locked",
+                    source
+                )
+            }
+            TemplateSource::Git(source) => {
+                let commit = locked_commit(lock, source)?;
+                let url = split_ref(source).0;
+                let caches = cache_dir();
+                create_dir_all(&caches)?;
+                migrate(&caches)?;
+                let path = mirror_path(&caches, url);
+                let _lock = lock_entry(&path)?;
+                let mut mirror = if path.exists() {
+                    Repo::open_bare(&path)?
+                } else {
+                    Repo::mirror(url, &path)?
+                };
+                if !mirror.has_commit(commit) {
+                    mirror.update_mirror()?;
+                }
+                checkout(&caches, &mut mirror, url, commit)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CachedTemplate {
+    // The extracted archive or file, or the mirror git templates are checked out of for each run
+    pub path: PathBuf,
+    // For git templates, the commit a run would check out, on `branch`
+    pub commit: Option<String>,
+    pub branch: Option<String>,
+}
+
+// Where the cache holds a template, without fetching or updating it
+pub fn cached(template: String) -> Result<CachedTemplate> {
+    let caches = cache_dir();
+    let not_cached = |source: &str| anyhow!("{} isn't cached, generate from it first", source);
+    match resolve(template)?.0 {
+        TemplateSource::Local(path) => bail!("{} is local, so it isn't cached", path.display()),
+        TemplateSource::Archive(source) => {
+            let path = caches.join("archives").join(url_key(&source));
+            ensure!(path.exists(), not_cached(&source));
+            Ok(CachedTemplate {
+                path: archive::template_dir(&path)?,
+                ..CachedTemplate::default()
+            })
+        }
+        TemplateSource::File(source) => {
+            let path = caches.join("files").join(url_key(&source));
+            ensure!(path.exists(), not_cached(&source));
+            Ok(CachedTemplate {
+                path,
+                ..CachedTemplate::default()
+            })
+        }
+        TemplateSource::Git(source) => {
+            migrate(&caches)?;
+            let (url, reference) = split_ref(&source);
+            let path = mirror_path(&caches, url);
+            ensure!(path.exists(), not_cached(url));
+            let mirror = Repo::open_bare(&path)?;
+            let (commit, branch) = match reference {
+                Some(reference) => (mirror.resolve(reference)?, None),
+                None => (mirror.head()?, mirror.branch()?),
+            };
+            Ok(CachedTemplate {
+                path,
+                commit: Some(commit),
+                branch,
+            })
+        }
+    }
+}
+
+// URLs of git templates in the cache
+pub fn cached_templates() -> Result<Vec<String>> {
+    let caches = cache_dir();
+    if !caches.exists() {
+        return Ok(vec![]);
+    }
+    migrate(&caches)?;
+    let mirrors = caches.join("mirrors");
+    if !mirrors.exists() {
+        return Ok(vec![]);
+    }
+    let mut templates = vec![];
+    for entry in read_dir(&mirrors)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            // A clone interrupted before it finished may not be a repository
+            if let std::result::Result::Ok(url) =
+                Repo::open_bare(entry.path()).and_then(|o| o.remote_url())
+            {
+                templates.push(url);
+            }
+        }
+    }
+    templates.sort();
+    Ok(templates)
+}
+
+// The source a template argument refers to, after expanding aliases and abbreviations, and
+// the integrity it is pinned to either in the argument or in its alias
+pub fn resolve(template: String) -> Result<(TemplateSource, Option<Integrity>)> {
+    if <str as AsRef<Path>>::as_ref(&template).exists() {
+        return Ok((TemplateSource::parse(&template), None));
+    }
+    let registry = Registry::load()?;
+    let (template, mut integrity) = Integrity::split(&template);
+    // The ref applies to whatever an alias or abbreviation expands to
+    let (template, mut reference) = split_ref(template);
+    let template = match registry.get(template) {
+        Some(url) => {
+            let (url, pinned) = Integrity::split(url);
+            integrity = integrity.or(pinned);
+            let (url, aliased) = split_ref(url);
+            reference = reference.or(aliased);
+            url
+        }
+        None => template,
+    };
+    let mut template = registry
+        .expand(template)?
+        .unwrap_or_else(|| template.to_owned());
+    if let Some(reference) = reference {
+        template = format!("{}#{}", template, reference);
+    }
+    Ok((TemplateSource::parse(&template), integrity))
+}
+
+pub fn fetch(template: String) -> Result<Fetched> {
+    fetch_pinned(template, None, None)
+}
+
+// Fetches a template, checked out at its commit in `lock` if given and into `fresh` rather than
+// the cache if given, and refuses it if it doesn't match the integrity it's pinned to, or isn't
+// signed when the registry requires it
+pub fn fetch_pinned(
+    template: String,
+    lock: Option<&Lockfile>,
+    fresh: Option<&Path>,
+) -> Result<Fetched> {
+    let (source, integrity) = resolve(template)?;
+    let path = match (fresh, lock) {
+        (Some(dir), lock) => Fetched::new(source.fetch_fresh(dir, lock)?),
+        (None, Some(lock)) => source.fetch_locked(lock)?,
+        (None, None) => source.fetch()?,
+    };
+    if Registry::load()?.require_signed()? {
+        match &source {
+            // Local templates are the user's own
+            TemplateSource::Local(_) => {}
+            TemplateSource::File(source) | TemplateSource::Archive(source) if !is_http(source) => {}
+            TemplateSource::Archive(url) => {
+                bail!("{} is an archive, which can't be signed", url)
+            }
+            TemplateSource::File(url) => {
+                bail!("{} is a single file, which can't be signed", url)
+            }
+            TemplateSource::Git(_) => Repo::open(&path)?.verify_signature()?,
+        }
+    }
+    if let Some(integrity) = integrity {
+        integrity.verify(&path)?;
+    }
+    Ok(path)
+}
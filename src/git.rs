@@ -1,10 +1,15 @@
 use std::{
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Output},
+    thread::sleep,
+    time::Duration,
 };
 
 use anyhow::*;
 use thiserror::Error;
+use walkdir::WalkDir;
+
+use crate::{proxy, GIT_CONFIG_ENV};
 
 pub struct Repo(PathBuf);
 
@@ -20,19 +25,172 @@ pub struct OpenError(PathBuf);
 #[error("Failed to pull from remote in repo {0}")]
 pub struct PullError(PathBuf);
 
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to initialise repo at {0}")]
+pub struct InitError(PathBuf);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to commit in repo {0}: {1}")]
+pub struct CommitError(PathBuf, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to read the current commit of repo {0}")]
+pub struct HeadError(PathBuf);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("No branch, tag or commit {1} in repo {0}")]
+pub struct RefError(PathBuf, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to check out {1} in repo {0}")]
+pub struct CheckoutError(PathBuf, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to tag repo {0} as {1}: {2}")]
+pub struct TagError(PathBuf, String, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to push from repo {0}: {1}")]
+pub struct PushError(PathBuf, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to update submodules of repo {0}: {1}")]
+pub struct SubmoduleError(PathBuf, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to check out {2} of repo {0} at {1}")]
+pub struct WorktreeError(PathBuf, PathBuf, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Failed to configure repo {0}: {1}")]
+pub struct ConfigError(PathBuf, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Could not reach {0}, the network may be unreachable: {1}")]
+pub struct NetworkError(String, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Neither the HEAD commit nor a tag of repo {0} has a valid signature: {1}")]
+pub struct SignatureError(PathBuf, String);
+
+// What git says when it couldn't reach a remote, as opposed to the remote refusing the request
+const NETWORK_FAILURES: &[&str] = &[
+    "Could not resolve host",
+    "Could not resolve proxy",
+    "Failed to connect",
+    "Connection refused",
+    "Connection reset",
+    "Connection timed out",
+    "Operation timed out",
+    "Network is unreachable",
+    "The remote end hung up unexpectedly",
+    "early EOF",
+];
+
+const ATTEMPTS: u32 = 4;
+const BACKOFF: Duration = Duration::from_secs(1);
+
+// Runs git against `remote`, trying again after 1, 2 and then 4 seconds while it can't reach it
+fn retrying(remote: &str, mut git: impl FnMut() -> std::io::Result<Output>) -> Result<Output> {
+    let mut delay = BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let out = git()?;
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        if out.status.success() || !NETWORK_FAILURES.iter().any(|o| stderr.contains(o)) {
+            return Ok(out);
+        }
+        if attempt == ATTEMPTS {
+            bail!(NetworkError(remote.to_owned(), stderr.trim().to_owned()));
+        }
+        sleep(delay);
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+// `-c` options for git commands reaching `remote`: the extra config given with `--git-config`,
+// and the proxy from the environment, which git's curl doesn't read from `HTTP_PROXY`
+fn remote_config(remote: &str) -> Vec<String> {
+    let extra = std::env::var(GIT_CONFIG_ENV).unwrap_or_default();
+    let mut config = extra
+        .lines()
+        .filter(|o| !o.trim().is_empty())
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    if let Some(proxy) = proxy::for_url(remote) {
+        if !config.iter().any(|o| o.starts_with("http.proxy=")) {
+            config.push(format!("http.proxy={}", proxy));
+        }
+    }
+    config
+        .into_iter()
+        .flat_map(|o| ["-c".to_owned(), o])
+        .collect()
+}
+
 impl Repo {
+    pub fn available() -> bool {
+        Command::new("git").arg("--version").output().is_ok()
+    }
+
     pub fn clone(remote: &str, dst: impl AsRef<Path>) -> Result<Self> {
         let dst = dst.as_ref().to_owned();
-        let out = Command::new("git")
-            .arg("clone")
-            .arg(remote)
-            .arg(&dst)
-            .output()
-            .with_context(|| CloneError(remote.to_owned(), dst.clone()))?;
+        let out = retrying(remote, || {
+            Command::new("git")
+                .args(remote_config(remote))
+                .arg("clone")
+                .arg(remote)
+                .arg(&dst)
+                .output()
+        })
+        .with_context(|| CloneError(remote.to_owned(), dst.clone()))?;
+        ensure!(out.status.success(), CloneError(remote.to_owned(), dst));
+        Ok(Self(dst))
+    }
+
+    // A bare mirror of every ref of `remote`, with no working copy to get into a state that stops
+    // it updating
+    pub fn mirror(remote: &str, dst: impl AsRef<Path>) -> Result<Self> {
+        let dst = dst.as_ref().to_owned();
+        let out = retrying(remote, || {
+            Command::new("git")
+                .args(remote_config(remote))
+                .args(["clone", "--mirror", "--quiet"])
+                .arg(remote)
+                .arg(&dst)
+                .output()
+        })
+        .with_context(|| CloneError(remote.to_owned(), dst.clone()))?;
         ensure!(out.status.success(), CloneError(remote.to_owned(), dst));
         Ok(Self(dst))
     }
 
+    // Turns the `.git` directory of a clone, moved out of its working copy, into a mirror of the
+    // same remote
+    pub fn convert_to_mirror(git_dir: impl AsRef<Path>) -> Result<Self> {
+        let mut repo = Self(git_dir.as_ref().to_owned());
+        repo.set_config("core.bare", "true")?;
+        repo.set_config("remote.origin.fetch", "+refs/*:refs/*")?;
+        repo.set_config("remote.origin.mirror", "true")?;
+        Ok(repo)
+    }
+
+    pub fn open_bare(location: impl AsRef<Path>) -> Result<Self> {
+        let location = location.as_ref().to_owned();
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(&location)
+            .args(["rev-parse", "--is-bare-repository"])
+            .output()
+            .with_context(|| OpenError(location.clone()))?;
+        ensure!(
+            out.status.success() && String::from_utf8_lossy(&out.stdout).trim() == "true",
+            OpenError(location)
+        );
+        Ok(Self(location))
+    }
+
     pub fn open(location: impl AsRef<Path>) -> Result<Self> {
         let location = location.as_ref().to_owned();
         let out = Command::new("git")
@@ -50,13 +208,357 @@ impl Repo {
     }
 
     pub fn pull(&mut self) -> Result<()> {
+        let remote = self
+            .remote_url()
+            .unwrap_or_else(|_| self.path().display().to_string());
+        let out = retrying(&remote, || {
+            Command::new("git")
+                .args(remote_config(&remote))
+                .arg("-C")
+                .arg(self.path())
+                .arg("pull")
+                .output()
+        })
+        .with_context(|| PullError(self.path().to_owned()))?;
+        ensure!(out.status.success(), PullError(self.path().to_owned()));
+        Ok(())
+    }
+
+    // Updates every ref of a mirror, dropping those the remote no longer has
+    pub fn update_mirror(&mut self) -> Result<()> {
+        let remote = self
+            .remote_url()
+            .unwrap_or_else(|_| self.path().display().to_string());
+        let out = retrying(&remote, || {
+            Command::new("git")
+                .args(remote_config(&remote))
+                .arg("-C")
+                .arg(self.path())
+                .args(["remote", "update", "--prune"])
+                .output()
+        })
+        .with_context(|| PullError(self.path().to_owned()))?;
+        ensure!(out.status.success(), PullError(self.path().to_owned()));
+        Ok(())
+    }
+
+    pub fn has_commit(&self, commit: &str) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["cat-file", "-e"])
+            .arg(format!("{}^{{commit}}", commit))
+            .output()
+            .is_ok_and(|o| o.status.success())
+    }
+
+    // Checks out `commit` at `path`, sharing this repository's objects rather than copying them
+    pub fn add_worktree(&mut self, path: impl AsRef<Path>, commit: &str) -> Result<Repo> {
+        let path = path.as_ref().to_owned();
+        let error = |message: String| WorktreeError(self.path().to_owned(), path.clone(), message);
+        // Forgets worktrees whose directories were removed
+        Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["worktree", "prune"])
+            .output()
+            .with_context(|| error("failed to run git".to_owned()))?;
         let out = Command::new("git")
             .arg("-C")
             .arg(self.path())
-            .arg("pull")
+            .args(["worktree", "add", "--quiet", "--detach", "--force"])
+            .arg(&path)
+            .arg(commit)
             .output()
-            .with_context(|| PullError(self.path().to_owned()))?;
-        ensure!(out.status.success(), PullError(self.path().to_owned()));
+            .with_context(|| error("failed to run git".to_owned()))?;
+        ensure!(
+            out.status.success(),
+            error(String::from_utf8_lossy(&out.stderr).trim().to_owned())
+        );
+        Ok(Repo(path))
+    }
+
+    pub fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["config", key, value])
+            .output()
+            .with_context(|| ConfigError(self.path().to_owned(), "failed to run git".to_owned()))?;
+        ensure!(
+            out.status.success(),
+            ConfigError(
+                self.path().to_owned(),
+                String::from_utf8_lossy(&out.stderr).trim().to_owned()
+            )
+        );
         Ok(())
     }
+
+    // Replaces the `.git` files `destination` was given by copying a worktree, its own and its
+    // submodules', with repositories of their own holding the history of their commits
+    pub fn detach_worktree(worktree: &Path, destination: &Path) -> Result<()> {
+        let linked = WalkDir::new(destination)
+            .into_iter()
+            .filter_map(|o| o.ok())
+            .filter(|o| o.file_name() == ".git" && o.file_type().is_file())
+            .filter_map(|o| {
+                let dir = o.path().parent()?;
+                Some(dir.strip_prefix(destination).ok()?.to_owned())
+            })
+            .collect::<Vec<_>>();
+        for relative in linked {
+            Self::detach(&worktree.join(&relative), &destination.join(&relative))?;
+        }
+        Ok(())
+    }
+
+    fn detach(worktree: &Path, destination: &Path) -> Result<Repo> {
+        let source = Repo::open(worktree)?;
+        std::fs::remove_file(destination.join(".git"))
+            .with_context(|| InitError(destination.to_owned()))?;
+        let mut repo = Repo::init(destination)?;
+        let git = |args: &[&std::ffi::OsStr]| {
+            let out = Command::new("git")
+                .arg("-C")
+                .arg(destination)
+                .args(args)
+                .output()
+                .with_context(|| InitError(destination.to_owned()))?;
+            ensure!(out.status.success(), InitError(destination.to_owned()));
+            Ok(())
+        };
+        git(&[
+            "fetch".as_ref(),
+            "--quiet".as_ref(),
+            worktree.as_os_str(),
+            "HEAD".as_ref(),
+        ])?;
+        // Points the branch at the template's commit, leaving the generated files as they are
+        git(&["reset".as_ref(), "--quiet".as_ref(), "FETCH_HEAD".as_ref()])?;
+        if let std::result::Result::Ok(url) = source.remote_url() {
+            repo.set_config("remote.origin.url", &url)?;
+            repo.set_config("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*")?;
+        }
+        Ok(repo)
+    }
+
+    // Checks out the submodules of the current commit, and theirs, which templates use to vendor
+    // shared files
+    pub fn update_submodules(&mut self) -> Result<()> {
+        let remote = self
+            .remote_url()
+            .unwrap_or_else(|_| self.path().display().to_string());
+        let out = retrying(&remote, || {
+            Command::new("git")
+                .args(remote_config(&remote))
+                .arg("-C")
+                .arg(self.path())
+                .args(["submodule", "update", "--init", "--recursive", "--quiet"])
+                .output()
+        })
+        .with_context(|| SubmoduleError(self.path().to_owned(), "failed to run git".to_owned()))?;
+        ensure!(
+            out.status.success(),
+            SubmoduleError(
+                self.path().to_owned(),
+                String::from_utf8_lossy(&out.stderr).trim().to_owned()
+            )
+        );
+        Ok(())
+    }
+
+    pub fn init(location: impl AsRef<Path>) -> Result<Self> {
+        let location = location.as_ref().to_owned();
+        let out = Command::new("git")
+            .arg("init")
+            .arg(&location)
+            .output()
+            .with_context(|| InitError(location.clone()))?;
+        ensure!(out.status.success(), InitError(location));
+        Ok(Self(location))
+    }
+
+    pub fn commit(&mut self, message: &str) -> Result<()> {
+        let error = |out: &std::process::Output| {
+            CommitError(
+                self.path().to_owned(),
+                String::from_utf8_lossy(&out.stderr).trim().to_owned(),
+            )
+        };
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["add", "--all"])
+            .output()
+            .with_context(|| CommitError(self.path().to_owned(), "git add failed".to_owned()))?;
+        ensure!(out.status.success(), error(&out));
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["commit", "--quiet", "-m", message])
+            .output()
+            .with_context(|| CommitError(self.path().to_owned(), "git commit failed".to_owned()))?;
+        ensure!(out.status.success(), error(&out));
+        Ok(())
+    }
+
+    pub fn remote_url(&self) -> Result<String> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["remote", "get-url", "origin"])
+            .output()
+            .with_context(|| OpenError(self.path().to_owned()))?;
+        ensure!(out.status.success(), OpenError(self.path().to_owned()));
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+    }
+
+    pub fn head(&self) -> Result<String> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .with_context(|| HeadError(self.path().to_owned()))?;
+        ensure!(out.status.success(), HeadError(self.path().to_owned()));
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+    }
+
+    // The commit a branch, tag or commit id names
+    pub fn resolve(&self, reference: &str) -> Result<String> {
+        let error = || RefError(self.path().to_owned(), reference.to_owned());
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["rev-parse", "--verify", "--quiet", "--end-of-options"])
+            .arg(format!("{}^{{commit}}", reference))
+            .output()
+            .with_context(error)?;
+        ensure!(out.status.success(), error());
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+    }
+
+    // The branch checked out, or that a bare repository's HEAD names, unless it's detached
+    pub fn branch(&self) -> Result<Option<String>> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+            .output()
+            .with_context(|| HeadError(self.path().to_owned()))?;
+        Ok(if out.status.success() {
+            Some(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+        } else {
+            None
+        })
+    }
+
+    // Resolved first, so that a revision like `--orphan=x` can't be taken for an option. Branches
+    // other than the default are only remote-tracking ones in a fresh clone
+    pub fn checkout(&mut self, revision: &str) -> Result<()> {
+        let commit = self
+            .resolve(revision)
+            .or_else(|e| self.resolve(&format!("origin/{}", revision)).map_err(|_| e))?;
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["checkout", "--quiet", "--detach", &commit])
+            .output()
+            .with_context(|| CheckoutError(self.path().to_owned(), revision.to_owned()))?;
+        ensure!(
+            out.status.success(),
+            CheckoutError(self.path().to_owned(), revision.to_owned())
+        );
+        Ok(())
+    }
+
+    // Whether there are no uncommitted changes, including untracked files
+    pub fn is_clean(&self) -> Result<bool> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["status", "--porcelain"])
+            .output()
+            .with_context(|| OpenError(self.path().to_owned()))?;
+        ensure!(out.status.success(), OpenError(self.path().to_owned()));
+        Ok(out.stdout.is_empty())
+    }
+
+    pub fn tag(&mut self, name: &str, message: &str) -> Result<()> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["tag", "--annotate", name, "-m", message])
+            .output()
+            .with_context(|| {
+                TagError(
+                    self.path().to_owned(),
+                    name.to_owned(),
+                    "failed to run git".to_owned(),
+                )
+            })?;
+        ensure!(
+            out.status.success(),
+            TagError(
+                self.path().to_owned(),
+                name.to_owned(),
+                String::from_utf8_lossy(&out.stderr).trim().to_owned()
+            )
+        );
+        Ok(())
+    }
+
+    // Pushes the current branch and `refs`, like a tag, to `origin`
+    pub fn push(&mut self, refs: &[&str]) -> Result<()> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(self.path())
+            .args(["push", "--quiet", "origin", "HEAD"])
+            .args(refs)
+            .output()
+            .with_context(|| PushError(self.path().to_owned(), "failed to run git".to_owned()))?;
+        ensure!(
+            out.status.success(),
+            PushError(
+                self.path().to_owned(),
+                String::from_utf8_lossy(&out.stderr).trim().to_owned()
+            )
+        );
+        Ok(())
+    }
+
+    // Checks signatures with `git verify-commit` and `git verify-tag`, so gpg and ssh keys are
+    // trusted as git is configured to trust them
+    pub fn verify_signature(&self) -> Result<()> {
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(self.path())
+                .args(args)
+                .output()
+                .with_context(|| {
+                    SignatureError(self.path().to_owned(), "failed to run git".to_owned())
+                })
+        };
+        let out = git(&["verify-commit", "HEAD"])?;
+        if out.status.success() {
+            return Ok(());
+        }
+        let tags = git(&["tag", "--points-at", "HEAD"])?;
+        for tag in String::from_utf8_lossy(&tags.stdout).lines() {
+            if git(&["verify-tag", tag])?.status.success() {
+                return Ok(());
+            }
+        }
+        let reason = String::from_utf8_lossy(&out.stderr).trim().to_owned();
+        bail!(SignatureError(
+            self.path().to_owned(),
+            if reason.is_empty() {
+                "no signature found".to_owned()
+            } else {
+                reason
+            }
+        ))
+    }
 }
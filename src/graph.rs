@@ -0,0 +1,238 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{load_definition, usage, Config, Engine, Layer, TemplateDef};
+
+static VARIABLE_REFERENCE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{-?\s*([A-Za-z_][A-Za-z0-9_]*)").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    label: &'static str,
+}
+
+// The indices of the variables `text` refers to, found by pattern for engines that aren't parsed
+fn variables_in(def: &TemplateDef, text: &str) -> Vec<usize> {
+    if def.engine == Engine::Tera {
+        return usage::referenced(def, text);
+    }
+    VARIABLE_REFERENCE
+        .captures_iter(text)
+        .filter_map(|c| def.variables.iter().position(|v| v.name == c[1]))
+        .collect()
+}
+
+// Defaults profiles by name, with the indices of the variables each gives a default for
+fn profiles(def: &TemplateDef) -> Result<Vec<(String, Vec<usize>)>> {
+    Config::profiles()?
+        .into_iter()
+        .map(|name| {
+            let values = Config::profile_values(&name)?;
+            let variables = def
+                .variables
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| {
+                    values
+                        .keys()
+                        .any(|k| *k == v.name || k.starts_with(&format!("{}.", v.name)))
+                })
+                .map(|(i, _)| i)
+                .collect();
+            Ok((name, variables))
+        })
+        .collect()
+}
+
+fn edges(def: &TemplateDef, layers: &[Layer], profiles: &[(String, Vec<usize>)]) -> Vec<Edge> {
+    let mut edges = vec![];
+    // Each template extends the one before it, which is the most basic first
+    for i in 1..layers.len() {
+        edges.push(Edge {
+            from: format!("template{}", i),
+            to: format!("template{}", i - 1),
+            label: "extends",
+        });
+    }
+    for (i, dependencies) in usage::dependencies(def).into_iter().enumerate() {
+        for dependency in dependencies {
+            edges.push(Edge {
+                from: format!("var{}", dependency),
+                to: format!("var{}", i),
+                label: "default",
+            });
+        }
+    }
+    for (i, (_, variables)) in profiles.iter().enumerate() {
+        for v in variables {
+            edges.push(Edge {
+                from: format!("profile{}", i),
+                to: format!("var{}", v),
+                label: "default",
+            });
+        }
+    }
+    for (i, rule) in def.files.iter().enumerate() {
+        // Variables a rule's `rename` and `skip_if` read lead to it
+        let texts = rule.rename.iter().map(|o| (o, "rename"));
+        for (text, label) in texts.chain(rule.skip_if.iter().map(|o| (o, "skip_if"))) {
+            for v in variables_in(def, text) {
+                edges.push(Edge {
+                    from: format!("var{}", v),
+                    to: format!("rule{}", i),
+                    label,
+                })
+            }
+        }
+    }
+    edges
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Mermaid labels are quoted, so only quotes and its own `#` entities need escaping
+fn mermaid_escape(s: &str) -> String {
+    s.replace('#', "#35;").replace('"', "#quot;")
+}
+
+fn dot(def: &TemplateDef, layers: &[Layer], profiles: &[(String, Vec<usize>)]) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph template {{").unwrap();
+    writeln!(out, "    rankdir=LR;").unwrap();
+    if layers.len() > 1 {
+        writeln!(out, "    subgraph cluster_templates {{").unwrap();
+        writeln!(out, "        label=\"templates\";").unwrap();
+        for (i, layer) in layers.iter().enumerate() {
+            writeln!(
+                out,
+                "        template{} [shape=folder, label=\"{}\"];",
+                i,
+                escape(&layer.template.display().to_string())
+            )
+            .unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+    writeln!(out, "    subgraph cluster_files {{").unwrap();
+    writeln!(out, "        label=\"files (first match wins)\";").unwrap();
+    for (i, rule) in def.files.iter().enumerate() {
+        writeln!(
+            out,
+            "        rule{} [shape=box, label=\"{}\"];",
+            i,
+            escape(&rule.describe())
+        )
+        .unwrap();
+    }
+    for i in 1..def.files.len() {
+        writeln!(out, "        rule{} -> rule{} [style=dotted];", i - 1, i).unwrap();
+    }
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    subgraph cluster_variables {{").unwrap();
+    writeln!(out, "        label=\"variables\";").unwrap();
+    for (i, var) in def.variables.iter().enumerate() {
+        let label = match &var.default {
+            Some(default) => format!("{}\\ndefault: {}", escape(&var.name), escape(default)),
+            None => escape(&var.name),
+        };
+        writeln!(
+            out,
+            "        var{} [shape=ellipse, label=\"{}\"];",
+            i, label
+        )
+        .unwrap();
+    }
+    writeln!(out, "    }}").unwrap();
+    if !profiles.is_empty() {
+        writeln!(out, "    subgraph cluster_profiles {{").unwrap();
+        writeln!(out, "        label=\"defaults profiles\";").unwrap();
+        for (i, (name, _)) in profiles.iter().enumerate() {
+            writeln!(
+                out,
+                "        profile{} [shape=note, label=\"{}\"];",
+                i,
+                escape(name)
+            )
+            .unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+    for edge in edges(def, layers, profiles) {
+        writeln!(
+            out,
+            "    {} -> {} [label=\"{}\"];",
+            edge.from, edge.to, edge.label
+        )
+        .unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn mermaid(def: &TemplateDef, layers: &[Layer], profiles: &[(String, Vec<usize>)]) -> String {
+    let mut out = String::new();
+    writeln!(out, "flowchart LR").unwrap();
+    if layers.len() > 1 {
+        writeln!(out, "    subgraph templates").unwrap();
+        for (i, layer) in layers.iter().enumerate() {
+            writeln!(
+                out,
+                "        template{}[/\"{}\"/]",
+                i,
+                mermaid_escape(&layer.template.display().to_string())
+            )
+            .unwrap();
+        }
+        writeln!(out, "    end").unwrap();
+    }
+    writeln!(out, "    subgraph files").unwrap();
+    for (i, rule) in def.files.iter().enumerate() {
+        writeln!(
+            out,
+            "        rule{}[\"{}\"]",
+            i,
+            mermaid_escape(&rule.describe())
+        )
+        .unwrap();
+    }
+    writeln!(out, "    end").unwrap();
+    writeln!(out, "    subgraph variables").unwrap();
+    for (i, var) in def.variables.iter().enumerate() {
+        writeln!(out, "        var{}([\"{}\"])", i, mermaid_escape(&var.name)).unwrap();
+    }
+    writeln!(out, "    end").unwrap();
+    if !profiles.is_empty() {
+        writeln!(out, "    subgraph profiles").unwrap();
+        for (i, (name, _)) in profiles.iter().enumerate() {
+            writeln!(out, "        profile{}[[\"{}\"]]", i, mermaid_escape(name)).unwrap();
+        }
+        writeln!(out, "    end").unwrap();
+    }
+    for edge in edges(def, layers, profiles) {
+        writeln!(out, "    {} -->|{}| {}", edge.from, edge.label, edge.to).unwrap();
+    }
+    out
+}
+
+pub fn graph(template: impl AsRef<Path>, format: GraphFormat) -> Result<String> {
+    let template = template.as_ref();
+    let def = load_definition(template)?;
+    let layers = def.layers(template);
+    let profiles = profiles(&def)?;
+    Ok(match format {
+        GraphFormat::Dot => dot(&def, &layers, &profiles),
+        GraphFormat::Mermaid => mermaid(&def, &layers, &profiles),
+    })
+}
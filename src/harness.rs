@@ -0,0 +1,175 @@
+use std::{
+    collections::BTreeMap,
+    fs::{read, read_dir, read_to_string, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use serde_yaml::from_reader;
+use walkdir::WalkDir;
+
+use crate::{generate_with, load_definition, Coverage, Missing, Options, Profile, TemplateDef};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub name: String,
+    pub failures: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestReport {
+    pub cases: Vec<CaseResult>,
+    pub coverage: Coverage,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(CaseResult::passed)
+    }
+}
+
+pub(crate) fn cases(template: &Path, def: &TemplateDef) -> Result<Vec<String>> {
+    let dir = template.join(def.tests_dir());
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut cases = read_dir(&dir)
+        .with_context(|| format!("Failed to read tests in {}", dir.display()))?
+        .filter_map(|o| o.ok())
+        .filter(|o| o.path().join("expected").is_dir())
+        .filter_map(|o| o.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    cases.sort();
+    Ok(cases)
+}
+
+fn answers(case: &Path) -> Result<BTreeMap<String, tera::Value>> {
+    let path = case.join("answers.yml");
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let value: serde_yaml::Value = from_reader(File::open(&path)?)
+        .with_context(|| format!("Invalid yaml in {}", path.display()))?;
+    serde_json::from_value(serde_json::to_value(value)?)
+        .with_context(|| format!("Expected {} to be a mapping", path.display()))
+}
+
+// The profile a case generates with, named in its `profile` file, or the default one
+fn profile(case: &Path) -> Result<Profile> {
+    let path = case.join("profile");
+    if !path.exists() {
+        return Ok(Profile::Default);
+    }
+    read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid profile in {}", path.display()))
+}
+
+fn files(root: &Path) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+    WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(|o| o.ok())
+        .filter(|o| o.file_type().is_file())
+        .map(|o| {
+            let path = o
+                .path()
+                .strip_prefix(root)
+                .expect("Walked paths are children of the root")
+                .to_owned();
+            Ok((path, read(o.path())?))
+        })
+        .collect()
+}
+
+fn describe_difference(expected: &[u8], actual: &[u8]) -> String {
+    match (std::str::from_utf8(expected), std::str::from_utf8(actual)) {
+        (std::result::Result::Ok(expected), std::result::Result::Ok(actual)) => {
+            let mut expected_lines = expected.lines();
+            let mut actual_lines = actual.lines();
+            let mut line = 1;
+            loop {
+                match (expected_lines.next(), actual_lines.next()) {
+                    (Some(e), Some(a)) if e == a => line += 1,
+                    (e, a) => {
+                        return format!(
+                            "line {}: expected {:?}, got {:?}",
+                            line,
+                            e.unwrap_or("<end of file>"),
+                            a.unwrap_or("<end of file>")
+                        )
+                    }
+                }
+            }
+        }
+        _ => "binary contents differ".to_owned(),
+    }
+}
+
+pub(crate) fn diff(expected: &Path, actual: &Path) -> Result<Vec<String>> {
+    let expected = files(expected)?;
+    let actual = files(actual)?;
+    let mut failures = vec![];
+    for (path, contents) in &expected {
+        match actual.get(path) {
+            None => failures.push(format!("missing {}", path.display())),
+            Some(a) if a != contents => failures.push(format!(
+                "{} differs, {}",
+                path.display(),
+                describe_difference(contents, a)
+            )),
+            Some(_) => {}
+        }
+    }
+    for path in actual.keys().filter(|o| !expected.contains_key(*o)) {
+        failures.push(format!("unexpected {}", path.display()));
+    }
+    Ok(failures)
+}
+
+pub fn test_template(template: impl AsRef<Path>) -> Result<TestReport> {
+    let template = template.as_ref();
+    let def = load_definition(template)?;
+    let mut coverage = Coverage::new(&def);
+    let mut results = vec![];
+    for case in &def.test_cases {
+        let case_dir = template.join(def.tests_dir()).join(case);
+        let dir = tempfile::Builder::new()
+            .prefix("generator-test")
+            .tempdir()
+            .context("Failed to create temporary directory")?;
+        let destination = dir.path().join(case);
+        let options = Options {
+            missing: Missing::Fail,
+            answers: answers(&case_dir)?,
+            profile: profile(&case_dir)?,
+            ignore_user_defaults: true,
+            ignore_environment: true,
+            skip_record: true,
+            ..Options::default()
+        };
+        let failures = match generate_with(template, &destination, &options) {
+            std::result::Result::Ok(report) => {
+                coverage.merge(&report.coverage);
+                diff(&case_dir.join("expected"), &destination)?
+            }
+            Err(e) => vec![format!("generation failed: {:#}", e)],
+        };
+        results.push(CaseResult {
+            name: case.clone(),
+            failures,
+        });
+    }
+    Ok(TestReport {
+        cases: results,
+        coverage,
+    })
+}
@@ -0,0 +1,158 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::*;
+use serde_yaml::Value;
+use thiserror::Error;
+
+use crate::{get_strings, nested, quiet, smoke, Registry};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HookError {
+    #[error("Hook `{0}` failed with {1}")]
+    Failed(String, ExitStatus),
+    #[error("Hook `{0}` timed out after {1:?}")]
+    TimedOut(String, Duration),
+}
+
+// A command run before files are generated, whose output gives the values it `provides`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Hook {
+    pub(crate) command: String,
+    pub(crate) provides: Vec<String>,
+    // The template the hook is from, for hooks of base templates, which run there rather than in
+    // the template extending them
+    pub(crate) dir: Option<PathBuf>,
+}
+
+// `hooks.pre` is a sequence of commands, or of mappings of `command` and the variables it
+// `provides`
+pub(crate) fn parse(value: Option<&Value>) -> Result<Vec<Hook>> {
+    let pre = match value {
+        None => return Ok(vec![]),
+        Some(hooks) => {
+            ensure!(hooks.is_mapping(), "Expected `hooks` to be a mapping");
+            match hooks.get("pre") {
+                None => return Ok(vec![]),
+                Some(pre) => pre
+                    .as_sequence()
+                    .context("Expected `hooks.pre` to be a sequence")?,
+            }
+        }
+    };
+    pre.iter()
+        .map(|o| match o {
+            Value::String(command) => Ok(Hook {
+                command: command.clone(),
+                ..Hook::default()
+            }),
+            Value::Mapping(_) => Ok(Hook {
+                command: o
+                    .get("command")
+                    .and_then(Value::as_str)
+                    .context("Expected hooks to have a `command`")?
+                    .to_owned(),
+                provides: get_strings(o, "provides")?,
+                dir: None,
+            }),
+            _ => bail!("Expected hooks to be commands or mappings"),
+        })
+        .collect()
+}
+
+// Runs each hook in its template with the context so far as JSON on stdin, merging what it
+// prints, as a YAML or JSON mapping, into the context. Hooks give exactly the values they
+// declare, so templates can't come to depend on undeclared output
+pub(crate) fn run_pre(hooks: &[Hook], template: &Path, context: &mut tera::Context) -> Result<()> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    let sandbox = Registry::load()?.sandbox()?;
+    let timeout = sandbox.timeout.unwrap_or(smoke::DEFAULT_TIMEOUT);
+    for hook in hooks {
+        // Output of hooks providing nothing is shown on stderr, as `render` prints files to stdout
+        let stdout = if !hook.provides.is_empty() {
+            Stdio::piped()
+        } else if quiet() {
+            Stdio::null()
+        } else {
+            std::io::stderr().into()
+        };
+        let start = Instant::now();
+        let mut child = sandbox
+            .command(&hook.command, hook.dir.as_deref().unwrap_or(template))
+            .stdin(Stdio::piped())
+            .stdout(stdout)
+            .spawn()
+            .with_context(|| format!("Failed to run hook `{}`", hook.command))?;
+        // Read and written alongside waiting, so that a hook printing more than a pipe holds, or
+        // not reading a context larger than one, doesn't block before the timeout can stop it
+        let reader = child.stdout.take().map(|mut stdout| {
+            std::thread::spawn(move || {
+                let mut output = vec![];
+                stdout.read_to_end(&mut output).map(|_| output)
+            })
+        });
+        let input = serde_json::to_vec(&context.clone().into_json())?;
+        let mut stdin = child.stdin.take().expect("Stdin is piped");
+        // Hooks that don't read their input close stdin early, which isn't an error
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if start.elapsed() > timeout {
+                child.kill()?;
+                child.wait()?;
+                bail!(HookError::TimedOut(hook.command.clone(), timeout));
+            }
+            sleep(Duration::from_millis(50));
+        };
+        ensure!(
+            status.success(),
+            HookError::Failed(hook.command.clone(), status)
+        );
+        let output = match reader {
+            Some(reader) => reader
+                .join()
+                .map_err(|_| anyhow!("Failed to read output of hook `{}`", hook.command))??,
+            None => continue,
+        };
+        let values: Value = serde_yaml::from_slice(&output)
+            .with_context(|| format!("Invalid output from hook `{}`", hook.command))?;
+        let values = values.as_mapping().with_context(|| {
+            format!(
+                "Expected hook `{}` to print a mapping of the values it provides",
+                hook.command
+            )
+        })?;
+        for (name, value) in values {
+            let name = name
+                .as_str()
+                .context("Expected names of provided values to be strings")?;
+            ensure!(
+                hook.provides.iter().any(|o| o == name),
+                "Hook `{}` gave {}, which it doesn't list in `provides`",
+                hook.command,
+                name
+            );
+            nested::insert(context, name, serde_json::to_value(value)?);
+        }
+        for name in &hook.provides {
+            ensure!(
+                values.contains_key(&Value::String(name.clone())),
+                "Hook `{}` didn't give {}, which it `provides`",
+                hook.command,
+                name
+            );
+        }
+    }
+    Ok(())
+}
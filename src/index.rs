@@ -0,0 +1,89 @@
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::*;
+use serde_yaml::{from_reader, Value};
+
+use crate::proxy;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub url: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl IndexEntry {
+    pub fn matches(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        self.name.to_lowercase().contains(&term)
+            || self
+                .description
+                .as_ref()
+                .map(|o| o.to_lowercase().contains(&term))
+                .unwrap_or(false)
+            || self.tags.iter().any(|o| o.to_lowercase() == term)
+    }
+}
+
+pub(crate) fn open(source: &str) -> Result<Box<dyn Read>> {
+    Ok(
+        if source.starts_with("https://") || source.starts_with("http://") {
+            Box::new(
+                proxy::agent(source)?
+                    .get(source)
+                    .call()
+                    .with_context(|| format!("Failed to fetch {}", source))?
+                    .into_reader(),
+            )
+        } else {
+            Box::new(
+                File::open(Path::new(source))
+                    .with_context(|| format!("Failed to open {}", source))?,
+            )
+        },
+    )
+}
+
+fn parse_entry(value: &Value) -> Result<IndexEntry> {
+    let string = |key: &str| {
+        value
+            .get(key)
+            .map(|o| {
+                o.as_str()
+                    .map(str::to_owned)
+                    .with_context(|| format!("Expected `{}` to be a string", key))
+            })
+            .transpose()
+    };
+    Ok(IndexEntry {
+        name: string("name")?.context("Expected name for index entry")?,
+        url: string("url")?.context("Expected url for index entry")?,
+        description: string("description")?,
+        tags: value
+            .get("tags")
+            .unwrap_or(&Value::Sequence(vec![]))
+            .as_sequence()
+            .context("Expected `tags` to be a sequence")?
+            .iter()
+            .map(|o| {
+                o.as_str()
+                    .map(str::to_owned)
+                    .context("Expected tags to be strings")
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+pub fn fetch_index(source: &str) -> Result<Vec<IndexEntry>> {
+    let value: Value =
+        from_reader(open(source)?).with_context(|| format!("Invalid index {}", source))?;
+    value
+        .get("templates")
+        .context("Expected index to contain `templates`")?
+        .as_sequence()
+        .context("Expected `templates` to be a sequence")?
+        .iter()
+        .map(parse_entry)
+        .collect()
+}
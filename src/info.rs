@@ -0,0 +1,70 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::*;
+
+use crate::{load_definition, VariableKind};
+
+pub fn info(template: impl AsRef<Path>) -> Result<String> {
+    let def = load_definition(template.as_ref())?;
+    let mut out = String::new();
+    let metadata = &def.metadata;
+    writeln!(
+        out,
+        "{}{}",
+        metadata.name.as_deref().unwrap_or("(unnamed template)"),
+        metadata
+            .version
+            .as_ref()
+            .map(|o| format!(" {}", o))
+            .unwrap_or_default()
+    )?;
+    if let Some(description) = &metadata.description {
+        writeln!(out, "  {}", description)?;
+    }
+    if let Some(author) = &metadata.author {
+        writeln!(out, "  author: {}", author)?;
+    }
+    if let Some(min) = &metadata.min_generator_version {
+        writeln!(out, "  requires generator {} or newer", min)?;
+    }
+    writeln!(out, "  engine: {:?}", def.engine)?;
+
+    writeln!(out, "\nVariables:")?;
+    if def.variables.is_empty() {
+        writeln!(out, "  (none)")?;
+    }
+    for var in &def.variables {
+        write!(
+            out,
+            "  {}: {}",
+            var.name,
+            match var.kind {
+                VariableKind::String => "string",
+                VariableKind::List => "list",
+            }
+        )?;
+        match &var.default {
+            Some(default) => write!(out, " = {:?}", default)?,
+            None => write!(out, " (prompted)")?,
+        }
+        if let Some(validate) = &var.validate {
+            write!(out, ", must match `{}`", validate)?;
+        }
+        if !var.renamed_from.is_empty() {
+            write!(out, ", formerly {}", var.renamed_from.join(", "))?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "\nFile rules (first match wins):")?;
+    for rule in &def.files {
+        writeln!(out, "  {}", rule.describe())?;
+    }
+    if !def.verify.is_empty() {
+        writeln!(out, "\nVerify commands:")?;
+        for command in &def.verify {
+            writeln!(out, "  {}", command)?;
+        }
+    }
+    Ok(out)
+}
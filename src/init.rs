@@ -0,0 +1,65 @@
+use std::{
+    fs::{create_dir_all, read_dir, write},
+    path::Path,
+};
+
+use anyhow::*;
+
+const TEMPLATE_YML: &str = r#"name: {{name}}
+description: A new template
+version: 0.1.0
+
+# Values asked for when generating. Answers are available in every templated file
+# and in `rename`, as `{{ variable }}`.
+variables:
+  # A variable with a default is only asked for if not given elsewhere
+  - name: project_name
+    default: "{{ basename }}"
+  - name: author
+    default: Your Name
+  # A variable without a default is always prompted for, `validate` rejects answers not
+  # matching the regex
+  - name: license
+    validate: "^(MIT|Apache-2.0)$"
+
+# Rules matched in order against paths relative to the template, the first match wins.
+# Paths matching no rule are rendered as templates.
+files:
+  - sources: "^static/"
+    template: false
+  - sources: "^README\\.md$"
+    rename: "{{ project_name }}.md"
+# Globs of paths copied as they are whichever rule they match, like cookiecutter's
+# copy_without_render: ["*.png"]
+
+# Commands run in the generated project by `generator smoke`
+verify: []
+"#;
+
+const README: &str = "# {{ project_name }}\n\nBy {{ author }}, licensed under {{ license }}.\n";
+
+pub fn init(dir: impl AsRef<Path>) -> Result<()> {
+    let dir = dir.as_ref();
+    if dir.exists() {
+        ensure!(
+            read_dir(dir)?.next().is_none(),
+            "{} already exists and is not empty",
+            dir.display()
+        );
+    }
+    let name = dir
+        .file_name()
+        .and_then(|o| o.to_str())
+        .unwrap_or("template");
+    create_dir_all(dir.join("static"))?;
+    write(
+        dir.join("template.yml"),
+        TEMPLATE_YML.replacen("{{name}}", name, 1),
+    )?;
+    write(dir.join("README.md"), README)?;
+    write(
+        dir.join("static/logo.txt"),
+        "Copied as-is, {{ not rendered }}\n",
+    )?;
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::{fmt, fs::read, path::Path};
+
+use anyhow::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use ring::digest::{Context as Hasher, SHA256};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+use crate::{slash_path, Repo};
+
+static PINNED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(.+)@(sha256:[0-9a-fA-F]{64}|[0-9a-fA-F]{7,40})$").unwrap());
+
+// What a template is expected to contain, given after its URL as `url@<commit>` or
+// `url@sha256:<digest>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    Commit(String),
+    Sha256(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Template {0} failed verification: expected {1}, found {2}")]
+pub struct IntegrityError(String, Integrity, String);
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Integrity::Commit(commit) => write!(f, "commit {}", commit),
+            Integrity::Sha256(digest) => write!(f, "sha256:{}", digest),
+        }
+    }
+}
+
+impl Integrity {
+    // The template without its pin, and the pin if there is one
+    pub fn split(template: &str) -> (&str, Option<Integrity>) {
+        match PINNED.captures(template) {
+            Some(c) => {
+                let pin = c.get(2).unwrap().as_str().to_lowercase();
+                let integrity = match pin.strip_prefix("sha256:") {
+                    Some(digest) => Integrity::Sha256(digest.to_owned()),
+                    None => Integrity::Commit(pin),
+                };
+                (c.get(1).unwrap().as_str(), Some(integrity))
+            }
+            None => (template, None),
+        }
+    }
+
+    pub fn verify(&self, template: &Path) -> Result<()> {
+        let found = match self {
+            Integrity::Commit(commit) => {
+                let head = Repo::open(template)?.head()?;
+                if head.starts_with(commit.as_str()) {
+                    return Ok(());
+                }
+                format!("commit {}", head)
+            }
+            Integrity::Sha256(expected) => {
+                let found = digest(template)?;
+                if found == *expected {
+                    return Ok(());
+                }
+                format!("sha256:{}", found)
+            }
+        };
+        bail!(IntegrityError(
+            template.display().to_string(),
+            self.clone(),
+            found
+        ))
+    }
+}
+
+// Hex sha256 over every file outside `.git`, in path order, as each path, a nul, the length of
+// the contents and the contents
+pub fn digest(template: impl AsRef<Path>) -> Result<String> {
+    let template = template.as_ref();
+    let mut hasher = Hasher::new(&SHA256);
+    let walk = WalkDir::new(template)
+        .min_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|o| o.file_name() != ".git");
+    for entry in walk {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .strip_prefix(template)
+            .expect("Walked paths are children of the template");
+        let contents = read(entry.path())
+            .with_context(|| format!("Failed to read file {}", entry.path().display()))?;
+        hasher.update(
+            slash_path(path)
+                .context("Filename is not a string")?
+                .as_bytes(),
+        );
+        hasher.update(&[0]);
+        hasher.update(&(contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Ok(hex(hasher.finish().as_ref()))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|o| format!("{:02x}", o)).collect()
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    hex(ring::digest::digest(&SHA256, bytes).as_ref())
+}
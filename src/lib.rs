@@ -1,41 +1,491 @@
+mod adopt;
+mod archive;
+mod batch;
+mod builtins;
+mod cargo_generate;
+mod completions;
+mod config;
+mod cookiecutter;
+mod coverage;
+mod engine;
+mod fetch;
+mod git;
+mod graph;
+mod harness;
+mod hooks;
+mod index;
+mod info;
+mod init;
+mod integrity;
+mod license;
+mod lock;
+mod nested;
+mod permissions;
+mod plugin;
+mod preview;
+mod prompt;
+mod proxy;
+mod publish;
+mod questions;
+mod random;
+mod record;
+mod registry;
+mod resume;
+mod sandbox;
+mod saved;
+mod smoke;
+mod style;
+mod summary;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+mod usage;
+mod validate;
+mod warning;
+mod wizard;
+
 use anyhow::*;
 use directories::ProjectDirs;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use once_cell::sync::Lazy;
-use regex::Regex;
-use serde_yaml::{from_reader, Value};
+use rayon::prelude::*;
+use regex::{Captures, Regex, RegexBuilder};
+use serde_yaml::{Mapping, Value};
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
     ffi::OsStr,
-    fs::{read, read_to_string, File},
-    io::{BufRead, Read, Write},
-    path::Path,
+    fs::{create_dir_all, read, rename, File},
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
-use tera::Tera;
 use walkdir::WalkDir;
 
+pub use adopt::{adopt, AdoptReport, PROVENANCE_FILE};
+pub use batch::{generate_batch, BatchEntry, Manifest};
+pub use completions::{completions, template_names, Shell};
+pub use config::Config;
+pub use coverage::{Coverage, ProfileCoverage, RuleCoverage, VariableCoverage};
+use engine::Templates;
+pub use engine::{Delimiters, Engine, HandlebarsEngine, RenderEngine, RenderError, TeraEngine};
+pub use fetch::{
+    cached, cached_templates, fetch, fetch_pinned, resolve, CachedTemplate, Fetched, TemplateSource,
+};
+pub use git::{NetworkError, Repo};
+pub use graph::{graph, GraphFormat};
+pub use harness::{test_template, CaseResult, TestReport};
+pub use hooks::HookError;
+pub use index::{fetch_index, IndexEntry};
+pub use info::info;
+pub use init::init;
+pub use integrity::{digest, Integrity, IntegrityError};
+pub use license::{license_ids, license_text};
+pub use lock::{Lockfile, LOCKFILE};
+pub use permissions::Permissions;
+use plugin::Plugin;
+use prompt::prompt;
+pub use prompt::{ask, pick, PromptError};
+pub use publish::{publish, PublishOptions, Published};
+use record::Record;
+pub use record::{undo, UndoReport};
+pub use registry::{CommandDefaults, Registry};
+pub use resume::resume;
+use resume::Progress;
+pub use sandbox::Sandbox;
+pub use smoke::{smoke, VerifyError, DEFAULT_TIMEOUT};
+pub use style::{Painted, Style, NO_COLOR_ENV};
+pub use summary::Summary;
+use usage::Usage;
+pub use validate::{validate, variable_warnings, Issue};
+use warning::Warnings;
+pub use warning::{Warning, WarningKind};
+pub use wizard::wizard;
+
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)\{\{.*?\}\}|\{%.*?%\}").unwrap());
+
 pub static DIRECTORIES: Lazy<ProjectDirs> =
     Lazy::new(|| directories::ProjectDirs::from("", "bluepython508", "generator").unwrap());
 
+thread_local! {
+    // Config and cache directories replacing `DIRECTORIES` on this thread, for sandboxed tests
+    static DIRECTORY_OVERRIDE: RefCell<Option<(PathBuf, PathBuf)>> = const { RefCell::new(None) };
+}
+
+// Replace the platform's directories, for CI and hermetic builds where they may not be writable
+pub const CONFIG_DIR_ENV: &str = "GENERATOR_CONFIG_DIR";
+pub const CACHE_DIR_ENV: &str = "GENERATOR_CACHE_DIR";
+// Extra `key=value` git config for clones and pulls, one per line
+pub const GIT_CONFIG_ENV: &str = "GENERATOR_GIT_CONFIG";
+// Set by `--quiet`, to print nothing but errors and never prompt
+pub const QUIET_ENV: &str = "GENERATOR_QUIET";
+
+pub fn quiet() -> bool {
+    std::env::var_os(QUIET_ENV).is_some_and(|o| !o.is_empty())
+}
+
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|o| !o.is_empty())
+        .map(PathBuf::from)
+}
+
+pub fn config_dir() -> PathBuf {
+    DIRECTORY_OVERRIDE.with(|o| match &*o.borrow() {
+        Some((config, _)) => config.clone(),
+        None => env_dir(CONFIG_DIR_ENV).unwrap_or_else(|| DIRECTORIES.config_dir().to_owned()),
+    })
+}
+
+pub fn cache_dir() -> PathBuf {
+    DIRECTORY_OVERRIDE.with(|o| match &*o.borrow() {
+        Some((_, cache)) => cache.clone(),
+        None => env_dir(CACHE_DIR_ENV).unwrap_or_else(|| DIRECTORIES.cache_dir().to_owned()),
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub version: Option<String>,
+    pub min_generator_version: Option<String>,
+}
+
+impl Metadata {
+    fn check_version(&self) -> Result<()> {
+        if let Some(min) = &self.min_generator_version {
+            let min = semver::Version::parse(min)
+                .with_context(|| format!("Invalid `min_generator_version` {}", min))?;
+            let current = semver::Version::parse(env!("CARGO_PKG_VERSION"))
+                .expect("Crate version is valid semver");
+            ensure!(
+                current >= min,
+                "Template requires generator {} or newer, but this is {}",
+                min,
+                current
+            );
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
+struct Layer {
+    template: PathBuf,
+    root: PathBuf,
+    // Which of several applied templates this layer belongs to
+    group: usize,
+}
+
+#[derive(Debug, Clone, Default)]
 struct TemplateDef {
+    metadata: Metadata,
     files: Vec<FileDef>,
     variables: Vec<VariableDef>,
+    template_paths: bool,
+    root: Option<PathBuf>,
+    namespace: Option<String>,
+    verify: Vec<String>,
+    hooks: Vec<hooks::Hook>,
+    allow: Vec<WarningKind>,
+    aliases: Vec<(String, String)>,
+    engine: Engine,
+    tests: Option<String>,
+    test_cases: Vec<String>,
+    // Paths of test cases in this template and its bases, which are never generated
+    case_exclusions: Vec<Regex>,
+    extends: Option<String>,
+    // Templates this one extends or is applied over, the most basic first
+    bases: Vec<Layer>,
+    // Checkouts of bases fetched for `extends`, which are removed once nothing uses them
+    fetched: Vec<Arc<Fetched>>,
+    // WASM modules giving filters, functions and validation, by their paths
+    plugins: Vec<PathBuf>,
+    // The plugins, and what Tera templates are rendered with, set up for each generation by
+    // `for_generation`
+    loaded: Vec<Arc<Plugin>>,
+    templates: Arc<Templates>,
+    group: usize,
+    vcs: Option<Vcs>,
+    respect_gitignore: bool,
+    eol: Option<Eol>,
+    // Copy the template's `.git` directory, and so its history, rather than leaving it out
+    keep_vcs: bool,
+    delimiters: Option<Delimiters>,
+    // Render every file before writing any
+    strict: bool,
+    // Generate the template's one file as the destination itself, rather than into it
+    single_file: bool,
+    // Shown once generation finishes, like next steps to take in the project
+    post_message: Option<String>,
+    // Paths copied as they are whichever rule they match, from cookiecutter-style globs
+    copy_without_render: Vec<Regex>,
+    // Treat `.gitkeep` and `.keep` files as marking their directory, which is generated without
+    // them
+    directory_markers: bool,
 }
 
 impl TemplateDef {
-    fn find_for_str(&self, s: &str) -> Option<&FileDef> {
-        self.files.iter().find(|d| {
-            d.sources.iter().any(|o| {
-                let e = o.is_match(s);
-                e
-            })
-        })
+    fn render(&self, source: &str, context: &tera::Context) -> Result<String> {
+        self.render_with(source, context, self.delimiters.as_ref())
+    }
+
+    fn render_with(
+        &self,
+        source: &str,
+        context: &tera::Context,
+        delimiters: Option<&Delimiters>,
+    ) -> Result<String> {
+        let source = self.prepare(source, delimiters);
+        match self.engine {
+            Engine::Tera => self.templates.render(&source, context),
+            engine => engine.get().render(&source, context),
+        }
+    }
+
+    // The source in Tera's own delimiters with aliases replaced, as the engine sees it
+    fn prepare<'a>(&self, source: &'a str, delimiters: Option<&Delimiters>) -> Cow<'a, str> {
+        let source = match delimiters {
+            Some(delimiters) => Cow::Owned(delimiters.translate(source)),
+            None => Cow::Borrowed(source),
+        };
+        if self.aliases.is_empty() {
+            return source;
+        }
+        let replaced = TAG.replace_all(&source, |c: &Captures| {
+            self.aliases
+                .iter()
+                .fold(c[0].to_owned(), |tag, (from, to)| tag.replace(from, to))
+        });
+        Cow::Owned(replaced.into_owned())
+    }
+
+    // Whether the file at `path`, matched by `rule`, is rendered rather than copied as it is
+    fn renders(&self, rule: &FileDef, path: &Path) -> bool {
+        rule.template
+            && !slash_path(path)
+                .is_some_and(|path| self.copy_without_render.iter().any(|o| o.is_match(&path)))
+    }
+
+    fn tests_dir(&self) -> &str {
+        self.tests.as_deref().unwrap_or("tests")
+    }
+
+    fn source_root(&self, template: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) => template.join(root),
+            None => template.to_owned(),
+        }
+    }
+
+    // Every template files are generated from, bases first so later layers override them
+    fn layers(&self, template: &Path) -> Vec<Layer> {
+        let mut layers = self.bases.clone();
+        layers.push(Layer {
+            template: template.to_owned(),
+            root: self.source_root(template),
+            group: self.group,
+        });
+        layers
+    }
+
+    // `$1` style references in `rename` are replaced with groups of the matching source, which
+    // are also given to the template as `captures`, by index or by name
+    fn render_rename(
+        &self,
+        rule: &FileDef,
+        rename: &str,
+        path: &Path,
+        context: &tera::Context,
+    ) -> Result<String> {
+        let path = slash_path(path).unwrap_or_default();
+        let captures = match rule.sources.iter().find_map(|o| o.captures(&path)) {
+            Some(captures) => captures,
+            None => return self.render(rename, context),
+        };
+        let mut groups = BTreeMap::new();
+        for (i, group) in captures.iter().enumerate() {
+            groups.insert(i.to_string(), group.map_or("", |o| o.as_str()));
+        }
+        for name in rule
+            .sources
+            .iter()
+            .flat_map(|o| o.capture_names().flatten())
+        {
+            if let Some(group) = captures.name(name) {
+                groups.insert(name.to_owned(), group.as_str());
+            }
+        }
+        let mut context = context.clone();
+        context.insert("captures", &groups);
+        let mut expanded = String::new();
+        captures.expand(rename, &mut expanded);
+        self.render(&expanded, &context)
+    }
+
+    // Whether text has variable tags, in the template's own delimiters
+    fn has_variables(&self, text: &str) -> bool {
+        match &self.delimiters {
+            Some(delimiters) => text.contains(&delimiters.variable.0),
+            None => text.contains("{{"),
+        }
+    }
+
+    fn keeping_vcs(&self) -> TemplateDef {
+        let mut def = self.clone();
+        for rule in def.files.iter_mut().filter(|o| o.origin == RuleOrigin::Vcs) {
+            rule.include = true;
+            rule.template = false;
+        }
+        def
+    }
+
+    // A copy for a single generation, with its plugins loaded and a Tera of its own. Every
+    // layer's rendered files are registered with it by their paths so that they can include and
+    // extend each other, partials left out of the project included
+    fn for_generation(&self, layers: &[Layer]) -> Result<TemplateDef> {
+        let mut def = self.clone();
+        def.loaded = self
+            .plugins
+            .iter()
+            .map(|o| Plugin::load(o).map(Arc::new))
+            .collect::<Result<_>>()?;
+        if self.engine != Engine::Tera {
+            return Ok(def);
+        }
+        // Later layers' files replace earlier ones' of the same path, as when generating
+        let mut files = BTreeMap::new();
+        for layer in layers {
+            let walk = WalkDir::new(&layer.root)
+                .min_depth(1)
+                .into_iter()
+                .filter_entry(|o| o.file_name() != ".git");
+            for entry in walk.filter_map(|o| o.ok()) {
+                let path = entry
+                    .path()
+                    .strip_prefix(&layer.root)
+                    .expect("Walked paths are children of the root");
+                let index = match self.position_for(path) {
+                    Some(index)
+                        if self.files[index].explicit()
+                            || self.files[index].origin == RuleOrigin::CatchAll =>
+                    {
+                        index
+                    }
+                    // The definition and the like aren't templates, whatever their rule says
+                    _ => continue,
+                };
+                let rule = &self.files[index];
+                if !entry.file_type().is_file() || !self.renders(rule, path) {
+                    continue;
+                }
+                // Files that aren't UTF-8 are copied rather than rendered
+                if let std::result::Result::Ok(source) = std::fs::read_to_string(entry.path()) {
+                    let delimiters = rule.delimiters.as_ref().or(self.delimiters.as_ref());
+                    files.insert(
+                        slash_path(path).context("Filename is not a string")?,
+                        self.prepare(&source, delimiters).into_owned(),
+                    );
+                }
+            }
+        }
+        def.templates =
+            Arc::new(Templates::new(files, &def.loaded).context("Failed to load templates")?);
+        Ok(def)
+    }
+
+    fn position_for(&self, path: &Path) -> Option<usize> {
+        let path = slash_path(path)?;
+        self.files
+            .iter()
+            .position(|d| d.sources.iter().any(|o| o.is_match(&path)))
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct VariableDef {
     name: String,
+    kind: VariableKind,
     default: Option<String>,
+    renamed_from: Vec<String>,
+    validate: Option<Regex>,
+    // Heading the variable is prompted for under, along with the rest of its section
+    section: Option<String>,
+    // A command whose output is the default, like `git config user.name`, over `default`
+    default_from_command: Option<String>,
+    input: Input,
+    // Declared on the template's behalf, like cargo-generate's `crate_name`, so going unused is
+    // no mistake of its author's
+    implicit: bool,
+    // Shown to whoever is asked for the variable
+    description: Option<String>,
+    // The only values allowed, offered to pick from when asking
+    choices: Vec<String>,
+}
+
+// How a variable without a value is asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Input {
+    #[default]
+    Line,
+    // Written in the user's editor, for multi-line values
+    Editor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum VariableKind {
+    #[default]
+    String,
+    // Entered as comma separated text, and given to templates as an array
+    List,
+}
+
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+impl VariableDef {
+    // Turns text given for a list variable into the list
+    fn normalise(&self, value: tera::Value) -> tera::Value {
+        match (self.kind, value) {
+            (VariableKind::List, tera::Value::String(s)) => split_list(&s).into(),
+            (_, value) => value,
+        }
+    }
+
+    fn check(&self, value: &tera::Value) -> Result<()> {
+        let items = match self.normalise(value.clone()) {
+            tera::Value::Array(items) => items,
+            value => vec![value],
+        };
+        for value in items.iter().filter_map(|o| o.as_str()) {
+            if let Some(validate) = &self.validate {
+                ensure!(
+                    validate.is_match(value),
+                    "Value {:?} for variable {} does not match `{}`",
+                    value,
+                    self.name,
+                    validate
+                );
+            }
+            ensure!(
+                self.choices.is_empty() || self.choices.iter().any(|o| o == value),
+                "Value {:?} for variable {} is not one of {}",
+                value,
+                self.name,
+                self.choices.join(", ")
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -44,33 +494,358 @@ struct FileDef {
     template: bool,
     include: bool,
     rename: Option<String>,
+    eol: Option<Eol>,
+    // A template that leaves out the path, and anything in it, unless it renders empty or `false`
+    skip_if: Option<String>,
+    delimiters: Option<Delimiters>,
+    permissions: Permissions,
+    origin: RuleOrigin,
+}
+
+// Where a file rule comes from, as rules the template doesn't write are added around its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleOrigin {
+    Template,
+    // Leaving out the definition and other files generator reads
+    Builtin,
+    // Leaving out `.git`, unless the template's history is kept
+    Vcs,
+    // For paths no other rule matches
+    CatchAll,
+    // Leaving out the template's test cases
+    CaseExclusion,
 }
-fn parse_definition(def: impl Read) -> Result<TemplateDef> {
-    let mut default_files_entry = vec![
+
+impl FileDef {
+    // Written by the template author, rather than added by generator
+    fn explicit(&self) -> bool {
+        self.origin == RuleOrigin::Template
+    }
+
+    fn describe(&self) -> String {
+        let mut label = self
+            .sources
+            .iter()
+            .map(|o| o.as_str())
+            .collect::<Vec<_>>()
+            .join(" | ");
+        if !self.include {
+            label.push_str(" (excluded)");
+        } else if !self.template {
+            label.push_str(" (copied)");
+        }
+        if let Some(rename) = &self.rename {
+            label.push_str(" -> ");
+            label.push_str(rename);
+        }
+        if let Some(mode) = self.permissions.mode {
+            label.push_str(&format!(" (mode {:o})", mode));
+        }
+        label
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Directory,
+    Templated,
+    Copied,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedEntry {
+    pub source: PathBuf,
+    // The template, or base template, the source is in
+    pub root: PathBuf,
+    pub destination: PathBuf,
+    pub kind: EntryKind,
+    pub eol: Eol,
+    pub delimiters: Option<Delimiters>,
+    pub permissions: Permissions,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub coverage: Coverage,
+    pub warnings: Vec<Warning>,
+    // The version control the template asks for, if it does
+    pub vcs: Option<Vcs>,
+    pub summary: Summary,
+}
+
+// A rule's source pattern, optionally ignoring case or only matching from the start of the path,
+// so that `src/` doesn't also match `docs/src/`
+fn source_regex(pattern: &str, ignore_case: bool, anchored: bool) -> Result<Regex> {
+    let pattern = if anchored {
+        format!("^(?:{})", pattern)
+    } else {
+        pattern.to_owned()
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .context("Expected a valid regex")
+}
+
+fn default_files() -> Vec<FileDef> {
+    vec![
         FileDef {
-            sources: vec![Regex::new("^template.yml$").unwrap()],
+            sources: vec![Regex::new("^template\\.(yml|toml|json)$").unwrap()],
             template: true,
             include: false,
             rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Builtin,
         },
         FileDef {
-            sources: vec![Regex::new("^.git/").unwrap(), Regex::new("^.git$").unwrap()],
+            sources: vec![Regex::new("^defaults\\.yml$").unwrap()],
+            template: false,
+            include: false,
+            rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Builtin,
+        },
+        FileDef {
+            sources: vec![Regex::new("^\\.generatorignore$").unwrap()],
+            template: false,
+            include: false,
+            rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Builtin,
+        },
+        FileDef {
+            // Including those of submodules, and the list of them
+            sources: vec![Regex::new("(^|/)\\.git(/|$)|^\\.gitmodules$").unwrap()],
             include: false,
             template: true,
             rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::Vcs,
         },
         FileDef {
             sources: vec![Regex::new(".*").unwrap()],
             include: true,
             template: true,
             rename: None,
+            eol: None,
+            skip_if: None,
+            delimiters: None,
+            permissions: Permissions::default(),
+            origin: RuleOrigin::CatchAll,
+        },
+    ]
+}
+
+fn get_string(value: &Value, key: &str) -> Result<Option<String>> {
+    value
+        .get(key)
+        .map(|o| {
+            o.as_str()
+                .map(str::to_owned)
+                .with_context(|| format!("Expected `{}` to be a string", key))
+        })
+        .transpose()
+}
+
+fn get_strings(value: &Value, key: &str) -> Result<Vec<String>> {
+    match value.get(key) {
+        None => Ok(vec![]),
+        Some(Value::String(s)) => Ok(vec![s.clone()]),
+        Some(Value::Sequence(s)) => s
+            .iter()
+            .map(|o| o.as_str().map(str::to_owned))
+            .collect::<Option<_>>()
+            .with_context(|| format!("Expected `{}` to be a sequence of strings", key)),
+        Some(v) => bail!(format!(
+            "Unexpected value {:?} for `{}`, expected string or sequence of strings",
+            v, key
+        )),
+    }
+}
+
+fn parse_default(value: &Value) -> Result<Option<String>> {
+    Ok(match value {
+        Value::Null => None,
+        Value::Sequence(s) => Some(
+            s.iter()
+                .map(|o| o.as_str().context("Expected list defaults to be strings"))
+                .collect::<Result<Vec<_>>>()?
+                .join(", "),
+        ),
+        o => Some(
+            o.as_str()
+                .context("Expected `default` to be a string")?
+                .to_owned(),
+        ),
+    })
+}
+
+// A mapping `default` declares a variable for each of its leaves, named by their dotted path,
+// like `author.name`, which a null default leaves to be prompted for
+fn parse_fields(var: &VariableDef, fields: &Mapping) -> Result<Vec<VariableDef>> {
+    let mut leaves = vec![];
+    for (key, value) in fields {
+        let leaf = VariableDef {
+            name: format!(
+                "{}.{}",
+                var.name,
+                key.as_str()
+                    .context("Expected `default` keys to be strings")?
+            ),
+            renamed_from: vec![],
+            ..var.clone()
+        };
+        match value {
+            Value::Mapping(fields) => leaves.append(&mut parse_fields(&leaf, fields)?),
+            value => leaves.push(VariableDef {
+                default: parse_default(value)?,
+                ..leaf
+            }),
+        }
+    }
+    Ok(leaves)
+}
+
+fn parse_variable(v: &Value) -> Result<Vec<VariableDef>> {
+    if let Some(section) = get_string(v, "section")? {
+        return Ok(parse_variables(v.get("variables"))?
+            .into_iter()
+            .map(|o| VariableDef {
+                section: Some(section.clone()),
+                ..o
+            })
+            .collect());
+    }
+    let var = match v {
+        Value::String(s) => VariableDef {
+            name: s.to_owned(),
+            ..VariableDef::default()
+        },
+        Value::Mapping(_) => VariableDef {
+            name: v
+                .get("name")
+                .context("Expected name for variable")?
+                .as_str()
+                .context("Expected variable name to be string")?
+                .to_string(),
+            kind: match get_string(v, "type")?.as_deref() {
+                None | Some("string") => VariableKind::String,
+                Some("list") => VariableKind::List,
+                Some(other) => bail!(
+                    "Unknown variable type {}, expected `string` or `list`",
+                    other
+                ),
+            },
+            default: match v.get("default") {
+                None | Some(Value::Mapping(_)) => None,
+                Some(o) => parse_default(o)?,
+            },
+            renamed_from: get_strings(v, "renamed_from")?,
+            validate: get_string(v, "validate")?
+                .map(|o| Regex::new(&o).context("Expected `validate` to be a valid regex"))
+                .transpose()?,
+            section: None,
+            default_from_command: get_string(v, "default_from_command")?,
+            input: match get_string(v, "input")?.as_deref() {
+                None | Some("line") => Input::Line,
+                Some("editor") => Input::Editor,
+                Some(other) => bail!("Unknown input {}, expected `line` or `editor`", other),
+            },
+            implicit: false,
+            description: get_string(v, "description")?,
+            choices: get_strings(v, "choices")?,
         },
-    ];
-    let value: Value = from_reader(def).context("Invalid yaml in template definition")?;
+        v => bail!(format!(
+            "Unexpected value {:?}, expected string or mapping",
+            v
+        )),
+    };
+    match v.get("default") {
+        Some(Value::Mapping(fields)) => parse_fields(&var, fields),
+        _ => Ok(vec![var]),
+    }
+}
+
+// Variables of a section are prompted for together, where the section first appears
+fn group_sections(variables: &mut [VariableDef]) {
+    let mut sections = vec![];
+    for var in variables.iter() {
+        if !sections.contains(&var.section) {
+            sections.push(var.section.clone());
+        }
+    }
+    variables.sort_by_key(|o| sections.iter().position(|s| *s == o.section));
+}
+
+fn parse_variables(value: Option<&Value>) -> Result<Vec<VariableDef>> {
+    value
+        .unwrap_or(&Value::Sequence(vec![]))
+        .as_sequence()
+        .context("Expected `variables` to be a sequence")?
+        .iter()
+        .map(parse_variable)
+        .collect::<Result<Vec<_>>>()
+        .map(|o| o.into_iter().flatten().collect())
+}
+
+// Definition files in the order they're looked for, all parsed into the same `TemplateDef`
+const DEFINITIONS: [&str; 3] = ["template.yml", "template.toml", "template.json"];
+
+fn definition_path(template: &Path) -> Option<PathBuf> {
+    DEFINITIONS
+        .iter()
+        .map(|o| template.join(o))
+        .find(|o| o.exists())
+}
+
+// TOML and JSON are read into the same structure as yaml, so they're parsed the same way
+fn read_definition(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    match path.extension().and_then(OsStr::to_str) {
+        Some("toml") => serde_yaml::to_value(
+            toml::from_str::<toml::Value>(&text).context("Invalid toml in template definition")?,
+        )
+        .context("Invalid toml in template definition"),
+        Some("json") => serde_yaml::to_value(
+            serde_json::from_str::<serde_json::Value>(&text)
+                .context("Invalid json in template definition")?,
+        )
+        .context("Invalid json in template definition"),
+        _ => {
+            let value =
+                serde_yaml::from_str(&text).context("Invalid yaml in template definition")?;
+            permissions::check_yaml_modes(&text)?;
+            Ok(value)
+        }
+    }
+}
+
+fn parse_definition(value: Value, template: &Path) -> Result<TemplateDef> {
     ensure!(
         value.is_mapping(),
         "Expected template definition to be mapping at top level"
     );
+    let metadata = Metadata {
+        name: get_string(&value, "name")?,
+        description: get_string(&value, "description")?,
+        author: get_string(&value, "author")?,
+        version: get_string(&value, "version")?,
+        min_generator_version: get_string(&value, "min_generator_version")?,
+    };
+    metadata.check_version()?;
     let files = value
         .get("files")
         .map(|o| o.as_sequence().context("Expected `files` to be a sequence"))
@@ -80,45 +855,74 @@ fn parse_definition(def: impl Read) -> Result<TemplateDef> {
             .iter()
             .map(|o| match o {
                 Value::String(s) => Ok(FileDef {
-                    sources: vec![Regex::new(s).context("Expected valid regex")?],
+                    sources: vec![source_regex(s, false, false)?],
                     template: true,
                     include: true,
                     rename: None,
+                    eol: None,
+                    skip_if: None,
+                    delimiters: None,
+                    permissions: Permissions::default(),
+                    origin: RuleOrigin::Template,
                 }),
-                Value::Mapping(m) => Ok(FileDef {
-                    sources: match m.get(&Value::String("sources".to_owned())) {
-                        Some(Value::String(s)) => {
-                            vec![Regex::new(s).context("Expected valid regex")?]
-                        }
-                        Some(Value::Sequence(s)) => s
-                            .iter()
+                Value::Mapping(m) => {
+                    let flag = |key: &str| {
+                        m.get(&Value::String(key.to_owned()))
                             .map(|o| {
-                                o.as_str()
-                                    .map(|o| Regex::new(o).context("Expected a valid regex"))
+                                o.as_bool()
+                                    .with_context(|| format!("Expected `{}` to be a boolean", key))
                             })
-                            .collect::<Option<Result<Vec<_>>>>()
-                            .context("Expected a sequence of strings")??,
-                        v => bail!(format!(
-                            "Unexpected value {:?}, expected string or sequence of strings",
-                            v
-                        )),
-                    },
-                    template: m
-                        .get(&Value::String("template".to_owned()))
-                        .map(|o| o.as_bool().context("Expected `template` to be a boolean"))
-                        .transpose()?
-                        .unwrap_or(true),
-                    include: m
-                        .get(&Value::String("include".to_owned()))
-                        .map(|o| o.as_bool().context("Expected `include` to be a boolean"))
-                        .transpose()?
-                        .unwrap_or(true),
-                    rename: m
-                        .get(&Value::String("rename".to_owned()))
-                        .map(|o| o.as_str().context("Expected `rename` to be a string"))
-                        .transpose()?
-                        .map(|o| o.to_owned()),
-                }),
+                            .transpose()
+                            .map(Option::unwrap_or_default)
+                    };
+                    let ignore_case = flag("ignore_case")?;
+                    let anchored = flag("anchored")?;
+                    let source = |s: &str| source_regex(s, ignore_case, anchored);
+                    Ok(FileDef {
+                        sources: match m.get(&Value::String("sources".to_owned())) {
+                            Some(Value::String(s)) => vec![source(s)?],
+                            Some(Value::Sequence(s)) => s
+                                .iter()
+                                .map(|o| o.as_str().map(source))
+                                .collect::<Option<Result<Vec<_>>>>()
+                                .context("Expected a sequence of strings")??,
+                            v => bail!(format!(
+                                "Unexpected value {:?}, expected string or sequence of strings",
+                                v
+                            )),
+                        },
+                        template: m
+                            .get(&Value::String("template".to_owned()))
+                            .map(|o| o.as_bool().context("Expected `template` to be a boolean"))
+                            .transpose()?
+                            .unwrap_or(true),
+                        include: m
+                            .get(&Value::String("include".to_owned()))
+                            .map(|o| o.as_bool().context("Expected `include` to be a boolean"))
+                            .transpose()?
+                            .unwrap_or(true),
+                        rename: m
+                            .get(&Value::String("rename".to_owned()))
+                            .map(|o| o.as_str().context("Expected `rename` to be a string"))
+                            .transpose()?
+                            .map(|o| o.to_owned()),
+                        eol: m
+                            .get(&Value::String("eol".to_owned()))
+                            .map(|o| o.as_str().context("Expected `eol` to be a string")?.parse())
+                            .transpose()?,
+                        skip_if: m
+                            .get(&Value::String("skip_if".to_owned()))
+                            .map(|o| o.as_str().context("Expected `skip_if` to be a string"))
+                            .transpose()?
+                            .map(|o| o.to_owned()),
+                        delimiters: m
+                            .get(&Value::String("delimiters".to_owned()))
+                            .map(Delimiters::parse)
+                            .transpose()?,
+                        permissions: Permissions::parse(m)?,
+                        origin: RuleOrigin::Template,
+                    })
+                }
                 v => bail!(format!(
                     "Unexpected value {:?}, expected string or mapping",
                     v
@@ -129,128 +933,1639 @@ fn parse_definition(def: impl Read) -> Result<TemplateDef> {
         vec![]
     };
 
-    let variables = value
-        .get("variables")
+    let mut variables: Vec<VariableDef> = vec![];
+    let uses = get_strings(&value, "use")?;
+    if !uses.is_empty() {
+        let registry = Registry::load()?;
+        for set in uses {
+            variables.extend(parse_variables(Some(
+                registry
+                    .variable_set(&set)
+                    .with_context(|| format!("No shared variable set named {}", set))?,
+            ))?);
+        }
+    }
+    // Fragments from `variables_from` override shared sets, and are overridden by the template
+    let shared = questions::load(template, &get_strings(&value, "variables_from")?)?;
+    for var in shared
+        .into_iter()
+        .chain(parse_variables(value.get("variables"))?)
+    {
+        match variables.iter_mut().find(|o| o.name == var.name) {
+            Some(existing) => *existing = var,
+            None => variables.push(var),
+        }
+    }
+    group_sections(&mut variables);
+    let template_paths = value
+        .get("template_paths")
+        .map(|o| {
+            o.as_bool()
+                .context("Expected `template_paths` to be a boolean")
+        })
+        .transpose()?
+        .unwrap_or(false);
+    let respect_gitignore = value
+        .get("respect_gitignore")
+        .map(|o| {
+            o.as_bool()
+                .context("Expected `respect_gitignore` to be a boolean")
+        })
+        .transpose()?
+        .unwrap_or(false);
+    let verify = value
+        .get("verify")
         .unwrap_or(&Value::Sequence(vec![]))
         .as_sequence()
-        .context("Expected `variables` to be a sequence")?
-        .into_iter()
-        .map(|v| match v {
-            Value::String(s) => Ok(VariableDef {
-                name: s.to_owned(),
-                default: None,
-            }),
-            Value::Mapping(m) => Ok(VariableDef {
-                name: m
-                    .get(&Value::String("name".to_owned()))
-                    .context("Expected name for variable")?
-                    .as_str()
-                    .context("Expected variable name to be string")?
-                    .to_string(),
-                default: m
-                    .get(&Value::String("default".to_owned()))
-                    .map(|v| v.as_str().unwrap().to_owned()),
-            }),
-            v => bail!(format!(
-                "Unexpected value {:?}, expected string or mapping",
-                v
-            )),
+        .context("Expected `verify` to be a sequence")?
+        .iter()
+        .map(|o| {
+            o.as_str()
+                .map(str::to_owned)
+                .context("Expected `verify` commands to be strings")
         })
         .collect::<Result<_>>()?;
-    files.append(&mut default_files_entry);
-    Ok(TemplateDef { files, variables })
-}
-
-fn prompt(context: &mut tera::Context, variable: &str) {
-    print!("Variable {} missing - value? ", variable);
-    std::io::stdout().flush().unwrap();
-    context.insert(
-        variable,
-        &std::io::stdin().lock().lines().next().unwrap().unwrap(),
+    let allow = value
+        .get("allow")
+        .unwrap_or(&Value::Sequence(vec![]))
+        .as_sequence()
+        .context("Expected `allow` to be a sequence")?
+        .iter()
+        .map(|o| {
+            o.as_str()
+                .context("Expected `allow` entries to be strings")?
+                .parse()
+        })
+        .collect::<Result<_>>()?;
+    let engine = value
+        .get("engine")
+        .map(|o| {
+            o.as_str()
+                .context("Expected `engine` to be a string")?
+                .parse()
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let vcs = get_string(&value, "vcs")?.map(|o| o.parse()).transpose()?;
+    let strict = value
+        .get("strict")
+        .map(|o| o.as_bool().context("Expected `strict` to be a boolean"))
+        .transpose()?
+        .unwrap_or(false);
+    let delimiters = value.get("delimiters").map(Delimiters::parse).transpose()?;
+    ensure!(
+        engine == Engine::Tera
+            || (delimiters.is_none() && files.iter().all(|o| o.delimiters.is_none())),
+        "Custom `delimiters` need the tera engine"
     );
-}
-pub fn generate(template: impl AsRef<Path>, destination: impl AsRef<Path>) -> Result<()> {
-    let destination = destination.as_ref();
-    let template = template.as_ref();
-    let def = parse_definition(
-        File::open(template.join("template.yml")).context("Template definition not found")?,
-    )?;
-    std::fs::create_dir_all(destination)?;
-    let mut context = tera::Context::from_serialize(
-        from_reader::<_, Value>(File::open(DIRECTORIES.config_dir().join("defaults.yml"))?)
-            .context("While parsing default variables")?,
-    )?;
-    if let Some(s) = destination.file_name().and_then(OsStr::to_str) {
-        context.insert("basename", s)
-    }
-    for var in &def.variables {
-        if context.contains_key(&var.name) {
-            continue;
-        }
-        if let Some(default) = &var.default {
-            context.insert(&var.name, default)
-        } else {
-            prompt(&mut context, &var.name)
-        }
+    let keep_vcs = value
+        .get("keep_vcs")
+        .map(|o| o.as_bool().context("Expected `keep_vcs` to be a boolean"))
+        .transpose()?
+        .unwrap_or(false);
+    let eol = get_string(&value, "eol")?.map(|o| o.parse()).transpose()?;
+    let single_file = value
+        .get("single_file")
+        .map(|o| {
+            o.as_bool()
+                .context("Expected `single_file` to be a boolean")
+        })
+        .transpose()?
+        .unwrap_or(false);
+    let plugins = get_strings(&value, "plugins")?
+        .iter()
+        .map(|o| template.join(o))
+        .collect::<Vec<_>>();
+    // Plugins' filters and functions are registered with Tera
+    ensure!(
+        engine == Engine::Tera || plugins.is_empty(),
+        "`plugins` need the tera engine"
+    );
+    let hooks = hooks::parse(value.get("hooks"))?;
+    for name in hooks.iter().flat_map(|o| &o.provides) {
+        ensure!(
+            !variables.iter().any(|o| o.name == *name),
+            "{} is provided by a hook and can't also be a variable",
+            name
+        );
     }
-    for path in WalkDir::new(&template)
+    files.append(&mut default_files());
+    Ok(TemplateDef {
+        metadata,
+        files,
+        variables,
+        template_paths,
+        verify,
+        hooks,
+        plugins,
+        allow,
+        engine,
+        tests: get_string(&value, "tests")?,
+        vcs,
+        respect_gitignore,
+        eol,
+        keep_vcs,
+        delimiters,
+        strict,
+        single_file,
+        post_message: get_string(&value, "post_message")?,
+        directory_markers: value
+            .get("directory_markers")
+            .map(|o| {
+                o.as_bool()
+                    .context("Expected `directory_markers` to be a boolean")
+            })
+            .transpose()?
+            .unwrap_or(false),
+        copy_without_render: get_strings(&value, "copy_without_render")?
+            .iter()
+            .map(|o| cookiecutter::fnmatch_to_regex(o))
+            .collect::<Result<_>>()?,
+        extends: get_string(&value, "extends")?,
+        ..TemplateDef::default()
+    })
+}
+
+fn walk<'a>(
+    def: &'a TemplateDef,
+    root: &'a Path,
+    ignore: &'a Gitignore,
+    coverage: &'a mut Coverage,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    WalkDir::new(root)
         .min_depth(1)
         .into_iter()
-        .filter_entry(|e| {
-            e.path()
-                .strip_prefix(&template)
-                .expect("Impossible as path guaranteed to be child of template")
-                .to_str()
-                .and_then(|o| def.find_for_str(o))
-                .map(|o| o.include)
-                .unwrap_or_default()
+        .filter_entry(move |e| {
+            let path = e
+                .path()
+                .strip_prefix(root)
+                .expect("Impossible as path guaranteed to be child of template");
+            !ignore
+                .matched_path_or_any_parents(e.path(), e.file_type().is_dir())
+                .is_ignore()
+                && def
+                    .position_for(path)
+                    .map(|i| {
+                        coverage.hit_rule(i);
+                        def.files[i].include
+                    })
+                    .unwrap_or_default()
         })
         .filter_map(|f| f.ok())
-        .map(|o| {
+        .map(move |o| {
             o.path()
-                .strip_prefix(&template)
+                .strip_prefix(root)
                 .expect("Impossible as path guaranteed to be child of template")
                 .to_owned()
         })
+}
+
+fn load_ignore(template: &Path, def: &TemplateDef) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(template);
+    let mut files = vec![template.join(".generatorignore")];
+    if def.respect_gitignore {
+        files.push(template.join(".gitignore"));
+    }
+    for file in files.iter().filter(|o| o.exists()) {
+        if let Some(e) = builder.add(file) {
+            return Err(e).with_context(|| format!("Invalid ignore file {}", file.display()));
+        }
+    }
+    builder.build().context("Invalid ignore files")
+}
+
+fn case_exclusions(template: &Path, def: &TemplateDef) -> Result<Vec<Regex>> {
+    harness::cases(template, def)?
+        .iter()
+        .map(|o| {
+            Regex::new(&format!(
+                "^{}/{}(/|$)",
+                regex::escape(def.tests_dir()),
+                regex::escape(o)
+            ))
+            .map_err(Error::from)
+        })
+        .collect()
+}
+
+const MAX_EXTENDS_DEPTH: usize = 16;
+
+// Layers the template `def` extends underneath it: variables and rules from the base apply
+// unless `def` redefines them, and its files are generated unless `def` has the same path
+fn inherit(template: &Path, def: &mut TemplateDef, depth: usize) -> Result<()> {
+    let extends = match def.extends.take() {
+        Some(extends) => extends,
+        None => return Ok(()),
+    };
+    ensure!(
+        depth < MAX_EXTENDS_DEPTH,
+        "Templates extend each other more than {} deep, is there a cycle?",
+        MAX_EXTENDS_DEPTH
+    );
+    let local = template.join(&extends);
+    let fetched = if local.exists() {
+        None
+    } else {
+        Some(Arc::new(fetch(extends.clone())?))
+    };
+    let base_dir = fetched.as_deref().map_or(local, |o| o.to_path_buf());
+    let mut base = load_definition_file(&base_dir)
+        .with_context(|| format!("Failed to load base template {}", extends))?;
+    inherit(&base_dir, &mut base, depth + 1)?;
+    def.fetched.extend(fetched);
+    overlay(def, base, &base_dir)
+}
+
+// Puts `base`, loaded from `base_dir`, underneath `def`: variables and rules from the base apply
+// unless `def` redefines them, and its files are generated unless `def` has the same path
+fn overlay(def: &mut TemplateDef, mut base: TemplateDef, base_dir: &Path) -> Result<()> {
+    let exclusions = case_exclusions(base_dir, &base)?;
+    def.case_exclusions.append(&mut base.case_exclusions);
+    def.case_exclusions.extend(exclusions);
+    let mut bases = base.layers(base_dir);
+    bases.append(&mut def.bases);
+    def.bases = bases;
+
+    for var in std::mem::take(&mut def.variables) {
+        match base.variables.iter_mut().find(|o| o.name == var.name) {
+            Some(existing) => *existing = var,
+            None => base.variables.push(var),
+        }
+    }
+    def.variables = base.variables;
+    group_sections(&mut def.variables);
+    // The base's rules go after those of `def`, and before the rules generator adds after them
+    let own = |files: &[FileDef]| {
+        files
+            .iter()
+            .rposition(FileDef::explicit)
+            .map_or(0, |i| i + 1)
+    };
+    let added = def.files.split_off(own(&def.files));
+    base.files.truncate(own(&base.files));
+    def.files.append(&mut base.files);
+    def.files.extend(added);
+    for hook in &mut base.hooks {
+        hook.dir.get_or_insert_with(|| base_dir.to_owned());
+    }
+    base.hooks.append(&mut def.hooks);
+    def.hooks = base.hooks;
+    base.plugins.append(&mut def.plugins);
+    def.plugins = base.plugins;
+    base.verify.append(&mut def.verify);
+    def.verify = base.verify;
+    def.allow.append(&mut base.allow);
+    def.aliases.append(&mut base.aliases);
+    def.eol = def.eol.or(base.eol);
+    def.keep_vcs |= base.keep_vcs;
+    def.strict |= base.strict;
+    def.directory_markers |= base.directory_markers;
+    def.delimiters = def.delimiters.take().or(base.delimiters);
+    def.post_message = def.post_message.take().or(base.post_message);
+    def.copy_without_render
+        .append(&mut base.copy_without_render);
+    def.fetched.append(&mut base.fetched);
+    Ok(())
+}
+
+// Loads templates applied one after another as a single definition, each layered over those
+// before it
+fn load_applied(templates: &[PathBuf]) -> Result<TemplateDef> {
+    let mut previous: Option<(TemplateDef, &Path)> = None;
+    for (group, template) in templates.iter().enumerate() {
+        let mut def = load_definition_file(template)?;
+        inherit(template, &mut def, 0)?;
+        def.group = group;
+        for base in &mut def.bases {
+            base.group = group;
+        }
+        if let Some((base, base_dir)) = previous.take() {
+            overlay(&mut def, base, base_dir)?;
+        }
+        previous = Some((def, template));
+    }
+    let (mut def, template) = previous.context("No templates given")?;
+    def.test_cases = harness::cases(template, &def)?;
+    let exclusions = case_exclusions(template, &def)?;
+    def.case_exclusions.extend(exclusions);
+    if !def.case_exclusions.is_empty() {
+        def.files.insert(
+            0,
+            FileDef {
+                sources: def.case_exclusions.clone(),
+                template: false,
+                include: false,
+                rename: None,
+                eol: None,
+                skip_if: None,
+                delimiters: None,
+                permissions: Permissions::default(),
+                origin: RuleOrigin::CaseExclusion,
+            },
+        );
+    }
+    Ok(def)
+}
+
+fn load_definition(template: &Path) -> Result<TemplateDef> {
+    load_applied(&[template.to_owned()])
+}
+
+fn load_definition_file(template: &Path) -> Result<TemplateDef> {
+    let cookiecutter = template.join("cookiecutter.json");
+    let definition = definition_path(template);
+    if definition.is_none() && cargo_generate::detect(template) {
+        return cargo_generate::parse_definition(template);
+    }
+    if definition.is_none() && cookiecutter.exists() {
+        return cookiecutter::parse_definition(
+            File::open(&cookiecutter).context("Failed to open cookiecutter.json")?,
+            template,
+        );
+    }
+    parse_definition(
+        read_definition(&definition.context("Template definition not found")?)?,
+        template,
+    )
+}
+
+// A relative path with `/` separators on every platform, as rules and templates expect
+fn slash_path(path: &Path) -> Option<String> {
+    let path = path.to_str()?;
+    Some(if std::path::MAIN_SEPARATOR == '/' {
+        path.to_owned()
+    } else {
+        path.replace(std::path::MAIN_SEPARATOR, "/")
+    })
+}
+
+fn file_context(context: &tera::Context, path: &Path) -> tera::Context {
+    let mut c = context.clone();
+    match slash_path(path) {
+        Some(path) => c.insert("file", &path),
+        None => c.insert("file", path),
+    }
+    c
+}
+
+// Layers a mapping of values over the context, merging objects like `author` field by field
+fn layer_values(context: &mut tera::Context, values: tera::Value) {
+    if let tera::Value::Object(values) = values {
+        nested::extend(context, &values);
+    }
+}
+
+// A missing or empty `defaults.yml` gives no defaults
+fn template_defaults(template: &Path) -> Result<Option<Value>> {
+    let path = template.join("defaults.yml");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path).context("Failed to open template defaults")?;
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let defaults: Value =
+        serde_yaml::from_str(&text).context("While parsing template default variables")?;
+    match defaults {
+        Value::Null => Ok(None),
+        Value::Mapping(_) => Ok(Some(defaults)),
+        _ => bail!("Expected template default variables to be a mapping at top level"),
+    }
+}
+
+pub fn metadata(template: impl AsRef<Path>) -> Result<Metadata> {
+    Ok(load_definition(template.as_ref())?.metadata)
+}
+
+// Variables a project's directory is named after when no destination is given, as cookiecutter
+// does
+const PROJECT_NAME_VARIABLES: [&str; 2] = ["project_name", "name"];
+
+// The template's project name variable and its value, from the answers or asked for, or nothing if
+// it has no such variable
+pub fn project_name(
+    template: impl AsRef<Path>,
+    options: &Options,
+) -> Result<Option<(String, tera::Value)>> {
+    let def = load_definition(template.as_ref())?;
+    let var = match PROJECT_NAME_VARIABLES
+        .iter()
+        .find_map(|name| def.variables.iter().find(|o| o.name == *name))
     {
-        let f = def
-            .find_for_str(path.to_str().context("Filename is not a string")?)
-            .context("Could not find a spec for file")?;
-        let context = {
-            let mut c = tera::Context::new();
-            c.extend(context.clone());
-            c.insert("file", &path);
-            c
+        Some(var) => var,
+        None => return Ok(None),
+    };
+    let value = match options.answers.get(&var.name) {
+        Some(value) => value.clone(),
+        None => {
+            let default = match &var.default {
+                Some(default) if def.has_variables(default) => {
+                    Some(def.render(default, &builtins::context())?)
+                }
+                default => default.clone(),
+            };
+            if options.missing == Missing::Fail && default.is_none() {
+                bail!(PromptError::Disabled(var.name.clone()));
+            }
+            let answer = match (options.missing, default) {
+                (Missing::Prompt, default) => {
+                    prompt(&var.name, default.as_deref(), options.prompt_timeout)?
+                }
+                (_, Some(default)) => default,
+                (_, None) => var.name.clone(),
+            };
+            tera::Value::String(answer)
+        }
+    };
+    let value = var.normalise(value);
+    var.check(&value)?;
+    Ok(Some((var.name.clone(), value)))
+}
+
+// Name of the map of every resolved variable, for templates to iterate over
+const VARS: &str = "vars";
+
+// Resolved variables are also given as a map, as `vars` and under the template's `namespace`
+fn insert_namespace(context: &mut tera::Context, def: &TemplateDef) {
+    let mut values = tera::Value::Object(tera::Map::new());
+    for var in &def.variables {
+        if let Some(value) = nested::get(context, &var.name) {
+            nested::set(&mut values, &var.name, value.clone());
+        }
+    }
+    if let Some(namespace) = &def.namespace {
+        context.insert(namespace, &values);
+    }
+    // Unless the template has a variable of its own by that name
+    if !def.variables.iter().any(|o| o.name == VARS) {
+        context.insert(VARS, &values);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Missing {
+    #[default]
+    Prompt,
+    Fake,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    #[default]
+    Default,
+    // Renders files in parallel, clones files copied without rendering where the filesystem can
+    // and skips variable validation
+    Fast,
+    // Renders every file up front like `strict`, generates into a staging directory, runs
+    // `verify` commands there and fails on any warning before moving the result into place,
+    // which has to be a new or empty directory
+    Safe,
+}
+
+impl Profile {
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Default => "default",
+            Profile::Fast => "fast",
+            Profile::Safe => "safe",
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(Profile::Default),
+            "fast" => Ok(Profile::Fast),
+            "safe" => Ok(Profile::Safe),
+            _ => bail!("Unknown profile {}, expected `fast` or `safe`", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vcs {
+    Git,
+    None,
+}
+
+impl FromStr for Vcs {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "git" => Ok(Vcs::Git),
+            "none" => Ok(Vcs::None),
+            _ => bail!("Unknown vcs {}, expected `git` or `none`", s),
+        }
+    }
+}
+
+// Line endings of rendered files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Eol {
+    Lf,
+    Crlf,
+    // Those of the platform generating
+    Native,
+    // Whatever the source and its rendering contain
+    #[default]
+    Preserve,
+}
+
+impl FromStr for Eol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lf" => Ok(Eol::Lf),
+            "crlf" => Ok(Eol::Crlf),
+            "native" => Ok(Eol::Native),
+            "preserve" => Ok(Eol::Preserve),
+            _ => bail!(
+                "Unknown line ending {}, expected `lf`, `crlf`, `native` or `preserve`",
+                s
+            ),
+        }
+    }
+}
+
+impl Eol {
+    fn apply(self, text: String) -> String {
+        let crlf = match self {
+            Eol::Preserve => return text,
+            Eol::Lf => false,
+            Eol::Crlf => true,
+            Eol::Native => cfg!(windows),
         };
-        let input = template.join(&path);
-        let new = destination.join(if let Some(rename) = &f.rename {
-            Tera::one_off(rename, &context, false)?.into()
-        } else {
-            path.clone()
-        });
-        if input.is_dir() {
-            std::fs::create_dir_all(&new).with_context(|| {
-                format!("Could not create dir {}", new.display())
-            })?;
+        let text = text.replace("\r\n", "\n");
+        if crlf {
+            text.replace('\n', "\r\n")
         } else {
-            let mut file = std::fs::File::create(&new).with_context(|| {
-                format!(
-                    "Destination {} already exists!",
-                    new.display()
-                )
+            text
+        }
+    }
+}
+
+// What to do when several applied templates generate the same file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflict {
+    // The later template's file is used
+    #[default]
+    Overwrite,
+    // The earlier template's file is used
+    Keep,
+    Error,
+}
+
+impl Conflict {
+    pub fn name(self) -> &'static str {
+        match self {
+            Conflict::Overwrite => "overwrite",
+            Conflict::Keep => "keep",
+            Conflict::Error => "error",
+        }
+    }
+}
+
+impl FromStr for Conflict {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "overwrite" => Ok(Conflict::Overwrite),
+            "keep" => Ok(Conflict::Keep),
+            "error" => Ok(Conflict::Error),
+            _ => bail!(
+                "Unknown conflict policy {}, expected `overwrite`, `keep` or `error`",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub missing: Missing,
+    pub answers: BTreeMap<String, tera::Value>,
+    pub ignore_user_defaults: bool,
+    pub ignore_environment: bool,
+    pub allow: Vec<WarningKind>,
+    pub prompt_timeout: Option<Duration>,
+    pub confirm: bool,
+    pub profile: Profile,
+    // Key under which answers are saved after generation and offered as defaults next time
+    pub remember: Option<String>,
+    pub conflict: Conflict,
+    pub keep_vcs: bool,
+    // Leave files written before a failure in place, so that `resume` can finish generating
+    pub keep_partial: bool,
+    // Render every file before writing any, failing with all that can't be
+    pub strict: bool,
+    // A named defaults profile from the config directory, over the usual defaults
+    pub defaults: Option<String>,
+    // Keep no record for `undo`, for destinations that are removed straight after generating
+    pub skip_record: bool,
+}
+
+pub fn generate(template: impl AsRef<Path>, destination: impl AsRef<Path>) -> Result<Report> {
+    generate_with(template, destination, &Options::default())
+}
+
+pub fn generate_with(
+    template: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    options: &Options,
+) -> Result<Report> {
+    run(
+        &[template.as_ref().to_owned()],
+        destination.as_ref(),
+        options,
+        None,
+    )
+}
+
+// The files generating into `destination` would write, without running hooks or asking for
+// anything, variables without a value standing in as their names
+fn planned_files(
+    def: &TemplateDef,
+    template: &Path,
+    destination: &Path,
+    options: &Options,
+) -> Result<Vec<PathBuf>> {
+    let def = TemplateDef {
+        hooks: vec![],
+        ..def.clone()
+    };
+    let options = Options {
+        missing: Missing::Fake,
+        ..options.clone()
+    };
+    let mut warnings = Warnings::new(def.allow.iter().chain(&options.allow).copied());
+    let undeclared = Usage::collect(&def, template)?.undeclared(&def, template)?;
+    let def = usage::declaring(&def, undeclared);
+    let mut coverage = Coverage::new(&def);
+    let layers = def.layers(template);
+    let def = def.for_generation(&layers)?;
+    let context = resolve_context(
+        &def,
+        &layers,
+        destination,
+        &options,
+        &Default::default(),
+        &mut coverage,
+        &mut warnings,
+    )?;
+    Ok(plan_entries(
+        &def,
+        &layers,
+        &context,
+        &options,
+        &mut coverage,
+        &mut warnings,
+        &mut vec![],
+    )?
+    .into_iter()
+    .filter(|o| o.kind != EntryKind::Directory)
+    .map(|o| o.destination)
+    .collect())
+}
+
+// Renders one file of a template as generating into `destination` would, without writing
+// anything, so a template's author can check a file without generating the whole project. The
+// file is given relative to the template, or to its `root`.
+pub fn render_file(
+    template: impl AsRef<Path>,
+    file: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    options: &Options,
+) -> Result<String> {
+    let template = template.as_ref();
+    let file = file.as_ref();
+    let def = load_applied(&[template.to_owned()])?;
+    let mut warnings = Warnings::new(def.allow.iter().chain(&options.allow).copied());
+    let undeclared = Usage::collect(&def, template)?.undeclared(&def, template)?;
+    let def = usage::declaring(&def, undeclared);
+    let mut coverage = Coverage::new(&def);
+    let layers = def.layers(template);
+    let def = def.for_generation(&layers)?;
+    // Later layers override earlier ones, as when generating
+    let (layer, path) = layers
+        .iter()
+        .rev()
+        .find_map(|layer| {
+            let path = layer
+                .root
+                .strip_prefix(&layer.template)
+                .ok()
+                .and_then(|root| file.strip_prefix(root).ok())
+                .unwrap_or(file);
+            layer.root.join(path).is_file().then_some((layer, path))
+        })
+        .with_context(|| format!("No file {} in template", file.display()))?;
+    let rule = &def.files[def
+        .position_for(path)
+        .context("Could not find a spec for file")?];
+    ensure!(
+        rule.include,
+        "{} is left out of generated projects",
+        file.display()
+    );
+    let input = layer.root.join(path);
+    let contents = std::fs::read_to_string(&input)
+        .with_context(|| format!("Failed to read file {} as text", input.display()))?;
+    if !def.renders(rule, path) {
+        return Ok(contents);
+    }
+    let context = resolve_context(
+        &def,
+        &layers,
+        destination.as_ref(),
+        options,
+        &BTreeMap::new(),
+        &mut coverage,
+        &mut warnings,
+    )?;
+    let entry = PlannedEntry {
+        source: path.to_owned(),
+        root: layer.root.clone(),
+        destination: path.to_owned(),
+        kind: EntryKind::Templated,
+        eol: rule.eol.or(def.eol).unwrap_or_default(),
+        delimiters: rule.delimiters.clone().or_else(|| def.delimiters.clone()),
+        permissions: rule.permissions.clone(),
+    };
+    render_entry(&def, &entry, &contents, &context)
+}
+
+// Generates several templates into one destination, later templates layered over earlier ones
+pub fn apply_with(
+    templates: &[impl AsRef<Path>],
+    destination: impl AsRef<Path>,
+    options: &Options,
+) -> Result<Report> {
+    let templates = templates
+        .iter()
+        .map(|o| o.as_ref().to_owned())
+        .collect::<Vec<_>>();
+    run(&templates, destination.as_ref(), options, None)
+}
+
+const ENV_PREFIX: &str = "GENERATOR_VAR_";
+
+// `GENERATOR_VAR_<NAME>` matches a variable by its exact name or by its name in upper case
+fn environment_variables(def: &TemplateDef) -> Vec<(String, String)> {
+    std::env::vars()
+        .filter_map(|(key, value)| {
+            let suffix = key.strip_prefix(ENV_PREFIX)?;
+            let name = def
+                .variables
+                .iter()
+                .find(|o| o.name == suffix || o.name.to_uppercase().replace('.', "_") == suffix)
+                .map_or(suffix, |o| &o.name);
+            Some((name.to_owned(), value))
+        })
+        .collect()
+}
+
+// The trimmed output of a variable's `default_from_command`, if the registry's
+// `command_defaults` allows running it and it succeeds with some output
+fn command_default(var: &VariableDef) -> Result<Option<String>> {
+    let command = match &var.default_from_command {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+    let registry = Registry::load()?;
+    let allowed = match registry.command_defaults()? {
+        CommandDefaults::Always => true,
+        CommandDefaults::Never => false,
+        CommandDefaults::Ask => {
+            std::io::stdin().is_terminal()
+                && !quiet()
+                && prompt::confirm(&format!(
+                    "Run `{}` for the default of {}?",
+                    command, var.name
+                ))?
+        }
+    };
+    if !allowed {
+        return Ok(None);
+    }
+    // Run where generator is, as these ask about the user rather than the template
+    let output = registry
+        .sandbox()?
+        .command(command, &std::env::current_dir()?)
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .with_context(|| {
+            format!(
+                "Failed to run `{}` for the default of {}",
+                command, var.name
+            )
+        })?;
+    let default = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    Ok(Some(default).filter(|o| output.status.success() && !o.is_empty()))
+}
+
+// The resolved value of every variable the template defines
+fn answers(def: &TemplateDef, context: &tera::Context) -> BTreeMap<String, tera::Value> {
+    def.variables
+        .iter()
+        .filter_map(|o| Some((o.name.clone(), nested::get(context, &o.name)?.clone())))
+        .collect()
+}
+
+fn render_entry(
+    def: &TemplateDef,
+    entry: &PlannedEntry,
+    contents: &str,
+    context: &tera::Context,
+) -> Result<String> {
+    let rendered = def
+        .render_with(
+            contents,
+            &file_context(context, &entry.source),
+            entry.delimiters.as_ref(),
+        )
+        .map_err(|e| anyhow!(RenderError::new(&entry.source, contents, &e)))?;
+    Ok(entry.eol.apply(rendered))
+}
+
+fn write_entry(
+    def: &TemplateDef,
+    destination: &Path,
+    entry: &PlannedEntry,
+    context: &tera::Context,
+    profile: Profile,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let input = entry.root.join(&entry.source);
+    let new = destination.join(&entry.destination);
+    match entry.kind {
+        EntryKind::Directory => {
+            std::fs::create_dir_all(&new)
+                .with_context(|| format!("Could not create dir {}", new.display()))?;
+        }
+        // The platform's copy clones files on filesystems that can, like APFS, btrfs and XFS,
+        // rather than reading and writing them. It keeps the source's permissions too
+        EntryKind::Copied if profile == Profile::Fast => {
+            if let Some(parent) = new.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Could not create dir {}", parent.display()))?;
+            }
+            std::fs::copy(&input, &new).with_context(|| {
+                format!("Failed to copy {} to {}", input.display(), new.display())
             })?;
-            file.write_all(&if f.template {
-                Tera::one_off(
-                    &read_to_string(&input)
-                        .with_context(|| format!("Invalid UTF-8 in file {}", input.display()))?,
-                    &context,
-                    false,
-                )?
-                .into_bytes()
+        }
+        EntryKind::Templated | EntryKind::Copied => {
+            // A renamed file may land in a directory the template doesn't have
+            if let Some(parent) = new.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Could not create dir {}", parent.display()))?;
+            }
+            let mut file = std::fs::File::create(&new)
+                .with_context(|| format!("Destination {} already exists!", new.display()))?;
+            let contents =
+                read(&input).with_context(|| format!("Failed to read file {}", input.display()))?;
+            file.write_all(&if entry.kind == EntryKind::Templated {
+                match String::from_utf8(contents) {
+                    Ok(contents) => render_entry(def, entry, &contents, context)?.into_bytes(),
+                    Err(e) => {
+                        warnings.warn(
+                            WarningKind::NonUtf8,
+                            format!(
+                                "{} is not valid UTF-8, copied without rendering",
+                                entry.source.display()
+                            ),
+                        );
+                        e.into_bytes()
+                    }
+                }
             } else {
-                read(&input).with_context(|| format!("Failed to read file {}", input.display()))?
+                contents
             })?;
         }
     }
     Ok(())
 }
+
+fn run(
+    templates: &[PathBuf],
+    destination: &Path,
+    options: &Options,
+    progress: Option<Progress>,
+) -> Result<Report> {
+    let def = load_applied(templates)?;
+    if def.single_file {
+        return run_single(templates, &def, destination, options, progress);
+    }
+    run_loaded(templates, &def, destination, options, progress)
+}
+
+// Generates a single-file template into a directory beside `destination`, then moves the file
+// it generated to `destination`
+fn run_single(
+    templates: &[PathBuf],
+    def: &TemplateDef,
+    destination: &Path,
+    options: &Options,
+    progress: Option<Progress>,
+) -> Result<Report> {
+    ensure!(
+        !destination.exists(),
+        "{} already exists",
+        destination.display()
+    );
+    let parent = match destination.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    create_dir_all(parent)?;
+    let staging = tempfile::Builder::new()
+        .prefix(".generator-file")
+        .tempdir_in(parent)
+        .context("Failed to create temporary directory")?;
+    let generated = staging.path().join(
+        destination
+            .file_name()
+            .context("Destination has no file name")?,
+    );
+    let mut report = run_loaded(templates, def, &generated, options, progress)?;
+    let files = WalkDir::new(&generated)
+        .into_iter()
+        .filter_map(|o| o.ok())
+        .filter(|o| o.file_type().is_file())
+        .map(|o| o.into_path())
+        .collect::<Vec<_>>();
+    match files.as_slice() {
+        [] => {}
+        [file] => rename(file, destination)
+            .with_context(|| format!("Failed to write {}", destination.display()))?,
+        _ => bail!(
+            "Single-file template generated {} files, expected one",
+            files.len()
+        ),
+    }
+    if generated.exists() {
+        record::forget(&generated)?;
+    }
+    // There's no directory to initialize a repository in
+    report.vcs = None;
+    Ok(report)
+}
+
+// The context variables are resolved in: builtins, then defaults from the templates and config,
+// the environment and answers given, each overriding the last. Also gives the values given for
+// this generation alone, as opposed to defaults from `defaults.yml` or a profile
+fn initial_context(
+    def: &TemplateDef,
+    layers: &[Layer],
+    destination: &Path,
+    options: &Options,
+    answers: &BTreeMap<String, tera::Value>,
+) -> Result<(tera::Context, tera::Context)> {
+    let mut context = builtins::context();
+    for layer in layers {
+        if let Some(defaults) = template_defaults(&layer.template)? {
+            layer_values(&mut context, tera::to_value(defaults)?);
+        }
+    }
+    // Then system and user config, the environment, and answers given directly, each overriding
+    // the last
+    if !options.ignore_user_defaults {
+        if let Some(profile) = &options.defaults {
+            ensure!(
+                Config::profile_path(profile).exists(),
+                "No defaults profile named {}, expected {}",
+                profile,
+                Config::profile_path(profile).display()
+            );
+        }
+        let config = Config::load_profile(options.defaults.as_deref())?;
+        layer_values(&mut context, tera::to_value(config.values())?);
+    }
+    let mut explicit = tera::Context::new();
+    if !options.ignore_environment {
+        for (name, value) in environment_variables(def) {
+            nested::insert(&mut explicit, &name, value.clone().into());
+            nested::insert(&mut context, &name, value.into());
+        }
+    }
+    for context in [&mut context, &mut explicit] {
+        nested::extend(context, answers);
+        nested::extend(context, &options.answers);
+    }
+    if let Some(s) = destination.file_name().and_then(OsStr::to_str) {
+        context.insert("basename", s);
+        for (name, value) in &builtins::names(s) {
+            // Templates defining one of these themselves, like cargo-generate's `crate_name`,
+            // resolve it as any other variable
+            if !def.variables.iter().any(|o| o.name == *name) {
+                context.insert(*name, value);
+            }
+        }
+    }
+    insert_namespace(&mut context, def);
+    Ok((context, explicit))
+}
+
+// Resolves every variable from template defaults, config, the environment and answers given,
+// prompting for the rest, into the context files are rendered with
+fn resolve_context(
+    def: &TemplateDef,
+    layers: &[Layer],
+    destination: &Path,
+    options: &Options,
+    answers: &BTreeMap<String, tera::Value>,
+    coverage: &mut Coverage,
+    warnings: &mut Warnings,
+) -> Result<tera::Context> {
+    let (mut context, explicit) = initial_context(def, layers, destination, options, answers)?;
+    let saved = match &options.remember {
+        Some(key) => saved::load(key)?,
+        None => BTreeMap::new(),
+    };
+    // The section last prompted under
+    let mut heading = None;
+    for var in usage::resolution_order(def)? {
+        let var_coverage = coverage
+            .variable(&var.name)
+            .expect("Coverage is built from the same definition");
+        if let Some(old) = var
+            .renamed_from
+            .iter()
+            .find(|o| !nested::contains(&context, &var.name) && nested::contains(&context, o))
+        {
+            let value = nested::get(&context, old).expect("Checked above").clone();
+            nested::insert(&mut context, &var.name, value);
+            warnings.warn(
+                WarningKind::DeprecatedVariable,
+                format!("Variable {} has been renamed to {}", old, var.name),
+            );
+        }
+        if nested::contains(&context, &var.name) {
+            // Defaults from `defaults.yml` or a profile stand in for the variable's own
+            let given = std::iter::once(&var.name)
+                .chain(&var.renamed_from)
+                .any(|o| nested::contains(&explicit, o));
+            var_coverage.provided |= given;
+            var_coverage.defaulted |= !given;
+        } else if let Some(answer) = saved.get(&var.name).filter(|_| {
+            var.default.is_some()
+                || var.default_from_command.is_some()
+                || options.missing != Missing::Prompt
+        }) {
+            var_coverage.defaulted = var.default.is_some();
+            var_coverage.provided = var.default.is_none();
+            nested::insert(&mut context, &var.name, answer.clone());
+        } else if let Some(default) = command_default(var)? {
+            var_coverage.defaulted = true;
+            nested::insert(&mut context, &var.name, default.into())
+        } else if let Some(default) = &var.default {
+            var_coverage.defaulted = true;
+            let default = if def.has_variables(default) {
+                def.render(default, &context).with_context(|| {
+                    format!("Failed to render default for variable {}", var.name)
+                })?
+            } else {
+                default.clone()
+            };
+            nested::insert(&mut context, &var.name, default.into())
+        } else if options.missing == Missing::Fake {
+            var_coverage.provided = true;
+            // Something allowed, where only some values are
+            let fake = var.choices.first().unwrap_or(&var.name);
+            nested::insert(&mut context, &var.name, fake.clone().into())
+        } else if options.missing == Missing::Fail {
+            bail!(PromptError::Disabled(var.name.clone()));
+        } else {
+            var_coverage.provided = true;
+            let last = saved.get(&var.name).map(|o| match o {
+                tera::Value::String(s) => s.clone(),
+                tera::Value::Array(items) => items
+                    .iter()
+                    .map(|o| o.as_str().map_or_else(|| o.to_string(), str::to_owned))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                o => o.to_string(),
+            });
+            if var.section.is_some() && var.section != heading {
+                heading = var.section.clone();
+                prompt::section(heading.as_deref().unwrap_or_default());
+            }
+            prompt::describe(var.description.as_deref(), &var.choices);
+            let answer = loop {
+                let answer = match var.input {
+                    Input::Line => prompt(&var.name, last.as_deref(), options.prompt_timeout),
+                    Input::Editor => {
+                        prompt::editor(&var.name, last.as_deref(), options.prompt_timeout)
+                    }
+                };
+                let answer = match answer {
+                    Err(e) if e.is_retryable() => continue,
+                    answer => answer?,
+                };
+                // Someone at a terminal can correct a typo, piped answers fail below instead
+                match var.check(&tera::Value::String(answer.clone())) {
+                    Err(e) if std::io::stdin().is_terminal() => {
+                        eprintln!("{}", Style::Error.paint_err(e))
+                    }
+                    _ => break answer,
+                }
+            };
+            nested::insert(&mut context, &var.name, answer.into());
+        }
+        let value = var.normalise(
+            nested::get(&context, &var.name)
+                .expect("Variable was just resolved")
+                .clone(),
+        );
+        if options.profile != Profile::Fast {
+            var.check(&value)?;
+        }
+        nested::insert(&mut context, &var.name, value);
+        insert_namespace(&mut context, def);
+    }
+    let template = &layers
+        .last()
+        .expect("A template has at least one layer")
+        .template;
+    hooks::run_pre(&def.hooks, template, &mut context)?;
+    Ok(context)
+}
+
+const DIRECTORY_MARKERS: [&str; 2] = [".gitkeep", ".keep"];
+
+// What each file and directory of the template is generated as, in the order they're walked
+fn plan_entries(
+    def: &TemplateDef,
+    layers: &[Layer],
+    context: &tera::Context,
+    options: &Options,
+    coverage: &mut Coverage,
+    warnings: &mut Warnings,
+    // Paths left out, with why
+    skips: &mut Vec<(PathBuf, String)>,
+) -> Result<Vec<PlannedEntry>> {
+    let explicit_rules = def.files.iter().any(FileDef::explicit);
+    // Destinations of directories, relative to `destination`, so that children of a renamed
+    // directory follow it
+    let mut renamed = HashMap::<PathBuf, PathBuf>::new();
+    let mut plan: Vec<PlannedEntry> = vec![];
+    // Which applied template each destination came from
+    let mut groups = HashMap::<PathBuf, usize>::new();
+    for layer in layers {
+        let ignore = load_ignore(&layer.template, def)?;
+        // Sources left out by `skip_if` or an empty name, along with everything in them
+        let mut skipped = HashSet::<PathBuf>::new();
+        // Which way each `skip_if` went, counted once the walk is done with `coverage`
+        let mut branches = vec![];
+        for path in walk(def, &layer.root, &ignore, coverage) {
+            let index = def
+                .position_for(&path)
+                .context("Could not find a spec for file")?;
+            let f = &def.files[index];
+            let context = file_context(context, &path);
+            let parent = path.parent().unwrap_or_else(|| Path::new(""));
+            if skipped.contains(parent) {
+                skipped.insert(path);
+                continue;
+            }
+            // Directories are generated whether empty or not, so markers keeping them in version
+            // control aren't needed in the project
+            if def.directory_markers
+                && DIRECTORY_MARKERS
+                    .iter()
+                    .any(|o| path.file_name() == Some(OsStr::new(o)))
+                && layer.root.join(&path).is_file()
+            {
+                continue;
+            }
+            let skip = match &f.skip_if {
+                Some(condition) => {
+                    let rendered = def.render(condition, &context).with_context(|| {
+                        format!("Failed to render `skip_if` for {}", path.display())
+                    })?;
+                    let rendered = rendered.trim();
+                    !rendered.is_empty() && rendered != "false"
+                }
+                None => false,
+            };
+            if f.skip_if.is_some() {
+                branches.push((index, skip));
+            }
+            if skip {
+                skips.push((path.clone(), "`skip_if` is true".to_owned()));
+                skipped.insert(path);
+                continue;
+            }
+            let relative = if let Some(rename) = &f.rename {
+                let rendered = PathBuf::from(def.render_rename(f, rename, &path, &context)?.trim());
+                // A rename keeping the source's directories, like `src/{{ name }}` for a file in
+                // `src`, follows the nearest of them to wherever it was renamed
+                match path
+                    .ancestors()
+                    .skip(1)
+                    .filter(|o| !o.as_os_str().is_empty())
+                    .find_map(|o| Some((rendered.strip_prefix(o).ok()?, renamed.get(o)?)))
+                {
+                    Some((rest, directory)) if !rendered.as_os_str().is_empty() => {
+                        directory.join(rest)
+                    }
+                    _ => rendered,
+                }
+            } else {
+                let name = path
+                    .file_name()
+                    .expect("Walked paths always have a file name")
+                    .to_str()
+                    .context("Filename is not a string")?;
+                let name = if def.template_paths && def.has_variables(name) {
+                    def.render(name, &context)
+                        .with_context(|| format!("Failed to render path {}", path.display()))?
+                } else {
+                    name.to_owned()
+                };
+                if name.trim().is_empty() {
+                    PathBuf::new()
+                } else {
+                    renamed
+                        .get(parent)
+                        .map(PathBuf::as_path)
+                        .unwrap_or(parent)
+                        .join(name)
+                }
+            };
+            // A rename or templated name rendering empty leaves the path out
+            if relative.as_os_str().is_empty() {
+                skips.push((path.clone(), "its name rendered empty".to_owned()));
+                skipped.insert(path);
+                continue;
+            }
+            let kind = if layer.root.join(&path).is_dir() {
+                renamed.insert(path.clone(), relative.clone());
+                EntryKind::Directory
+            } else {
+                // A path in several layers is only warned about once
+                if explicit_rules
+                    && def.files[index].origin == RuleOrigin::CatchAll
+                    && !plan.iter().any(|o| o.source == path)
+                {
+                    warnings.warn(
+                        WarningKind::DefaultRule,
+                        format!(
+                            "{} matched no file rule, falling back to the default",
+                            path.display()
+                        ),
+                    );
+                }
+                if def.renders(f, &path) {
+                    EntryKind::Templated
+                } else {
+                    EntryKind::Copied
+                }
+            };
+            let entry = PlannedEntry {
+                source: path,
+                root: layer.root.clone(),
+                destination: relative,
+                kind,
+                eol: f.eol.or(def.eol).unwrap_or_default(),
+                delimiters: f.delimiters.clone().or_else(|| def.delimiters.clone()),
+                permissions: f.permissions.clone(),
+            };
+            match plan.iter_mut().find(|o| o.destination == entry.destination) {
+                Some(existing)
+                    if existing.kind == EntryKind::Directory
+                        || groups[&entry.destination] == layer.group =>
+                {
+                    *existing = entry
+                }
+                Some(existing) => match options.conflict {
+                    Conflict::Overwrite => {
+                        groups.insert(entry.destination.clone(), layer.group);
+                        *existing = entry
+                    }
+                    Conflict::Keep => skips.push((
+                        entry.source,
+                        "an earlier template generates the same path".to_owned(),
+                    )),
+                    Conflict::Error => bail!(
+                        "{} is generated by more than one template",
+                        entry.destination.display()
+                    ),
+                },
+                None => {
+                    groups.insert(entry.destination.clone(), layer.group);
+                    plan.push(entry)
+                }
+            }
+        }
+        for (index, skipped) in branches {
+            coverage.branch(index, skipped);
+        }
+    }
+    Ok(plan)
+}
+
+fn run_loaded(
+    templates: &[PathBuf],
+    def: &TemplateDef,
+    destination: &Path,
+    options: &Options,
+    progress: Option<Progress>,
+) -> Result<Report> {
+    let kept;
+    let keep_vcs = options.keep_vcs || def.keep_vcs;
+    let def = if keep_vcs {
+        kept = def.keeping_vcs();
+        &kept
+    } else {
+        def
+    };
+    let resuming = progress.is_some();
+    let mut progress = progress.unwrap_or_default();
+    let template = templates
+        .last()
+        .expect("Loading checks a template was given");
+    let mut warnings = Warnings::new(def.allow.iter().chain(&options.allow).copied());
+    let usage = Usage::collect(def, template)?;
+    usage.warn(def, template, &mut warnings)?;
+    let undeclared = usage.undeclared(def, template)?;
+    let declared;
+    let def = if undeclared.is_empty() {
+        def
+    } else {
+        declared = usage::declaring(def, undeclared);
+        &declared
+    };
+    let mut coverage = Coverage::new(def);
+    coverage.profile(options.profile);
+    let layers = def.layers(template);
+    // Each generation has plugins and a Tera of its own, dropped when it ends
+    let def = &def.for_generation(&layers)?;
+    let context = resolve_context(
+        def,
+        &layers,
+        destination,
+        options,
+        &progress.answers,
+        &mut coverage,
+        &mut warnings,
+    )?;
+    for plugin in &def.loaded {
+        plugin.validate(&context)?;
+    }
+    // Hooks ran while resolving the context, and any failing would have stopped the generation
+    let mut summary = Summary {
+        hooks: def.hooks.iter().map(|o| o.command.clone()).collect(),
+        ..Summary::default()
+    };
+    let plan = plan_entries(
+        def,
+        &layers,
+        &context,
+        options,
+        &mut coverage,
+        &mut warnings,
+        &mut summary.skipped,
+    )?;
+    let fresh_destination = !destination.exists()
+        || std::fs::read_dir(destination)
+            .map(|mut o| o.next().is_none())
+            .unwrap_or(false);
+    // The safe profile only moves a complete and verified project into place, which needs
+    // somewhere empty to move it to
+    ensure!(
+        options.profile != Profile::Safe || resuming || fresh_destination,
+        "The safe profile generates into a new or empty destination, but {} has files in it",
+        destination.display()
+    );
+    // Every file is rendered before any is written, so that a missing variable is found up front
+    if options.strict || def.strict || options.profile == Profile::Safe {
+        let errors = plan
+            .iter()
+            .filter(|o| {
+                o.kind == EntryKind::Templated && !progress.completed.contains(&o.destination)
+            })
+            .filter_map(|entry| {
+                // Files that aren't UTF-8 are copied, and unreadable ones fail when written
+                let contents = std::fs::read_to_string(entry.root.join(&entry.source)).ok()?;
+                render_entry(def, entry, &contents, &context).err()
+            })
+            .map(|o| format!("{:#}", o))
+            .collect::<Vec<_>>();
+        ensure!(
+            errors.is_empty(),
+            "{} file(s) failed to render, nothing was written:\n{}",
+            errors.len(),
+            errors.join("\n")
+        );
+    }
+    if options.confirm && !resuming && std::io::stdin().is_terminal() && !quiet() {
+        let values = def
+            .variables
+            .iter()
+            .filter_map(|o| Some((o.name.as_str(), nested::get(&context, &o.name)?)))
+            .collect::<Vec<_>>();
+        print!("{}", preview::variables(&values));
+        println!("{}", preview::tree(destination, &plan));
+        if !prompt::confirm("Generate these files?")? {
+            bail!("Generation cancelled");
+        }
+    }
+    // Entries already there aren't the generation's to undo, unless an earlier attempt at it
+    // wrote them
+    let created_destination = !destination.exists();
+    let existed = plan
+        .iter()
+        .filter(|o| {
+            !progress.completed.contains(&o.destination)
+                && destination.join(&o.destination).exists()
+        })
+        .map(|o| o.destination.clone())
+        .collect::<HashSet<_>>();
+    // Files are generated into a staging directory moved into place once complete, so that a
+    // failure leaves nothing behind. Without a new or empty destination to move to, they're
+    // written in place
+    let staging = if !resuming
+        && fresh_destination
+        && (options.profile == Profile::Safe || !options.keep_partial)
+    {
+        let parent = match destination.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(parent)?;
+        Some(
+            tempfile::Builder::new()
+                .prefix(".generator-staging")
+                .tempdir_in(parent)
+                .context("Failed to create staging directory")?,
+        )
+    } else {
+        None
+    };
+    let output = staging.as_ref().map_or(destination, |o| o.path());
+    std::fs::create_dir_all(output)?;
+    let pending = plan
+        .iter()
+        .filter(|o| !progress.completed.contains(&o.destination))
+        .collect::<Vec<_>>();
+    let mut sequential = |entries: Vec<&PlannedEntry>, warnings: &mut Warnings| {
+        for entry in entries {
+            match write_entry(def, output, entry, &context, options.profile, warnings) {
+                Ok(()) => {
+                    progress.completed.insert(entry.destination.clone());
+                }
+                Err(e) => return Some((entry.source.clone(), e)),
+            }
+        }
+        None
+    };
+    let failure = if options.profile == Profile::Fast {
+        // Directories are created first so that files can be written in any order
+        let (dirs, files): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|o| o.kind == EntryKind::Directory);
+        match sequential(dirs, &mut warnings) {
+            Some(failure) => Some(failure),
+            None => {
+                let results = files
+                    .par_iter()
+                    .map(|entry| {
+                        let mut local = warnings.child();
+                        let result =
+                            write_entry(def, output, entry, &context, options.profile, &mut local);
+                        (*entry, result, local.emitted)
+                    })
+                    .collect::<Vec<_>>();
+                let mut failure = None;
+                for (entry, result, emitted) in results {
+                    warnings.emitted.extend(emitted);
+                    match result {
+                        Ok(()) => {
+                            progress.completed.insert(entry.destination.clone());
+                        }
+                        Err(e) => failure = failure.or(Some((entry.source.clone(), e))),
+                    }
+                }
+                failure
+            }
+        }
+    } else {
+        sequential(pending, &mut warnings)
+    };
+    if let Some((source, e)) = failure {
+        if staging.is_some() {
+            return Err(e.context(format!(
+                "Generation stopped at {}, nothing was written to {}",
+                source.display(),
+                destination.display()
+            )));
+        }
+        progress.templates = templates
+            .iter()
+            .map(|o| fetch::refetchable(o))
+            .collect::<Result<_>>()?;
+        progress.answers = answers(def, &context);
+        progress.options = options.clone();
+        progress.save(destination)?;
+        return Err(e.context(format!(
+            "Generation stopped at {}, fix the problem and run `generator resume {}` to continue",
+            source.display(),
+            destination.display()
+        )));
+    }
+    // Set once everything is written, so that a directory's mode can't keep its contents out
+    for entry in plan.iter().rev().filter(|o| !o.permissions.is_empty()) {
+        entry
+            .permissions
+            .apply(&output.join(&entry.destination), &mut warnings)?;
+    }
+    if let Some(staging) = staging {
+        if options.profile == Profile::Safe {
+            ensure!(
+                warnings.emitted.is_empty(),
+                "Generation produced warnings, which the safe profile treats as errors:\n{}",
+                warnings
+                    .emitted
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            let sandbox = Registry::load()?.sandbox()?;
+            let timeout = sandbox.timeout.unwrap_or(smoke::DEFAULT_TIMEOUT);
+            for command in &def.verify {
+                smoke::run(command, staging.path(), timeout, &sandbox)?;
+                summary.verified.push(command.clone());
+            }
+        }
+        if destination.exists() {
+            // Only an empty destination is staged for, and it can't be renamed over everywhere
+            std::fs::remove_dir(destination)
+                .with_context(|| format!("Failed to replace {}", destination.display()))?;
+        }
+        std::fs::rename(staging.path(), destination).with_context(|| {
+            format!(
+                "Failed to move generated files to {}",
+                destination.display()
+            )
+        })?;
+    }
+    Progress::clear(destination)?;
+    // Templates checked out as worktrees of the cache's mirrors have `.git` files pointing into
+    // the cache rather than their history, so the project is given repositories of its own
+    let detached =
+        keep_vcs && template.join(".git").is_file() && destination.join(".git").is_file();
+    if detached {
+        Repo::detach_worktree(template, destination)?;
+    }
+    let mut record = Record {
+        created_destination,
+        ..Record::default()
+    };
+    for entry in plan
+        .iter()
+        .filter(|o| !(detached && o.destination.ends_with(".git")))
+    {
+        let path = destination.join(&entry.destination);
+        if existed.contains(&entry.destination) {
+            if entry.kind != EntryKind::Directory {
+                summary.overwritten += 1;
+                summary.bytes += path.metadata()?.len();
+            }
+        } else if entry.kind == EntryKind::Directory {
+            record.directories.push(entry.destination.clone());
+        } else {
+            let digest = record::digest_file(&path)?;
+            record.files.push((entry.destination.clone(), digest));
+            summary.files += 1;
+            summary.bytes += path.metadata()?.len();
+        }
+    }
+    if !options.skip_record {
+        record.save(destination)?;
+    }
+    if let Some(key) = &options.remember {
+        saved::save(key, answers(def, &context))?;
+    }
+    summary.post_message = def
+        .post_message
+        .as_ref()
+        .map(|o| def.render(o, &context))
+        .transpose()
+        .context("Failed to render `post_message`")?;
+    Ok(Report {
+        coverage,
+        warnings: warnings.emitted,
+        vcs: def.vcs,
+        summary,
+    })
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use anyhow::*;
+use chrono::Datelike;
+
+use crate::builtins::GENERATED_AT;
+
+// SPDX identifiers with their texts, embedded verbatim
+const LICENSES: &[(&str, &str)] = &[
+    ("0BSD", include_str!("licenses/0BSD.txt")),
+    ("Apache-2.0", include_str!("licenses/Apache-2.0.txt")),
+    ("BSD-2-Clause", include_str!("licenses/BSD-2-Clause.txt")),
+    ("BSD-3-Clause", include_str!("licenses/BSD-3-Clause.txt")),
+    ("CC0-1.0", include_str!("licenses/CC0-1.0.txt")),
+    ("GPL-3.0", include_str!("licenses/GPL-3.0.txt")),
+    ("ISC", include_str!("licenses/ISC.txt")),
+    ("LGPL-3.0", include_str!("licenses/LGPL-3.0.txt")),
+    ("MIT", include_str!("licenses/MIT.txt")),
+    ("MPL-2.0", include_str!("licenses/MPL-2.0.txt")),
+    ("Unlicense", include_str!("licenses/Unlicense.txt")),
+];
+
+pub fn license_ids() -> impl Iterator<Item = &'static str> {
+    LICENSES.iter().map(|(id, _)| *id)
+}
+
+// The text of the license `id`, ignoring case and the `-only` or `-or-later` of GPL identifiers,
+// which share a text
+pub fn license_text(id: &str, holder: &str, year: &str) -> Result<String> {
+    let base = id
+        .strip_suffix("-only")
+        .or_else(|| id.strip_suffix("-or-later"))
+        .unwrap_or(id);
+    let (_, text) = LICENSES
+        .iter()
+        .find(|(o, _)| o.eq_ignore_ascii_case(base))
+        .with_context(|| {
+            format!(
+                "Unknown license {}, expected one of {}",
+                id,
+                license_ids().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+    // Only the copyright line heading licenses like MIT is filled in. Placeholders elsewhere, like
+    // those in the notices GPL and Apache suggest adding to source files, are part of the text
+    let mut filled = false;
+    Ok(text
+        .split_inclusive('\n')
+        .map(|line| {
+            if !filled && line.starts_with("Copyright") && line.contains("<year>") {
+                filled = true;
+                line.replace("<year>", year)
+                    .replace("<copyright holders>", holder)
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect())
+}
+
+// `license(id="MIT", holder="Jane Doe", year="2024")`, with `year` defaulting to this one
+pub(crate) fn function(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let string = |key: &str| -> tera::Result<Option<String>> {
+        match args.get(key) {
+            None => Ok(None),
+            Some(tera::Value::String(s)) => Ok(Some(s.clone())),
+            Some(tera::Value::Number(n)) => Ok(Some(n.to_string())),
+            Some(_) => Err(format!("`license` expects `{}` to be a string", key).into()),
+        }
+    };
+    let id = string("id")?.ok_or("`license` needs an `id`, like \"MIT\"")?;
+    let holder = string("holder")?.unwrap_or_default();
+    let year = string("year")?.unwrap_or_else(|| GENERATED_AT.year().to_string());
+    license_text(&id, &holder, &year)
+        .map(tera::Value::String)
+        .map_err(|e| e.to_string().into())
+}
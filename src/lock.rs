@@ -0,0 +1,59 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use serde_yaml::{from_reader, to_writer, Value};
+
+pub const LOCKFILE: &str = "generator.lock";
+
+// The commit each git template was generated from, kept alongside a project so that it can be
+// scaffolded again identically
+#[derive(Debug, Clone)]
+pub struct Lockfile {
+    path: PathBuf,
+    templates: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    // A missing lockfile is empty
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let templates = if path.exists() {
+            let value: Value = from_reader(
+                File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?,
+            )
+            .with_context(|| format!("Invalid yaml in {}", path.display()))?;
+            match value.get("templates") {
+                Some(templates) => serde_json::from_value(serde_json::to_value(templates)?)
+                    .context("Expected `templates` to map template URLs to commits")?,
+                None => BTreeMap::new(),
+            }
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self { path, templates })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        to_writer(
+            File::create(&self.path).context("Failed to write lockfile")?,
+            &serde_json::json!({ "templates": self.templates }),
+        )
+        .context("Failed to write lockfile")
+    }
+
+    pub fn get(&self, url: &str) -> Option<&str> {
+        self.templates.get(url).map(String::as_str)
+    }
+
+    pub fn set(&mut self, url: impl Into<String>, commit: impl Into<String>) {
+        self.templates.insert(url.into(), commit.into());
+    }
+
+    pub fn templates(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.templates.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
@@ -1,36 +1,965 @@
-mod git;
-use std::{fs::create_dir_all, path::{Path, PathBuf}};
+use std::{collections::BTreeMap, fs::File, io::IsTerminal, path::PathBuf, time::Duration};
 
-use generator::{generate, DIRECTORIES};
-use git::Repo;
+use generator::{
+    adopt, apply_with, ask, cached, cached_templates, completions, digest, fetch, fetch_index,
+    fetch_pinned, generate_batch, graph, info, init, metadata, pick, project_name, publish, quiet,
+    render_file, resolve, resume, smoke, template_names, test_template, undo, validate,
+    variable_warnings, wizard, Config, GraphFormat, Lockfile, Manifest, Metadata, Options,
+    PublishOptions, Registry, Repo, Shell, Style, TemplateSource, Vcs, Warning, CACHE_DIR_ENV,
+    CONFIG_DIR_ENV, DEFAULT_TIMEOUT, GIT_CONFIG_ENV, LOCKFILE, NO_COLOR_ENV, QUIET_ENV,
+};
 
 use anyhow::*;
-fn main() -> Result<()> {
-    let (template, destination): (String, PathBuf) = {
-        let mut args = std::env::args().skip(1);
-        (
-            args.next().context("Missing template URL")?,
-            args.next().context("Missing destination path")?.into(),
+
+const USAGE: &str = "\
+Usage: generator [global options] [subcommand] <template> [destination] [options]
+
+Subcommands:
+  adopt, alias, apply, batch, cache, completions, config, digest, graph, info, init, publish,
+  render, resume, search, smoke, test, tui, undo, update-lock, validate
+
+Global options:
+  --config <dir>          Read defaults and the registry from <dir>
+  --cache-dir <dir>       Keep fetched templates in <dir>
+  --git-config <key=val>  Set git configuration for every git command run
+  -q, --quiet             Print only the output a command is for
+  --no-color              Print without colors
+
+Generation options:
+  --profile <name>        `fast`, `safe` or `default` for how generation runs, any other name
+                          for the defaults in defaults.<name>.yml. Give it twice for both
+  --answers <file>        Take variables from a YAML or JSON file
+  --allow <warning>       Leave out warnings of this kind
+  -y, --yes               Generate without asking to confirm the files first
+  --conflict <action>     `overwrite`, `keep` or `error` for files that exist already
+  --vcs <vcs>             `git` or `none`, over what the template asks for
+  --keep-vcs              Copy the template's .git directory, and so its history
+  --keep-partial          Keep what was written if generation fails, for `resume`
+  --strict                Render every file before writing any
+  --locked                Fetch templates at their commits in generator.lock
+  --no-cache              Fetch templates into a temporary directory
+  --prompt-timeout <s>    Give up waiting for an answer after <s> seconds
+";
+
+// Progress and results other than the output a command is for, which `--quiet` leaves out
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+// `--profile` takes both kinds of profile: `fast`, `safe` and `default` choose how generation
+// runs, and any other name the defaults profile of that name, so the two can be given together
+fn set_profile(options: &mut Options, name: String) {
+    match name.parse() {
+        std::result::Result::Ok(profile) => options.profile = profile,
+        Err(_) => options.defaults = Some(name),
+    }
+}
+
+fn graph_command(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut template = None;
+    let mut format = GraphFormat::Dot;
+    for arg in args {
+        match arg.as_str() {
+            "--dot" => format = GraphFormat::Dot,
+            "--mermaid" => format = GraphFormat::Mermaid,
+            _ if template.is_none() => template = Some(arg),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    let template = fetch(template.context("Missing template URL")?)?;
+    print!("{}", graph(template, format)?);
+    Ok(())
+}
+
+fn alias_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut registry = Registry::load()?;
+    match args.next().as_deref() {
+        Some("add") => {
+            let name = args.next().context("Missing alias name")?;
+            let url = args.next().context("Missing template URL")?;
+            registry.add(name, url);
+            registry.save()?;
+        }
+        Some("remove") => {
+            let name = args.next().context("Missing alias name")?;
+            ensure!(registry.remove(&name), "No alias named {}", name);
+            registry.save()?;
+        }
+        Some("list") | None => {
+            for (name, url) in registry.aliases() {
+                println!("{}\t{}", name, url);
+            }
+        }
+        Some(other) => bail!(
+            "Unknown alias command {}, expected add, list or remove",
+            other
+        ),
+    }
+    Ok(())
+}
+
+fn cache_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let command = args.next();
+    let template = args.next().context("Missing template URL")?;
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    let cached = cached(template)?;
+    match command.as_deref() {
+        Some("path") => println!("{}", cached.path.display()),
+        Some("show") => {
+            println!("Path: {}", cached.path.display());
+            if let Some(branch) = &cached.branch {
+                println!("Branch: {}", branch);
+            }
+            if let Some(commit) = &cached.commit {
+                println!("Commit: {}", commit);
+            }
+        }
+        Some(other) => bail!("Unknown cache command {}, expected path or show", other),
+        None => bail!("Missing cache command, expected path or show"),
+    }
+    Ok(())
+}
+
+fn display_value(value: &serde_yaml::Value) -> Result<String> {
+    Ok(match value {
+        serde_yaml::Value::String(s) => s.clone(),
+        other => serde_json::to_string(other)?,
+    })
+}
+
+fn config_command(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut args = args.peekable();
+    let profile = if args.peek().map(String::as_str) == Some("--profile") {
+        args.next();
+        Some(args.next().context("Missing value for --profile")?)
+    } else {
+        None
+    };
+    let mut config = Config::load_profile(profile.as_deref())?;
+    match args.next().as_deref() {
+        Some("get") => {
+            let name = args.next().context("Missing variable name")?;
+            let value = config
+                .get(&name)
+                .with_context(|| format!("No default set for {}", name))?;
+            println!("{}", display_value(value)?);
+        }
+        Some("set") => {
+            let name = args.next().context("Missing variable name")?;
+            let value = args.next().context("Missing value")?;
+            config.set(name, &value)?;
+            config.save()?;
+        }
+        Some("unset") => {
+            let name = args.next().context("Missing variable name")?;
+            ensure!(config.unset(&name), "No default set for {}", name);
+            config.save()?;
+        }
+        Some("list") | None => {
+            for (name, value) in &config.values() {
+                println!("{}\t{}", name, display_value(value)?);
+            }
+        }
+        Some(other) => bail!(
+            "Unknown config command {}, expected get, set, unset or list",
+            other
+        ),
+    }
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    Ok(())
+}
+
+fn info_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let template = fetch(args.next().context("Missing template URL")?)?;
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    print!("{}", info(template)?);
+    Ok(())
+}
+
+fn init_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let dir = args.next().context("Missing template directory")?;
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    init(&dir)?;
+    status!(
+        "Created template in {}, edit {}/template.yml to get started",
+        dir,
+        dir
+    );
+    Ok(())
+}
+
+fn validate_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let template = fetch(args.next().context("Missing template URL")?)?;
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    let issues = validate(&template)?;
+    for warning in variable_warnings(&template)? {
+        print_warning(&warning);
+    }
+    for issue in &issues {
+        eprintln!("{}", issue);
+    }
+    ensure!(
+        issues.is_empty(),
+        "Found {} problem(s) in template",
+        issues.len()
+    );
+    status!("{}", Style::Success.paint("Template is valid"));
+    Ok(())
+}
+
+fn test_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut template = None;
+    let mut min_coverage = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--min-coverage" => {
+                min_coverage = Some(
+                    args.next()
+                        .context("Missing value for --min-coverage")?
+                        .parse::<f64>()
+                        .context("Expected --min-coverage to be a percentage")?,
+                )
+            }
+            _ if template.is_none() => template = Some(arg),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    let template = fetch(template.context("Missing template URL")?)?;
+    let report = test_template(template)?;
+    ensure!(!report.cases.is_empty(), "Template has no test cases");
+    for case in &report.cases {
+        if case.passed() {
+            status!("test {} ... {}", case.name, Style::Success.paint("ok"));
+        } else {
+            println!("test {} ... {}", case.name, Style::Error.paint("FAILED"));
+            for failure in &case.failures {
+                println!("    {}", failure);
+            }
+        }
+    }
+    let coverage = report.coverage.percent();
+    status!("\n{} {:.1}%", Style::Heading.paint("Coverage:"), coverage);
+    for uncovered in report.coverage.uncovered() {
+        status!("    {}", uncovered);
+    }
+    let failed = report.cases.iter().filter(|o| !o.passed()).count();
+    ensure!(failed == 0, "{} test case(s) failed", failed);
+    if let Some(min) = min_coverage {
+        ensure!(
+            coverage >= min,
+            "Coverage {:.1}% is below the required {:.1}%",
+            coverage,
+            min
+        );
+    }
+    Ok(())
+}
+
+fn search_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let term = args.next().unwrap_or_default();
+    let indexes = Registry::load()?.indexes()?;
+    ensure!(
+        !indexes.is_empty(),
+        "No template indexes configured, add `indexes:` to {}",
+        Registry::path().display()
+    );
+    for index in indexes {
+        for entry in fetch_index(&index)?.iter().filter(|o| o.matches(&term)) {
+            print!("{}\t{}", entry.name, entry.url);
+            if let Some(description) = &entry.description {
+                print!("\t{}", description);
+            }
+            if !entry.tags.is_empty() {
+                print!("\t[{}]", entry.tags.join(", "));
+            }
+            println!();
+        }
+    }
+    Ok(())
+}
+
+fn publish_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut template = None;
+    let mut options = PublishOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--push" => options.push = true,
+            "--index" => {
+                options.index = Some(args.next().context("Missing value for --index")?.into())
+            }
+            _ if template.is_none() => template = Some(arg),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    let published = publish(template.as_deref().unwrap_or("."), &options)?;
+    match &published.url {
+        Some(url) if options.push => status!("Published {} to {}", published.tag, url),
+        _ => status!("Tagged {}", published.tag),
+    }
+    Ok(())
+}
+
+// Renders one file of a template to stdout, for iterating on it without generating a project
+fn render_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut positional = vec![];
+    let mut options = Options::default();
+    // Stands in for the destination directory, which `basename` and the names from it come from
+    let mut name = "project".to_owned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--answers" => options.answers.extend(read_answers(
+                &args.next().context("Missing value for --answers")?,
+            )?),
+            "--profile" => set_profile(
+                &mut options,
+                args.next().context("Missing value for --profile")?,
+            ),
+            "--name" => name = args.next().context("Missing value for --name")?,
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let template = positional.next().context("Missing template URL")?;
+    let file = positional.next().context("Missing file to render")?;
+    if let Some(arg) = positional.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    print!("{}", render_file(fetch(template)?, file, name, &options)?);
+    Ok(())
+}
+
+// Starts an existing project following a template, reporting where it differs from it
+fn adopt_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut positional = vec![];
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--answers" => options.answers.extend(read_answers(
+                &args.next().context("Missing value for --answers")?,
+            )?),
+            "--profile" => set_profile(
+                &mut options,
+                args.next().context("Missing value for --profile")?,
+            ),
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let template = positional.next().context("Missing template URL")?;
+    let destination = positional.next().context("Missing project directory")?;
+    if let Some(arg) = positional.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    options.remember = Some(match std::fs::canonicalize(&template) {
+        std::result::Result::Ok(path) => path.display().to_string(),
+        Err(_) => template.clone(),
+    });
+    let report = adopt(
+        fetch(template.clone())?,
+        options.remember.as_deref().unwrap_or(&template),
+        &destination,
+        &options,
+    )?;
+    for path in &report.modified {
+        status!("{} {}", Style::Warning.paint("modified:"), path.display());
+    }
+    for path in &report.missing {
+        status!("{} {}", Style::Error.paint("missing:"), path.display());
+    }
+    for path in &report.extra {
+        status!("{} {}", Style::Muted.paint("extra:"), path.display());
+    }
+    if report.drifted() {
+        status!(
+            "Adopted {}, {} file(s) match the template, {} differ and {} are missing",
+            destination,
+            report.matching.len(),
+            report.modified.len(),
+            report.missing.len()
+        );
+    } else {
+        status!(
+            "{}",
+            Style::Success.paint(format_args!(
+                "Adopted {}, which matches the template",
+                destination
+            ))
+        );
+    }
+    Ok(())
+}
+
+fn smoke_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut template = None;
+    let mut timeout = DEFAULT_TIMEOUT;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => {
+                timeout = Duration::from_secs(
+                    args.next()
+                        .context("Missing value for --timeout")?
+                        .parse()
+                        .context("Expected --timeout to be a number of seconds")?,
+                )
+            }
+            _ if template.is_none() => template = Some(arg),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    let template = fetch(template.context("Missing template URL")?)?;
+    smoke(template, timeout)?;
+    status!("{}", Style::Success.paint("Smoke test passed"));
+    Ok(())
+}
+
+fn resume_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let destination = args.next().context("Missing destination path")?;
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    let report = resume(destination, &Options::default())?;
+    for warning in &report.warnings {
+        print_warning(warning);
+    }
+    if !quiet() {
+        print!("{}", report.summary);
+    }
+    Ok(())
+}
+
+fn batch_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut positional = vec![];
+    let mut options = Options::default();
+    let mut lock = None;
+    let mut fresh = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--locked" => lock = Some(Lockfile::load(LOCKFILE)?),
+            "--no-cache" => fresh = Some(tempfile::tempdir()?),
+            "--allow" => options
+                .allow
+                .push(args.next().context("Missing value for --allow")?.parse()?),
+            "--profile" => set_profile(
+                &mut options,
+                args.next().context("Missing value for --profile")?,
+            ),
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let manifest = Manifest::load(positional.next().context("Missing manifest path")?)?;
+    let template = positional
+        .next()
+        .or(manifest.template)
+        .context("Missing template URL")?;
+    if let Some(arg) = positional.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    let template = fetch_pinned(template, lock.as_ref(), fresh.as_ref().map(|o| o.path()))?;
+    print_metadata(&metadata(&template)?);
+    let mut failed = 0;
+    for (destination, report) in generate_batch(&template, &manifest.entries, &options)? {
+        match report {
+            std::result::Result::Ok(report) => {
+                for warning in &report.warnings {
+                    print_warning(warning);
+                }
+                if report.vcs == Some(Vcs::Git) {
+                    Repo::init(&destination)?.commit("Initial commit")?;
+                }
+                status!(
+                    "{}",
+                    Style::Success.paint(format_args!("Generated {}", destination.display()))
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!(
+                    "{} {:#}",
+                    Style::Error.paint_err(format_args!(
+                        "Failed to generate {}:",
+                        destination.display()
+                    )),
+                    e
+                );
+            }
+        }
+    }
+    if failed > 0 {
+        bail!(
+            "{} of {} projects failed to generate",
+            failed,
+            manifest.entries.len()
         )
+    }
+    Ok(())
+}
+
+fn completions_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    match args.next().as_deref() {
+        Some("--templates") => {
+            for name in template_names()? {
+                println!("{}", name);
+            }
+        }
+        Some(shell) => print!("{}", completions(shell.parse::<Shell>()?)),
+        None => bail!("Missing shell, expected `bash`, `zsh` or `fish`"),
+    }
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    Ok(())
+}
+
+fn digest_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let template = fetch(args.next().context("Missing template URL")?)?;
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    println!("sha256:{}", digest(template)?);
+    Ok(())
+}
+
+fn update_lock_command(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut lock = Lockfile::load(LOCKFILE)?;
+    let mut templates = args.collect::<Vec<_>>();
+    if templates.is_empty() {
+        templates = lock.templates().map(|(url, _)| url.to_owned()).collect();
+    }
+    ensure!(
+        !templates.is_empty(),
+        "Missing template URL, and {} has no templates to update",
+        LOCKFILE
+    );
+    for template in templates {
+        let (source, _) = resolve(template)?;
+        let url = match &source {
+            TemplateSource::Git(url) => url.clone(),
+            _ => bail!(
+                "{:?} is not a git template, only git templates can be locked",
+                source
+            ),
+        };
+        let commit = Repo::open(source.fetch()?)?.head()?;
+        status!("Locked {} at {}", url, commit);
+        lock.set(url, commit);
+    }
+    lock.save()
+}
+
+fn undo_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let destination = args.next().context("Missing destination path")?;
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    let report = undo(&destination)?;
+    status!("Removed {} generated paths", report.removed.len());
+    for path in report.modified.iter().filter(|_| !quiet()) {
+        eprintln!(
+            "{} {}, which has changed since it was generated",
+            Style::Warning.paint_err("Kept"),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn print_warning(warning: &Warning) {
+    if quiet() {
+        return;
+    }
+    eprintln!(
+        "{} {}",
+        Style::Warning.paint_err(format_args!(
+            "warning[{}/{}]:",
+            warning.kind.code(),
+            warning.kind.name()
+        )),
+        warning.message
+    );
+}
+
+fn print_metadata(metadata: &Metadata) {
+    if quiet() {
+        return;
+    }
+    if let Some(name) = &metadata.name {
+        match &metadata.version {
+            Some(version) => println!("{} {}", Style::Heading.paint(name), version),
+            None => println!("{}", Style::Heading.paint(name)),
+        }
+        if let Some(description) = &metadata.description {
+            println!("  {}", Style::Muted.paint(description));
+        }
+        if let Some(author) = &metadata.author {
+            println!("  {}", Style::Muted.paint(format_args!("by {}", author)));
+        }
+    }
+}
+
+fn pick_template() -> Result<String> {
+    let mut candidates = Registry::load()?
+        .aliases()
+        .map(|(name, url)| (name.to_owned(), url.to_owned()))
+        .collect::<Vec<_>>();
+    for cached in cached_templates()? {
+        if !candidates.iter().any(|(_, url)| *url == cached) {
+            candidates.push((cached, String::new()));
+        }
+    }
+    ensure!(
+        !candidates.is_empty(),
+        "Missing template URL, and there are no aliases or cached templates to choose from"
+    );
+    pick("Templates", &candidates)?.context("Missing template URL")
+}
+
+fn ask_destination() -> Result<PathBuf> {
+    match ask("Destination path")? {
+        Some(line) if !line.trim().is_empty() => Ok(line.trim().into()),
+        _ => bail!("Missing destination path"),
+    }
+}
+
+// Variable values from a yaml or json file, or stdin given `-`
+fn read_answers(path: &str) -> Result<BTreeMap<String, tera::Value>> {
+    let value: serde_yaml::Value = if path == "-" {
+        serde_yaml::from_reader(std::io::stdin().lock()).context("Invalid answers on stdin")?
+    } else {
+        serde_yaml::from_reader(
+            File::open(path).with_context(|| format!("Failed to open {}", path))?,
+        )
+        .with_context(|| format!("Invalid answers in {}", path))?
+    };
+    if value.is_null() {
+        return Ok(BTreeMap::new());
+    }
+    serde_json::from_value(serde_json::to_value(value)?)
+        .context("Expected answers to map variable names to values")
+}
+
+// With `apply`, the template argument is several templates separated by `+`
+fn generate_command(mut args: impl Iterator<Item = String>, apply: bool) -> Result<()> {
+    let mut positional = vec![];
+    let mut vcs = None;
+    let mut lock = None;
+    // A temporary directory templates are cloned into instead of the cache, removed on return
+    let mut fresh = None;
+    let mut options = Options {
+        confirm: true,
+        ..Options::default()
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--allow" => options
+                .allow
+                .push(args.next().context("Missing value for --allow")?.parse()?),
+            "--yes" | "-y" => options.confirm = false,
+            "--locked" => lock = Some(Lockfile::load(LOCKFILE)?),
+            "--no-cache" => fresh = Some(tempfile::tempdir()?),
+            "--keep-vcs" => options.keep_vcs = true,
+            "--keep-partial" => options.keep_partial = true,
+            "--strict" => options.strict = true,
+            "--answers" => options.answers.extend(read_answers(
+                &args.next().context("Missing value for --answers")?,
+            )?),
+            "--conflict" => {
+                options.conflict = args
+                    .next()
+                    .context("Missing value for --conflict")?
+                    .parse()?
+            }
+            "--vcs" => vcs = Some(args.next().context("Missing value for --vcs")?.parse()?),
+            "--profile" => set_profile(
+                &mut options,
+                args.next().context("Missing value for --profile")?,
+            ),
+            "--prompt-timeout" => {
+                options.prompt_timeout = Some(Duration::from_secs(
+                    args.next()
+                        .context("Missing value for --prompt-timeout")?
+                        .parse()
+                        .context("Expected --prompt-timeout to be a number of seconds")?,
+                ))
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let interactive = std::io::stdin().is_terminal() && !quiet();
+    let mut positional = positional.into_iter();
+    let template = match positional.next() {
+        Some(template) => template,
+        None if interactive && !apply => pick_template()?,
+        None => bail!("Missing template URL"),
+    };
+    let destination = positional.next().map(PathBuf::from);
+    if let Some(arg) = positional.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    if destination.as_ref().is_some_and(|o| o.exists()) {
+        bail!("Destination path exists")
+    }
+    let templates = if apply {
+        template.split('+').map(str::to_owned).collect()
+    } else {
+        vec![template]
+    };
+    options.remember = Some(
+        templates
+            .iter()
+            .map(|o| match std::fs::canonicalize(o) {
+                std::result::Result::Ok(path) => path.display().to_string(),
+                Err(_) => o.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("+"),
+    );
+    let templates = templates
+        .into_iter()
+        .map(|o| fetch_pinned(o, lock.as_ref(), fresh.as_ref().map(|o| o.path())))
+        .collect::<Result<Vec<_>>>()?;
+    for template in &templates {
+        print_metadata(&metadata(template)?);
+    }
+    // Without a destination, the project is named by the template's project name variable
+    let destination = match destination {
+        Some(destination) => destination,
+        None => {
+            let template = templates.last().expect("At least one template is given");
+            match project_name(template, &options)? {
+                Some((name, value)) => {
+                    let directory = value
+                        .as_str()
+                        .context("Expected the project name to be a string")?
+                        .trim()
+                        .to_owned();
+                    ensure!(!directory.is_empty(), "Missing project name");
+                    options.answers.insert(name, value);
+                    PathBuf::from(directory)
+                }
+                None if interactive => ask_destination()?,
+                None => bail!("Missing destination path"),
+            }
+        }
     };
     if destination.exists() {
         bail!("Destination path exists")
     }
-    let caches = DIRECTORIES.cache_dir();
-    if !caches.exists() {
-        create_dir_all(&caches)?
+    let report = apply_with(&templates, &destination, &options)?;
+    for warning in &report.warnings {
+        print_warning(warning);
     }
-    let cached_path = caches.join(&template);
-    let template = if <str as AsRef<Path>>::as_ref(&template).exists() {
-        template.into()
-    } else {
-        if !cached_path.exists() {
-            Repo::clone(&template, &cached_path)?;
-        } else {
-            Repo::open(&cached_path)?.pull()?
+    if vcs.or(report.vcs) == Some(Vcs::Git) {
+        Repo::init(&destination)?.commit("Initial commit")?;
+    }
+    if !quiet() {
+        print!("{}", report.summary);
+    }
+    Ok(())
+}
+
+fn tui_command(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut positional = vec![];
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--answers" => options.answers.extend(read_answers(
+                &args.next().context("Missing value for --answers")?,
+            )?),
+            "--profile" => set_profile(
+                &mut options,
+                args.next().context("Missing value for --profile")?,
+            ),
+            _ => positional.push(arg),
         }
-        cached_path
+    }
+    ensure!(
+        !quiet(),
+        "tui asks for every variable, so can't be used with --quiet"
+    );
+    let mut positional = positional.into_iter();
+    let template = match positional.next() {
+        Some(template) => template,
+        None => pick_template()?,
+    };
+    let destination = match positional.next() {
+        Some(destination) => destination.into(),
+        None => ask_destination()?,
     };
-    generate(template, destination)?;
+    if let Some(arg) = positional.next() {
+        bail!("Unexpected argument {}", arg)
+    }
+    ensure!(!destination.exists(), "Destination path exists");
+    options.remember = Some(match std::fs::canonicalize(&template) {
+        std::result::Result::Ok(path) => path.display().to_string(),
+        Err(_) => template.clone(),
+    });
+    let report = wizard(fetch(template)?, &destination, &options)?;
+    for warning in &report.warnings {
+        print_warning(warning);
+    }
+    if report.vcs == Some(Vcs::Git) {
+        Repo::init(&destination)?.commit("Initial commit")?;
+    }
+    if !quiet() {
+        print!("{}", report.summary);
+    }
     Ok(())
 }
+
+fn run() -> Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    // Given before the subcommand, and passed on through the environment like the variables
+    // they stand for, so that anything generator runs sees them too
+    loop {
+        let variable = match args.peek().map(String::as_str) {
+            Some("--config") => CONFIG_DIR_ENV,
+            Some("--cache-dir") => CACHE_DIR_ENV,
+            Some("-q" | "--quiet") => {
+                args.next();
+                std::env::set_var(QUIET_ENV, "1");
+                continue;
+            }
+            Some("--no-color") => {
+                args.next();
+                std::env::set_var(NO_COLOR_ENV, "1");
+                continue;
+            }
+            Some("--git-config") => {
+                args.next();
+                let config = args.next().context("Missing key=value for --git-config")?;
+                ensure!(
+                    config.contains('='),
+                    "Expected --git-config to be key=value, got {}",
+                    config
+                );
+                let mut all = std::env::var(GIT_CONFIG_ENV).unwrap_or_default();
+                all.push_str(&config);
+                all.push('\n');
+                std::env::set_var(GIT_CONFIG_ENV, all);
+                continue;
+            }
+            _ => break,
+        };
+        let flag = args.next().expect("Peeked above");
+        let dir = args
+            .next()
+            .with_context(|| format!("Missing directory for {}", flag))?;
+        std::env::set_var(
+            variable,
+            std::fs::canonicalize(&dir).unwrap_or_else(|_| dir.into()),
+        );
+    }
+    match args.peek().map(String::as_str) {
+        Some("help" | "--help" | "-h") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Some("adopt") => {
+            args.next();
+            adopt_command(args)
+        }
+        Some("graph") => {
+            args.next();
+            graph_command(args)
+        }
+        Some("alias") => {
+            args.next();
+            alias_command(args)
+        }
+        Some("config") => {
+            args.next();
+            config_command(args)
+        }
+        Some("cache") => {
+            args.next();
+            cache_command(args)
+        }
+        Some("info") => {
+            args.next();
+            info_command(args)
+        }
+        Some("init") => {
+            args.next();
+            init_command(args)
+        }
+        Some("validate") => {
+            args.next();
+            validate_command(args)
+        }
+        Some("test") => {
+            args.next();
+            test_command(args)
+        }
+        Some("search") => {
+            args.next();
+            search_command(args)
+        }
+        Some("resume") => {
+            args.next();
+            resume_command(args)
+        }
+        Some("render") => {
+            args.next();
+            render_command(args)
+        }
+        Some("smoke") => {
+            args.next();
+            smoke_command(args)
+        }
+        Some("tui") => {
+            args.next();
+            tui_command(args)
+        }
+        Some("publish") => {
+            args.next();
+            publish_command(args)
+        }
+        Some("batch") => {
+            args.next();
+            batch_command(args)
+        }
+        Some("completions") => {
+            args.next();
+            completions_command(args)
+        }
+        Some("digest") => {
+            args.next();
+            digest_command(args)
+        }
+        Some("undo") => {
+            args.next();
+            undo_command(args)
+        }
+        Some("update-lock") => {
+            args.next();
+            update_lock_command(args)
+        }
+        Some("apply") => {
+            args.next();
+            generate_command(args, true)
+        }
+        _ => generate_command(args, false),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{} {:?}", Style::Error.paint_err("Error:"), e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,64 @@
+use tera::{Context, Map, Value};
+
+// Variables named by a dotted path, like `author.name`, are leaves of objects in the context,
+// so that templates use them as `{{ author.name }}`
+
+pub(crate) fn get<'a>(context: &'a Context, name: &str) -> Option<&'a Value> {
+    let mut parts = name.split('.');
+    let mut value = context.get(parts.next()?)?;
+    for part in parts {
+        value = value.as_object()?.get(part)?;
+    }
+    Some(value)
+}
+
+pub(crate) fn contains(context: &Context, name: &str) -> bool {
+    get(context, name).is_some()
+}
+
+// Sets the leaf at `name`, keeping the rest of the object it's in
+pub(crate) fn insert(context: &mut Context, name: &str, value: Value) {
+    match name.split_once('.') {
+        None => context.insert(name, &value),
+        Some((root, rest)) => {
+            let mut object = context.get(root).cloned().unwrap_or(Value::Null);
+            set(&mut object, rest, value);
+            context.insert(root, &object);
+        }
+    }
+}
+
+// Sets the leaf at `path` inside `object`, replacing anything in the way that isn't an object
+pub(crate) fn set(object: &mut Value, path: &str, value: Value) {
+    if !object.is_object() {
+        *object = Value::Object(Map::new());
+    }
+    let map = object.as_object_mut().expect("Just made an object");
+    match path.split_once('.') {
+        None => {
+            map.insert(path.to_owned(), value);
+        }
+        Some((key, rest)) => set(map.entry(key).or_insert(Value::Null), rest, value),
+    }
+}
+
+// Answers given by dotted path or as objects, either way merged into what's already there
+pub(crate) fn extend<'a>(
+    context: &mut Context,
+    answers: impl IntoIterator<Item = (&'a String, &'a Value)>,
+) {
+    for (name, value) in answers {
+        merge(context, name, value);
+    }
+}
+
+fn merge(context: &mut Context, name: &str, value: &Value) {
+    match (value, get(context, name)) {
+        (Value::Object(fields), Some(Value::Object(_))) => {
+            for (key, value) in fields {
+                merge(context, &format!("{}.{}", name, key), value);
+            }
+        }
+        _ => insert(context, name, value.clone()),
+    }
+}
@@ -0,0 +1,207 @@
+use std::{convert::TryFrom, path::Path};
+
+use anyhow::*;
+use serde_yaml::{Mapping, Value};
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::{Marker, TScalarStyle},
+};
+
+use crate::{WarningKind, Warnings};
+
+// The mode, and owner and group, a rule gives the paths it matches, rather than those of the
+// template's copy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Permissions {
+    pub mode: Option<u32>,
+    // User and group names, or numeric ids
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+// An octal mode, written as `0o755` or as a string like `"755"`, `"0755"` or `"0o755"`. Bare
+// decimals like `mode: 755` are refused by `check_yaml_modes` before this sees them, so numbers
+// here already have the value their author meant
+fn parse_mode(value: &Value) -> Result<u32> {
+    let mode = match value {
+        Value::String(s) => {
+            let digits = s.strip_prefix("0o").unwrap_or(s);
+            u32::from_str_radix(digits, 8)
+                .with_context(|| format!("Expected `mode` {} to be octal", s))?
+        }
+        Value::Number(n) => n
+            .as_u64()
+            .and_then(|o| u32::try_from(o).ok())
+            .with_context(|| format!("Invalid `mode` {}", n))?,
+        _ => bail!("Expected `mode` to be octal, like 0o755 or \"755\""),
+    };
+    ensure!(
+        mode <= 0o7777,
+        "Invalid `mode` {:o}, write it as 0o755 or \"755\"",
+        mode
+    );
+    Ok(mode)
+}
+
+// Finds `mode`s written as bare decimals, which YAML reads as decimal even though every mode
+// is octal: `mode: 755` would be 0o1363. Parsed, that can't be told from a deliberate number,
+// so the raw text is checked instead
+#[derive(Default)]
+struct DecimalModes {
+    // For each open mapping, whether a key is next and the last key, and `None` for sequences
+    stack: Vec<Option<(bool, Option<String>)>>,
+    found: Option<(usize, String)>,
+}
+
+impl DecimalModes {
+    // A value, or a key that's itself a collection, has been read in the enclosing mapping
+    fn advance(&mut self) {
+        if let Some(Some((key_next, key))) = self.stack.last_mut() {
+            if !*key_next {
+                *key = None;
+            }
+            *key_next = !*key_next;
+        }
+    }
+}
+
+impl MarkedEventReceiver for DecimalModes {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::MappingStart(_) => {
+                self.advance();
+                self.stack.push(Some((true, None)));
+            }
+            Event::SequenceStart(_) => {
+                self.advance();
+                self.stack.push(None);
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            Event::Scalar(value, style, _, _) => {
+                if let Some(Some((key_next, key))) = self.stack.last_mut() {
+                    if *key_next {
+                        *key = Some(value);
+                        *key_next = false;
+                        return;
+                    }
+                    let decimal = style == TScalarStyle::Plain
+                        && value.starts_with(|o: char| o.is_ascii_digit() && o != '0')
+                        && value.chars().all(|o| o.is_ascii_digit());
+                    if key.as_deref() == Some("mode") && decimal && self.found.is_none() {
+                        self.found = Some((mark.line(), value));
+                    }
+                }
+                self.advance();
+            }
+            Event::Alias(_) => self.advance(),
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn check_yaml_modes(source: &str) -> Result<()> {
+    let mut modes = DecimalModes::default();
+    // Invalid yaml is reported by the parse proper
+    if Parser::new(source.chars()).load(&mut modes, true).is_err() {
+        return Ok(());
+    }
+    match modes.found {
+        Some((line, mode)) => bail!(
+            "`mode: {}` on line {} would be read as decimal, write it as 0o{} or \"{}\"",
+            mode,
+            line,
+            mode,
+            mode
+        ),
+        None => Ok(()),
+    }
+}
+
+impl Permissions {
+    pub(crate) fn parse(rule: &Mapping) -> Result<Self> {
+        let name = |key: &str| -> Result<Option<String>> {
+            rule.get(&Value::String(key.to_owned()))
+                .map(|o| match o {
+                    Value::String(s) => Ok(s.clone()),
+                    Value::Number(n) if n.is_u64() => Ok(n.to_string()),
+                    _ => bail!("Expected `{}` to be a name or id", key),
+                })
+                .transpose()
+        };
+        Ok(Self {
+            mode: rule
+                .get(&Value::String("mode".to_owned()))
+                .map(parse_mode)
+                .transpose()?,
+            owner: name("owner")?,
+            group: name("group")?,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Changing the owner or group takes privileges generator usually runs without, so where
+    // they're refused it's warned about rather than failing generation
+    #[cfg(unix)]
+    pub(crate) fn apply(&self, path: &Path, warnings: &mut Warnings) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if self.owner.is_some() || self.group.is_some() {
+            let owner = self
+                .owner
+                .as_deref()
+                .map(|o| id("/etc/passwd", "user", o))
+                .transpose()?;
+            let group = self
+                .group
+                .as_deref()
+                .map(|o| id("/etc/group", "group", o))
+                .transpose()?;
+            match std::os::unix::fs::chown(path, owner, group) {
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => warnings.warn(
+                    WarningKind::Ownership,
+                    format!(
+                        "Couldn't change the owner of {}, which needs root",
+                        path.display()
+                    ),
+                ),
+                result => result
+                    .with_context(|| format!("Failed to change owner of {}", path.display()))?,
+            }
+        }
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to set mode of {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    // Windows has neither modes nor owners like these
+    #[cfg(not(unix))]
+    pub(crate) fn apply(&self, _path: &Path, _warnings: &mut Warnings) -> Result<()> {
+        Ok(())
+    }
+}
+
+// The id of a user or group, given by name or as a number, from `/etc/passwd` or `/etc/group`
+#[cfg(unix)]
+fn id(database: &str, kind: &str, name: &str) -> Result<u32> {
+    if let std::result::Result::Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    std::fs::read_to_string(database)
+        .with_context(|| format!("Failed to read {}", database))?
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split(':');
+            if fields.next()? != name {
+                return None;
+            }
+            fields.nth(1)?.parse().ok()
+        })
+        .with_context(|| format!("No {} named {}", kind, name))
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::*;
+use serde_json::{json, Value};
+use tera::Tera;
+use wasmi::{
+    core::ValType, Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+// How much one call may run, roughly in instructions, so that a plugin stuck in a loop fails the
+// generation rather than hanging it
+const FUEL: u64 = 100_000_000;
+// How large a plugin's memory may grow
+const MEMORY: usize = 64 << 20;
+
+// A WASM module shipped with a template, giving its files filters and functions and checking the
+// values it's generated with. Plugins are given nothing to import, so they can't reach files,
+// the network or the clock, and each call is limited in how long it runs and the memory it takes.
+//
+// Plugins export `memory`, and `alloc(len) -> ptr` for the input of each call to be written to.
+// `filter_<name>`, `function_<name>` and `validate` take the input's `(ptr, len)` and return its
+// output's as `ptr << 32 | len`. Input and output are JSON: filters get `{"value", "args"}`,
+// functions their arguments and `validate` every variable, and each gives back `{"ok": value}`
+// or `{"error": message}`. An exported `dealloc(ptr, len)` is given each output once it's read.
+pub(crate) struct Plugin {
+    name: String,
+    state: Mutex<State>,
+    filters: Vec<String>,
+    functions: Vec<String>,
+    validates: bool,
+}
+
+struct State {
+    store: Store<StoreLimits>,
+    instance: Instance,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugin").field("name", &self.name).finish()
+    }
+}
+
+impl Plugin {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let name = path.display().to_string();
+        let wasm =
+            std::fs::read(path).with_context(|| format!("Failed to read plugin {}", name))?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &wasm[..])
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Invalid WASM in plugin {}", name))?;
+        if let Some(import) = module.imports().next() {
+            bail!(
+                "Plugin {} imports `{}.{}`, but plugins can't import anything",
+                name,
+                import.module(),
+                import.name()
+            );
+        }
+        let mut exports = vec![];
+        for export in module.exports() {
+            let ty = match export.ty().func() {
+                Some(ty) => ty,
+                None => continue,
+            };
+            let export_name = export.name();
+            if export_name.starts_with("filter_")
+                || export_name.starts_with("function_")
+                || export_name == "validate"
+            {
+                ensure!(
+                    ty.params() == [ValType::I32, ValType::I32] && ty.results() == [ValType::I64],
+                    "Expected `{}` of plugin {} to take (i32, i32) and return i64",
+                    export_name,
+                    name
+                );
+                exports.push(export_name.to_owned());
+            }
+        }
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MEMORY)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        // For its start function, if it has one
+        store.set_fuel(FUEL).map_err(|e| anyhow!("{}", e))?;
+        let instance = Linker::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|o| o.start(&mut store))
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Failed to start plugin {}", name))?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .with_context(|| format!("Expected plugin {} to export `memory`", name))?;
+        let alloc = instance
+            .get_typed_func(&store, "alloc")
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Expected plugin {} to export `alloc(i32) -> i32`", name))?;
+        let dealloc = instance.get_typed_func(&store, "dealloc").ok();
+        Ok(Plugin {
+            filters: exports
+                .iter()
+                .filter_map(|o| o.strip_prefix("filter_").map(str::to_owned))
+                .collect(),
+            functions: exports
+                .iter()
+                .filter_map(|o| o.strip_prefix("function_").map(str::to_owned))
+                .collect(),
+            validates: exports.iter().any(|o| o == "validate"),
+            name,
+            state: Mutex::new(State {
+                store,
+                instance,
+                memory,
+                alloc,
+                dealloc,
+            }),
+        })
+    }
+
+    // Calls one of the plugin's exports, as described above
+    fn call(&self, export: &str, input: &Value) -> Result<Value> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let State {
+            store,
+            instance,
+            memory,
+            alloc,
+            dealloc,
+        } = &mut *state;
+        let wasm_error = |e: &dyn fmt::Display| anyhow!("{}", e);
+        store.set_fuel(FUEL).map_err(|e| wasm_error(&e))?;
+        let input = serde_json::to_vec(input)?;
+        let len = i32::try_from(input.len()).context("Input too large for plugin")?;
+        let ptr = alloc
+            .call(&mut *store, len)
+            .map_err(|e| wasm_error(&e))
+            .context("`alloc` failed")?;
+        memory
+            .write(&mut *store, ptr as u32 as usize, &input)
+            .map_err(|e| wasm_error(&e))
+            .context("`alloc` gave memory out of bounds")?;
+        let packed = instance
+            .get_typed_func::<(i32, i32), i64>(&*store, export)
+            .and_then(|o| o.call(&mut *store, (ptr, len)))
+            .map_err(|e| wasm_error(&e))? as u64;
+        let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        // Checked before allocating, so that a bad length can't take more than the plugin has
+        ensure!(
+            ptr.checked_add(len)
+                .is_some_and(|end| end <= memory.data(&*store).len()),
+            "Output is out of bounds"
+        );
+        let mut output = vec![0; len];
+        memory
+            .read(&*store, ptr, &mut output)
+            .map_err(|e| wasm_error(&e))
+            .context("Output is out of bounds")?;
+        if let Some(dealloc) = dealloc {
+            dealloc
+                .call(&mut *store, (ptr as i32, len as i32))
+                .map_err(|e| wasm_error(&e))
+                .context("`dealloc` failed")?;
+        }
+        let output = serde_json::from_slice::<Value>(&output).context("Invalid JSON output")?;
+        match (output.get("ok"), output.get("error")) {
+            (Some(value), None) => Ok(value.clone()),
+            (None, Some(Value::String(error))) => bail!("{}", error),
+            _ => bail!("Expected output to be `{{\"ok\": value}}` or `{{\"error\": message}}`"),
+        }
+    }
+
+    // Gives `tera` the plugin's filters and functions
+    pub(crate) fn register(self: &Arc<Self>, tera: &mut Tera) {
+        for name in &self.filters {
+            let (plugin, export) = (self.clone(), format!("filter_{}", name));
+            tera.register_filter(name, move |value: &Value, args: &HashMap<String, Value>| {
+                plugin
+                    .call(&export, &json!({ "value": value, "args": args }))
+                    .map_err(|e| plugin.error(&export, e))
+            });
+        }
+        for name in &self.functions {
+            let (plugin, export) = (self.clone(), format!("function_{}", name));
+            tera.register_function(name, move |args: &HashMap<String, Value>| {
+                plugin
+                    .call(&export, &json!(args))
+                    .map_err(|e| plugin.error(&export, e))
+            });
+        }
+    }
+
+    fn error(&self, export: &str, e: Error) -> tera::Error {
+        tera::Error::msg(format!("`{}` of plugin {}: {:#}", export, self.name, e))
+    }
+
+    // Checks the values a project is about to be generated with, failing with the plugin's
+    // message for them
+    pub(crate) fn validate(&self, context: &tera::Context) -> Result<()> {
+        if self.validates {
+            self.call("validate", &context.clone().into_json())
+                .with_context(|| format!("Plugin {} rejected the values given", self.name))?;
+        }
+        Ok(())
+    }
+}
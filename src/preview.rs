@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use crate::{EntryKind, PlannedEntry, Style};
+
+// Directories with more files than this directly inside them are summarised instead of listed
+const COLLAPSE_AFTER: usize = 8;
+
+#[derive(Default)]
+struct Node {
+    kind: Option<EntryKind>,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn insert(&mut self, path: &Path, kind: EntryKind) {
+        let node = path.iter().fold(self, |node, part| {
+            node.children
+                .entry(part.to_string_lossy().into_owned())
+                .or_default()
+        });
+        node.kind = Some(kind);
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(self.kind, None | Some(EntryKind::Directory))
+    }
+
+    fn counts(&self) -> (usize, usize) {
+        self.children
+            .values()
+            .fold((0, 0), |(t, c), o| match o.kind {
+                Some(EntryKind::Templated) => (t + 1, c),
+                Some(EntryKind::Copied) => (t, c + 1),
+                _ => {
+                    let (ot, oc) = o.counts();
+                    (t + ot, c + oc)
+                }
+            })
+    }
+
+    fn render(&self, prefix: &str, out: &mut String) {
+        let (dirs, files): (Vec<_>, Vec<_>) = self.children.iter().partition(|(_, o)| o.is_dir());
+        let collapse = files.len() > COLLAPSE_AFTER;
+        let mut lines: Vec<(String, Option<&Node>)> = dirs
+            .iter()
+            .map(|(name, node)| {
+                let (t, c) = node.counts();
+                (format!("{}/ ({} files)", name, t + c), Some(*node))
+            })
+            .collect();
+        if collapse {
+            let templated = files
+                .iter()
+                .filter(|(_, o)| o.kind == Some(EntryKind::Templated))
+                .count();
+            lines.push((
+                format!(
+                    "... {} files ({} templated, {} copied)",
+                    files.len(),
+                    templated,
+                    files.len() - templated
+                ),
+                None,
+            ));
+        } else {
+            lines.extend(files.iter().map(|(name, node)| {
+                let marker = match node.kind {
+                    Some(EntryKind::Templated) => "[T]",
+                    _ => "[C]",
+                };
+                (format!("{} {}", Style::Muted.paint(marker), name), None)
+            }));
+        }
+        let count = lines.len();
+        for (i, (line, node)) in lines.into_iter().enumerate() {
+            let last = i + 1 == count;
+            writeln!(
+                out,
+                "{}{}{}",
+                prefix,
+                if last { "└── " } else { "├── " },
+                line
+            )
+            .unwrap();
+            if let Some(node) = node {
+                node.render(
+                    &format!("{}{}", prefix, if last { "    " } else { "│   " }),
+                    out,
+                );
+            }
+        }
+    }
+}
+
+pub(crate) fn variables(values: &[(&str, &tera::Value)]) -> String {
+    if values.is_empty() {
+        return String::new();
+    }
+    let width = values.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut out = format!("{}\n", Style::Heading.paint("Variables:"));
+    for (name, value) in values {
+        let value = match value {
+            tera::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        writeln!(
+            out,
+            "  {:width$} → {}",
+            Style::Name.paint(name),
+            value,
+            width = width
+        )
+        .unwrap();
+    }
+    out.push('\n');
+    out
+}
+
+pub(crate) fn tree(destination: &Path, plan: &[PlannedEntry]) -> String {
+    let mut root = Node::default();
+    for entry in plan {
+        root.insert(&entry.destination, entry.kind);
+    }
+    let (t, c) = root.counts();
+    let mut out = format!(
+        "{} ({} files: {} templated [T], {} copied [C])\n",
+        Style::Heading.paint(format_args!("{}/", destination.display())),
+        t + c,
+        t,
+        c
+    );
+    root.render("", &mut out);
+    out
+}
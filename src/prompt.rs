@@ -0,0 +1,318 @@
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    process::Command,
+    sync::{
+        mpsc::{channel, Receiver, RecvError, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use thiserror::Error;
+
+use crate::{quiet, Style};
+
+#[derive(Debug, Error)]
+pub enum PromptError {
+    #[error("Variable {0} missing and stdin has no more input")]
+    Eof(String),
+    #[error("Variable {0} missing and prompting is disabled")]
+    Disabled(String),
+    #[error("Variable {0} missing and no answer was given within {1:?}")]
+    Timeout(String, Duration),
+    #[error("Failed to prompt for variable {0}")]
+    Io(String, #[source] std::io::Error),
+    #[error("Editor for variable {0} exited with {1}")]
+    Editor(String, std::process::ExitStatus),
+}
+
+impl PromptError {
+    pub fn variable(&self) -> &str {
+        match self {
+            PromptError::Eof(v)
+            | PromptError::Disabled(v)
+            | PromptError::Timeout(v, _)
+            | PromptError::Io(v, _)
+            | PromptError::Editor(v, _) => v,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, PromptError::Io(_, e) if e.kind() == std::io::ErrorKind::Interrupted)
+    }
+}
+
+// Lines are read on a separate thread so that a prompt can give up waiting without losing
+// an answer that arrives later, which is then used for the next prompt. A line is only read
+// once asked for, so that nothing is waiting on the terminal while an editor has it
+struct Lines {
+    requests: Sender<()>,
+    lines: Receiver<std::io::Result<String>>,
+    // Whether the reader is already reading a line no prompt has received yet
+    pending: bool,
+}
+
+impl Lines {
+    fn request(&mut self) {
+        if !self.pending {
+            let _ = self.requests.send(());
+            self.pending = true;
+        }
+    }
+
+    fn recv(&mut self) -> Result<std::io::Result<String>, RecvError> {
+        self.request();
+        let line = self.lines.recv()?;
+        self.pending = false;
+        Ok(line)
+    }
+
+    fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<std::io::Result<String>, RecvTimeoutError> {
+        self.request();
+        let line = self.lines.recv_timeout(timeout)?;
+        self.pending = false;
+        Ok(line)
+    }
+}
+
+static STDIN_LINES: Lazy<Mutex<Lines>> = Lazy::new(|| {
+    let (requests, requested) = channel();
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let mut lines = std::io::stdin().lock().lines();
+        for () in requested {
+            let line = match lines.next() {
+                Some(line) => line,
+                None => break,
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    Mutex::new(Lines {
+        requests,
+        lines: rx,
+        pending: false,
+    })
+});
+
+// Heads the prompts for a section of variables
+pub(crate) fn section(name: &str) {
+    if std::io::stdin().is_terminal() && !quiet() {
+        eprintln!(
+            "\n{}\n{}",
+            Style::Heading.paint_err(name),
+            Style::Muted.paint_err("-".repeat(name.chars().count()))
+        );
+    }
+}
+
+// What a variable is for and the values it allows, ahead of asking for it
+pub(crate) fn describe(description: Option<&str>, choices: &[String]) {
+    if std::io::stdin().is_terminal() && !quiet() {
+        if let Some(description) = description {
+            eprintln!("{}", Style::Muted.paint_err(description));
+        }
+        if !choices.is_empty() {
+            eprintln!(
+                "{}",
+                Style::Muted.paint_err(format!("One of {}", choices.join(", ")))
+            );
+        }
+    }
+}
+
+pub(crate) fn prompt(
+    variable: &str,
+    default: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<String, PromptError> {
+    let io_error = |e| PromptError::Io(variable.to_owned(), e);
+    // Piped answers are still read when quiet, but there's no one at a terminal to ask
+    if std::io::stdin().is_terminal() && quiet() {
+        return Err(PromptError::Disabled(variable.to_owned()));
+    }
+    // Piped stdin is read one answer per line, without echoing prompts into the pipeline
+    if std::io::stdin().is_terminal() {
+        match default {
+            Some(default) => eprint!(
+                "Variable {} missing - value? {} ",
+                Style::Name.paint_err(variable),
+                Style::Muted.paint_err(format_args!("[{}]", default))
+            ),
+            None => eprint!(
+                "Variable {} missing - value? ",
+                Style::Name.paint_err(variable)
+            ),
+        }
+        std::io::stderr().flush().map_err(io_error)?;
+    }
+    let mut lines = STDIN_LINES.lock().expect("stdin reader poisoned");
+    let line = match timeout {
+        Some(timeout) => match lines.recv_timeout(timeout) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(PromptError::Eof(variable.to_owned()))
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if std::io::stdin().is_terminal() {
+                    eprintln!();
+                }
+                return default
+                    .map(str::to_owned)
+                    .ok_or_else(|| PromptError::Timeout(variable.to_owned(), timeout));
+            }
+        },
+        None => lines
+            .recv()
+            .map_err(|_| PromptError::Eof(variable.to_owned()))?,
+    };
+    let line = line.map_err(io_error)?;
+    let line = line.trim_end_matches('\r');
+    Ok(match default {
+        Some(default) if line.is_empty() => default.to_owned(),
+        // Nothing typed at a terminal is most likely an accidental enter, so ask again
+        None if line.is_empty() && std::io::stdin().is_terminal() => {
+            drop(lines);
+            return prompt(variable, default, timeout);
+        }
+        _ => line.to_owned(),
+    })
+}
+
+// Multi-line input written in `$VISUAL` or `$EDITOR`, starting from `default`. Without a
+// terminal to run an editor in, it's read from stdin as a line like any other answer
+pub(crate) fn editor(
+    variable: &str,
+    default: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<String, PromptError> {
+    if !std::io::stdin().is_terminal() || quiet() {
+        return prompt(variable, default, timeout);
+    }
+    let io_error = |e| PromptError::Io(variable.to_owned(), e);
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|o| !o.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_owned());
+    let file = tempfile::Builder::new()
+        .prefix("generator-")
+        .suffix(".txt")
+        .tempfile()
+        .map_err(io_error)?;
+    std::fs::write(file.path(), default.unwrap_or_default()).map_err(io_error)?;
+    eprintln!(
+        "Variable {} missing - editing in {}",
+        Style::Name.paint_err(variable),
+        editor
+    );
+    // Run by the shell, as editors are often given with arguments like `code --wait`
+    let status = if cfg!(windows) {
+        Command::new("cmd")
+            .arg("/C")
+            .arg(&editor)
+            .arg(file.path())
+            .status()
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(file.path())
+            .status()
+    }
+    .map_err(io_error)?;
+    if !status.success() {
+        return Err(PromptError::Editor(variable.to_owned(), status));
+    }
+    let text = std::fs::read_to_string(file.path()).map_err(io_error)?;
+    let text = text.trim_end_matches(['\n', '\r']);
+    Ok(match default {
+        Some(default) if text.is_empty() => default.to_owned(),
+        _ => text.to_owned(),
+    })
+}
+
+pub(crate) fn confirm(question: &str) -> std::io::Result<bool> {
+    eprint!("{} {} ", question, Style::Muted.paint_err("[Y/n]"));
+    std::io::stderr().flush()?;
+    let answer = match STDIN_LINES.lock().expect("stdin reader poisoned").recv() {
+        Ok(line) => line?,
+        Err(_) => return Ok(false),
+    };
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+// A line of input answering `question`, or `None` at end of input
+pub fn ask(question: &str) -> std::io::Result<Option<String>> {
+    eprint!("{}: ", question);
+    std::io::stderr().flush()?;
+    match STDIN_LINES.lock().expect("stdin reader poisoned").recv() {
+        Ok(line) => line.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+// Whether the characters of `filter` appear in order in `candidate`, ignoring case
+fn fuzzy_matches(filter: &str, candidate: &str) -> bool {
+    let mut candidate = candidate.chars().flat_map(char::to_lowercase);
+    filter
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|c| candidate.any(|o| o == c))
+}
+
+// Asks the user to choose one of `candidates`, narrowing them down by typing a filter. Each
+// candidate is a value and a description shown beside it. Returns `None` at end of input.
+pub fn pick(title: &str, candidates: &[(String, String)]) -> std::io::Result<Option<String>> {
+    let mut filter = String::new();
+    loop {
+        let matching = candidates
+            .iter()
+            .filter(|(value, description)| {
+                fuzzy_matches(&filter, &format!("{} {}", value, description))
+            })
+            .collect::<Vec<_>>();
+        eprintln!("{}", Style::Heading.paint_err(format_args!("{}:", title)));
+        for (i, (value, description)) in matching.iter().enumerate() {
+            if description.is_empty() {
+                eprintln!("  {}) {}", i + 1, Style::Name.paint_err(value));
+            } else {
+                eprintln!(
+                    "  {}) {}\t{}",
+                    i + 1,
+                    Style::Name.paint_err(value),
+                    Style::Muted.paint_err(description)
+                );
+            }
+        }
+        if matching.is_empty() {
+            eprintln!(
+                "  {}",
+                Style::Muted.paint_err(format_args!("(nothing matches `{}`)", filter))
+            );
+        }
+        eprint!("Number to choose, or text to filter by: ");
+        std::io::stderr().flush()?;
+        let line = match STDIN_LINES.lock().expect("stdin reader poisoned").recv() {
+            Ok(line) => line?,
+            Err(_) => return Ok(None),
+        };
+        let line = line.trim();
+        match line.parse::<usize>() {
+            Ok(n) if (1..=matching.len()).contains(&n) => {
+                return Ok(Some(matching[n - 1].0.clone()))
+            }
+            _ => filter = line.to_owned(),
+        }
+    }
+}
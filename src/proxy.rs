@@ -0,0 +1,45 @@
+use anyhow::*;
+
+// Read in either case, as curl and so git read them, so that archives and indexes go through the
+// same proxy as clones
+fn var(name: &str) -> Option<String> {
+    std::env::var(name.to_lowercase())
+        .or_else(|_| std::env::var(name.to_uppercase()))
+        .ok()
+        .filter(|o| !o.trim().is_empty())
+}
+
+// Whether `NO_PROXY` lists the host, or a domain it's in, or is `*`
+fn bypassed(host: &str) -> bool {
+    var("no_proxy").is_some_and(|list| {
+        list.split(',')
+            .map(|o| o.trim().trim_start_matches('.'))
+            .filter(|o| !o.is_empty())
+            .any(|o| o == "*" || host == o || host.ends_with(&format!(".{}", o)))
+    })
+}
+
+// The proxy from `HTTPS_PROXY`, `HTTP_PROXY` or `ALL_PROXY` to reach `url` through, if any
+pub(crate) fn for_url(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    if bypassed(host) {
+        return None;
+    }
+    match scheme {
+        "https" => var("https_proxy"),
+        "http" => var("http_proxy"),
+        _ => None,
+    }
+    .or_else(|| var("all_proxy"))
+}
+
+pub(crate) fn agent(url: &str) -> Result<ureq::Agent> {
+    let mut builder = ureq::AgentBuilder::new();
+    if let Some(proxy) = for_url(url) {
+        builder = builder
+            .proxy(ureq::Proxy::new(&proxy).with_context(|| format!("Invalid proxy {}", proxy))?);
+    }
+    Ok(builder.build())
+}
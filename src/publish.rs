@@ -0,0 +1,114 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use serde_yaml::{from_reader, to_writer, Mapping, Value};
+
+use crate::{load_definition, validate, Repo};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublishOptions {
+    // Push the current branch and the new tag to `origin`
+    pub push: bool,
+    // A local index file to add or update the template's entry in
+    pub index: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Published {
+    pub tag: String,
+    pub url: Option<String>,
+}
+
+// Validates a template and tags its `version`, refusing uncommitted changes so the tag is what
+// was checked
+pub fn publish(template: impl AsRef<Path>, options: &PublishOptions) -> Result<Published> {
+    let template = template.as_ref();
+    let issues = validate(template)?;
+    ensure!(
+        issues.is_empty(),
+        "Template has {} problem(s):\n{}",
+        issues.len(),
+        issues
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    let def = load_definition(template)?;
+    let version = def
+        .metadata
+        .version
+        .as_deref()
+        .context("Template has no `version` to publish")?;
+    semver::Version::parse(version)
+        .with_context(|| format!("Invalid template `version` {}", version))?;
+    let mut repo = Repo::open(template)?;
+    ensure!(
+        repo.is_clean()?,
+        "Template has uncommitted changes, commit them before publishing"
+    );
+    let tag = format!("v{}", version);
+    repo.tag(&tag, &format!("Release {}", version))?;
+    if options.push {
+        repo.push(&[&format!("refs/tags/{}", tag)])?;
+    }
+    let url = repo.remote_url().ok();
+    if let Some(index) = &options.index {
+        let url = url
+            .as_deref()
+            .context("Template has no `origin` remote to list in the index")?;
+        let name = def.metadata.name.clone().unwrap_or_else(|| {
+            template
+                .file_name()
+                .map(|o| o.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        update_index(index, &name, url, def.metadata.description.as_deref())?;
+    }
+    Ok(Published { tag, url })
+}
+
+// Replaces the url and description of the entry named `name`, keeping anything else it has like
+// `tags`, or adds one
+fn update_index(path: &Path, name: &str, url: &str, description: Option<&str>) -> Result<()> {
+    let mut index: Value = if path.exists() {
+        from_reader(File::open(path).with_context(|| format!("Failed to open {}", path.display()))?)
+            .with_context(|| format!("Invalid index {}", path.display()))?
+    } else {
+        Value::Mapping(Mapping::new())
+    };
+    let key = |k: &str| Value::String(k.to_owned());
+    let templates = index
+        .as_mapping_mut()
+        .context("Expected index to be a mapping at top level")?
+        .entry(key("templates"))
+        .or_insert_with(|| Value::Sequence(vec![]))
+        .as_sequence_mut()
+        .context("Expected `templates` to be a sequence")?;
+    let position = templates
+        .iter()
+        .position(|o| o.get("name").and_then(Value::as_str) == Some(name));
+    let entry = match position {
+        Some(i) => &mut templates[i],
+        None => {
+            templates.push(Value::Mapping(Mapping::new()));
+            templates.last_mut().expect("Just pushed")
+        }
+    };
+    let entry = entry
+        .as_mapping_mut()
+        .context("Expected index entries to be mappings")?;
+    entry.insert(key("name"), key(name));
+    entry.insert(key("url"), key(url));
+    if let Some(description) = description {
+        entry.insert(key("description"), key(description));
+    }
+    to_writer(
+        File::create(path).with_context(|| format!("Failed to write {}", path.display()))?,
+        &index,
+    )
+    .context("Failed to write index")
+}
@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use anyhow::*;
+use serde_yaml::{from_reader, Value};
+
+use crate::{index, parse_variables, VariableDef};
+
+// Variables from each of a template's `variables_from` fragments, in order, so that families of
+// templates can share their questions. A fragment is a sequence of variables, like `variables`,
+// or a mapping with one under `variables`, at a URL or a path relative to the template.
+pub(crate) fn load(template: &Path, sources: &[String]) -> Result<Vec<VariableDef>> {
+    let mut variables: Vec<VariableDef> = vec![];
+    for source in sources {
+        let location = if source.starts_with("https://") || source.starts_with("http://") {
+            source.clone()
+        } else {
+            template.join(source).display().to_string()
+        };
+        let value: Value = from_reader(index::open(&location)?)
+            .with_context(|| format!("Invalid yaml in variables from {}", source))?;
+        let fragment = match &value {
+            Value::Mapping(_) => value.get("variables"),
+            _ => Some(&value),
+        };
+        // Later fragments override earlier ones, as the template's own variables override them
+        for var in parse_variables(fragment)
+            .with_context(|| format!("Invalid variables from {}", source))?
+        {
+            match variables.iter_mut().find(|o| o.name == var.name) {
+                Some(existing) => *existing = var,
+                None => variables.push(var),
+            }
+        }
+    }
+    Ok(variables)
+}
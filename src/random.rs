@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::integrity::hex;
+
+// Characters of generated passwords, with symbols only where asked for, as some config formats
+// need them quoted
+const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const SYMBOLS: &[u8] = b"!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+// From the system's secure generator, as these end up as secrets in generated config
+fn random_bytes(n: usize) -> tera::Result<Vec<u8>> {
+    let mut bytes = vec![0; n];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "Failed to generate random bytes")?;
+    Ok(bytes)
+}
+
+fn count(
+    function: &str,
+    args: &HashMap<String, tera::Value>,
+    key: &str,
+    default: usize,
+) -> tera::Result<usize> {
+    match args.get(key) {
+        None => Ok(default),
+        Some(value) => value
+            .as_u64()
+            .filter(|o| (1..=4096).contains(o))
+            .map(|o| o as usize)
+            .ok_or_else(|| {
+                format!(
+                    "`{}` expects `{}` to be a number from 1 to 4096",
+                    function, key
+                )
+                .into()
+            }),
+    }
+}
+
+// A version 4 UUID, like `0b5e3c6a-1f0e-4c39-9d4a-2d1c5f3e8a7b`
+pub(crate) fn uuid(_args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let mut bytes = random_bytes(16)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex(&bytes);
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+    .into())
+}
+
+// `random_hex(bytes=16)`, that many random bytes in hex
+pub(crate) fn random_hex(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    Ok(hex(&random_bytes(count("random_hex", args, "bytes", 16)?)?).into())
+}
+
+// `random_password(length=24, symbols=false)`
+pub(crate) fn random_password(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let length = count("random_password", args, "length", 24)?;
+    let symbols = match args.get("symbols") {
+        None => false,
+        Some(tera::Value::Bool(b)) => *b,
+        Some(_) => return Err("`random_password` expects `symbols` to be a boolean".into()),
+    };
+    let mut characters = ALPHANUMERIC.to_vec();
+    if symbols {
+        characters.extend_from_slice(SYMBOLS);
+    }
+    // Bytes past the largest multiple of the character count are dropped, so that every
+    // character is as likely
+    let limit = 256 - 256 % characters.len();
+    let mut password = String::with_capacity(length);
+    while password.len() < length {
+        for byte in random_bytes(length)? {
+            if usize::from(byte) < limit && password.len() < length {
+                password.push(char::from(characters[usize::from(byte) % characters.len()]));
+            }
+        }
+    }
+    Ok(password.into())
+}
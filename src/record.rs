@@ -0,0 +1,136 @@
+use std::{
+    fs::{create_dir_all, read, remove_dir, remove_file, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use serde_yaml::{from_reader, to_writer, Value};
+
+use crate::{cache_dir, get_strings, integrity::sha256_hex, slash_path};
+
+// What the last generation into a destination created, so that it can be undone
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Record {
+    pub(crate) created_destination: bool,
+    // Relative paths, with a digest of the contents as generated
+    pub(crate) files: Vec<(PathBuf, String)>,
+    pub(crate) directories: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UndoReport {
+    pub removed: Vec<PathBuf>,
+    // Files changed since they were generated, which are left in place
+    pub modified: Vec<PathBuf>,
+}
+
+// Records are kept in the cache, by the destination's absolute path, rather than in the project
+fn path(destination: &Path) -> Result<PathBuf> {
+    let destination = std::fs::canonicalize(destination)
+        .with_context(|| format!("Failed to find {}", destination.display()))?;
+    let key = destination
+        .to_str()
+        .context("Destination is not a string")?;
+    Ok(cache_dir()
+        .join("records")
+        .join(format!("{}.yml", &sha256_hex(key.as_bytes())[..32])))
+}
+
+pub(crate) fn digest_file(path: &Path) -> Result<String> {
+    Ok(sha256_hex(&read(path).with_context(|| {
+        format!("Failed to read file {}", path.display())
+    })?))
+}
+
+impl Record {
+    pub(crate) fn save(&self, destination: &Path) -> Result<()> {
+        let path = path(destination)?;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let names = |paths: &mut dyn Iterator<Item = &PathBuf>| {
+            paths
+                .map(|o| slash_path(o).context("Filename is not a string"))
+                .collect::<Result<Vec<_>>>()
+        };
+        to_writer(
+            File::create(&path).context("Failed to write generation record")?,
+            &serde_json::json!({
+                "created_destination": self.created_destination,
+                "files": names(&mut self.files.iter().map(|(o, _)| o))?,
+                "digests": self.files.iter().map(|(_, o)| o).collect::<Vec<_>>(),
+                "directories": names(&mut self.directories.iter())?,
+            }),
+        )
+        .context("Failed to write generation record")
+    }
+
+    fn load(destination: &Path) -> Result<Self> {
+        let value: Value =
+            from_reader(File::open(path(destination)?).with_context(|| {
+                format!("No generation into {} to undo", destination.display())
+            })?)
+            .context("Invalid yaml in generation record")?;
+        let files = get_strings(&value, "files")?;
+        let digests = get_strings(&value, "digests")?;
+        ensure!(
+            files.len() == digests.len(),
+            "Generation record is missing file digests"
+        );
+        Ok(Self {
+            created_destination: value
+                .get("created_destination")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            files: files.into_iter().map(PathBuf::from).zip(digests).collect(),
+            directories: get_strings(&value, "directories")?
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+        })
+    }
+}
+
+// Drops the record of a generation into `destination`, for destinations that were only staging
+pub(crate) fn forget(destination: &Path) -> Result<()> {
+    let path = path(destination)?;
+    if path.exists() {
+        remove_file(path).context("Failed to remove generation record")?;
+    }
+    Ok(())
+}
+
+// Removes the files the last generation into `destination` created and that haven't changed
+// since, then the directories it created if that leaves them empty
+pub fn undo(destination: impl AsRef<Path>) -> Result<UndoReport> {
+    let destination = destination.as_ref();
+    let record_path = path(destination)?;
+    let record = Record::load(destination)?;
+    let mut report = UndoReport::default();
+    for (file, digest) in &record.files {
+        let path = destination.join(file);
+        if !path.exists() {
+            continue;
+        }
+        if digest_file(&path)? == *digest {
+            remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+            report.removed.push(file.clone());
+        } else {
+            report.modified.push(file.clone());
+        }
+    }
+    // Children before their parents
+    let mut directories = record.directories.clone();
+    directories.sort_by_key(|o| std::cmp::Reverse(o.components().count()));
+    for directory in directories {
+        if remove_dir(destination.join(&directory)).is_ok() {
+            report.removed.push(directory);
+        }
+    }
+    if record.created_destination {
+        // Fails, leaving it, if anything is left in it
+        let _ = remove_dir(destination);
+    }
+    remove_file(record_path).context("Failed to remove generation record")?;
+    Ok(report)
+}
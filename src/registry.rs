@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, fs::File, path::PathBuf, str::FromStr};
+
+use anyhow::*;
+use serde_yaml::{from_reader, to_writer, Mapping, Value};
+
+use crate::{config_dir, Sandbox};
+
+const BUILTIN_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("gh", "https://github.com/{}.git"),
+    ("gl", "https://gitlab.com/{}.git"),
+    ("bb", "https://bitbucket.org/{}.git"),
+];
+
+// Whether variables' `default_from_command` commands are run, configured as `command_defaults`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommandDefaults {
+    // Asked about each time, and not run without a terminal to ask at
+    #[default]
+    Ask,
+    Always,
+    Never,
+}
+
+impl FromStr for CommandDefaults {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ask" => Ok(CommandDefaults::Ask),
+            "always" => Ok(CommandDefaults::Always),
+            "never" => Ok(CommandDefaults::Never),
+            _ => bail!(
+                "Unknown `command_defaults` {}, expected `ask`, `always` or `never`",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Registry {
+    path: PathBuf,
+    aliases: BTreeMap<String, String>,
+    rest: Mapping,
+}
+
+impl Registry {
+    pub fn path() -> PathBuf {
+        config_dir().join("registry.yml")
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        let mut rest = if path.exists() {
+            match from_reader(File::open(&path).context("Failed to open registry")?)
+                .context("Invalid yaml in registry")?
+            {
+                Value::Mapping(m) => m,
+                Value::Null => Mapping::new(),
+                _ => bail!("Expected registry to be a mapping at top level"),
+            }
+        } else {
+            Mapping::new()
+        };
+        let aliases = match rest.remove(&Value::String("aliases".to_owned())) {
+            Some(Value::Mapping(m)) => m
+                .into_iter()
+                .map(|(k, v)| match (k, v) {
+                    (Value::String(k), Value::String(v)) => Ok((k, v)),
+                    (k, _) => bail!("Expected alias {:?} to map to a URL", k),
+                })
+                .collect::<Result<_>>()?,
+            None | Some(Value::Null) => BTreeMap::new(),
+            Some(_) => bail!("Expected `aliases` to be a mapping"),
+        };
+        Ok(Self {
+            path,
+            aliases,
+            rest,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut value = self.rest.clone();
+        value.insert(
+            Value::String("aliases".to_owned()),
+            Value::Mapping(
+                self.aliases
+                    .iter()
+                    .map(|(k, v)| (Value::String(k.clone()), Value::String(v.clone())))
+                    .collect(),
+            ),
+        );
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        to_writer(
+            File::create(&self.path).context("Failed to write registry")?,
+            &value,
+        )
+        .context("Failed to write registry")
+    }
+
+    pub fn indexes(&self) -> Result<Vec<String>> {
+        match self.rest.get(&Value::String("indexes".to_owned())) {
+            Some(Value::String(s)) => Ok(vec![s.clone()]),
+            Some(Value::Sequence(s)) => s
+                .iter()
+                .map(|o| {
+                    o.as_str()
+                        .map(str::to_owned)
+                        .context("Expected `indexes` to contain strings")
+                })
+                .collect(),
+            None => Ok(vec![]),
+            Some(_) => bail!("Expected `indexes` to be a string or sequence of strings"),
+        }
+    }
+
+    pub fn variable_set(&self, name: &str) -> Option<&Value> {
+        self.rest
+            .get(&Value::String("variable_sets".to_owned()))?
+            .get(name)
+    }
+
+    // Whether remote templates must have a signed HEAD commit or tag
+    pub fn require_signed(&self) -> Result<bool> {
+        match self.rest.get(&Value::String("require_signed".to_owned())) {
+            Some(value) => value
+                .as_bool()
+                .context("Expected `require_signed` to be a boolean"),
+            None => Ok(false),
+        }
+    }
+
+    // Whether to generate from a cached template that couldn't be updated for lack of network
+    pub fn offline_fallback(&self) -> Result<bool> {
+        match self.rest.get(&Value::String("offline_fallback".to_owned())) {
+            Some(value) => value
+                .as_bool()
+                .context("Expected `offline_fallback` to be a boolean"),
+            None => Ok(false),
+        }
+    }
+
+    // Whether to check out the submodules of git templates, which is the default
+    pub fn submodules(&self) -> Result<bool> {
+        match self.rest.get(&Value::String("submodules".to_owned())) {
+            Some(value) => value
+                .as_bool()
+                .context("Expected `submodules` to be a boolean"),
+            None => Ok(true),
+        }
+    }
+
+    pub fn command_defaults(&self) -> Result<CommandDefaults> {
+        match self.rest.get(&Value::String("command_defaults".to_owned())) {
+            Some(value) => value
+                .as_str()
+                .context("Expected `command_defaults` to be a string")?
+                .parse(),
+            None => Ok(CommandDefaults::default()),
+        }
+    }
+
+    pub fn sandbox(&self) -> Result<Sandbox> {
+        match self.rest.get(&Value::String("sandbox".to_owned())) {
+            Some(value) => Sandbox::parse(value),
+            None => Ok(Sandbox::default()),
+        }
+    }
+
+    // Expands `prefix:path` using the built-in or configured `abbreviations`, whose URLs hold `{}`
+    // where the path goes
+    pub fn expand(&self, template: &str) -> Result<Option<String>> {
+        let (prefix, path) = match template.split_once(':') {
+            Some((prefix, path)) if !path.starts_with("//") => (prefix, path),
+            _ => return Ok(None),
+        };
+        let configured = match self.rest.get(&Value::String("abbreviations".to_owned())) {
+            Some(Value::Mapping(m)) => m.get(&Value::String(prefix.to_owned())),
+            None | Some(Value::Null) => None,
+            Some(_) => bail!("Expected `abbreviations` to be a mapping"),
+        };
+        let url = match configured {
+            Some(url) => url
+                .as_str()
+                .context("Expected abbreviations to map to URLs")?,
+            None => match BUILTIN_ABBREVIATIONS.iter().find(|(o, _)| *o == prefix) {
+                Some((_, url)) => url,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(url.replace("{}", path)))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    pub fn aliases(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, url: impl Into<String>) {
+        self.aliases.insert(name.into(), url.into());
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+}
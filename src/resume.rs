@@ -0,0 +1,183 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{remove_file, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use serde_yaml::{from_reader, to_writer, Value};
+
+use crate::{fetch, get_string, get_strings, run, Options, Report};
+
+const PROGRESS_FILE: &str = ".generator-progress.yml";
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Progress {
+    // Paths, or for git templates the URL at the commit generated from
+    pub(crate) templates: Vec<String>,
+    pub(crate) answers: BTreeMap<String, tera::Value>,
+    pub(crate) completed: HashSet<PathBuf>,
+    // Settings of the interrupted generation, so resuming it generates what it would have
+    pub(crate) options: Options,
+}
+
+fn load_options(value: Option<&Value>) -> Result<Options> {
+    let value = match value {
+        Some(value) => value,
+        // Progress files from before options were saved
+        None => return Ok(Options::default()),
+    };
+    let flag = |key: &str| -> Result<bool> {
+        value
+            .get(key)
+            .map(|o| {
+                o.as_bool()
+                    .with_context(|| format!("Expected `options.{}` to be a boolean", key))
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    };
+    Ok(Options {
+        answers: match value.get("answers") {
+            Some(answers) => serde_json::from_value(serde_json::to_value(answers)?)
+                .context("Expected `options.answers` to be a mapping")?,
+            None => BTreeMap::new(),
+        },
+        ignore_user_defaults: flag("ignore_user_defaults")?,
+        ignore_environment: flag("ignore_environment")?,
+        allow: get_strings(value, "allow")?
+            .iter()
+            .map(|o| o.parse())
+            .collect::<Result<_>>()?,
+        profile: get_string(value, "profile")?
+            .map(|o| o.parse())
+            .transpose()?
+            .unwrap_or_default(),
+        remember: get_string(value, "remember")?,
+        conflict: get_string(value, "conflict")?
+            .map(|o| o.parse())
+            .transpose()?
+            .unwrap_or_default(),
+        keep_vcs: flag("keep_vcs")?,
+        keep_partial: flag("keep_partial")?,
+        strict: flag("strict")?,
+        defaults: get_string(value, "defaults")?,
+        ..Options::default()
+    })
+}
+
+impl Progress {
+    fn path(destination: &Path) -> PathBuf {
+        destination.join(PROGRESS_FILE)
+    }
+
+    pub(crate) fn load(destination: &Path) -> Result<Self> {
+        let path = Self::path(destination);
+        let value: Value = from_reader(File::open(&path).with_context(|| {
+            format!(
+                "No interrupted generation found in {}",
+                destination.display()
+            )
+        })?)
+        .context("Invalid yaml in progress file")?;
+        let templates = get_strings(&value, "templates")?;
+        ensure!(
+            !templates.is_empty(),
+            "Progress file is missing the templates"
+        );
+        let answers = match value.get("answers") {
+            Some(answers) => serde_json::from_value(serde_json::to_value(answers)?)
+                .context("Expected `answers` to be a mapping")?,
+            None => BTreeMap::new(),
+        };
+        let completed = match value.get("completed") {
+            Some(Value::Sequence(s)) => s
+                .iter()
+                .map(|o| {
+                    o.as_str()
+                        .map(PathBuf::from)
+                        .context("Expected `completed` to contain paths")
+                })
+                .collect::<Result<_>>()?,
+            None => HashSet::new(),
+            Some(_) => bail!("Expected `completed` to be a sequence"),
+        };
+        Ok(Self {
+            templates,
+            answers,
+            completed,
+            options: load_options(value.get("options"))?,
+        })
+    }
+
+    pub(crate) fn save(&self, destination: &Path) -> Result<()> {
+        let mut completed = self
+            .completed
+            .iter()
+            .map(|o| o.to_str().context("Filename is not a string"))
+            .collect::<Result<Vec<_>>>()?;
+        completed.sort_unstable();
+        let mut options = serde_json::json!({
+            "answers": self.options.answers,
+            "ignore_user_defaults": self.options.ignore_user_defaults,
+            "ignore_environment": self.options.ignore_environment,
+            "allow": self.options.allow.iter().map(|o| o.code()).collect::<Vec<_>>(),
+            "profile": self.options.profile.name(),
+            "conflict": self.options.conflict.name(),
+            "keep_vcs": self.options.keep_vcs,
+            "keep_partial": self.options.keep_partial,
+            "strict": self.options.strict,
+        });
+        if let Some(remember) = &self.options.remember {
+            options["remember"] = remember.as_str().into();
+        }
+        if let Some(defaults) = &self.options.defaults {
+            options["defaults"] = defaults.as_str().into();
+        }
+        to_writer(
+            File::create(Self::path(destination)).context("Failed to write progress file")?,
+            &serde_json::json!({
+                "templates": self.templates,
+                "answers": self.answers,
+                "completed": completed,
+                "options": options,
+            }),
+        )
+        .context("Failed to write progress file")
+    }
+
+    pub(crate) fn clear(destination: &Path) -> Result<()> {
+        let path = Self::path(destination);
+        if path.exists() {
+            remove_file(path).context("Failed to remove progress file")?;
+        }
+        Ok(())
+    }
+}
+
+// Continues with the settings the generation was started with, other than how to prompt, which
+// is `options`'s
+pub fn resume(destination: impl AsRef<Path>, options: &Options) -> Result<Report> {
+    let destination = destination.as_ref();
+    let progress = Progress::load(destination)?;
+    let options = Options {
+        missing: options.missing,
+        prompt_timeout: options.prompt_timeout,
+        confirm: options.confirm,
+        ..progress.options.clone()
+    };
+    let templates = progress
+        .templates
+        .iter()
+        .map(|o| fetch(o.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    run(
+        &templates
+            .iter()
+            .map(|o| o.to_path_buf())
+            .collect::<Vec<_>>(),
+        destination,
+        &options,
+        Some(progress),
+    )
+}
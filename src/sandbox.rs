@@ -0,0 +1,118 @@
+use std::{path::Path, process::Command, time::Duration};
+
+use anyhow::*;
+use once_cell::sync::Lazy;
+use serde_yaml::Value;
+
+// Variables commands are always given, with everything else in the environment left out
+const PASSED_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LANG",
+    "LC_ALL",
+    "TERM",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "SYSTEMROOT",
+    "COMSPEC",
+];
+
+// Whether commands can be cut off from the network, which needs unprivileged user namespaces
+static UNSHARE: Lazy<bool> = Lazy::new(|| {
+    cfg!(target_os = "linux")
+        && Command::new("unshare")
+            .args(["--net", "--map-root-user", "true"])
+            .output()
+            .is_ok_and(|o| o.status.success())
+});
+
+// How commands a template gives, like `verify`, are run, configured as `sandbox` in the registry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    // Run commands as they are, with the full environment and network
+    pub disabled: bool,
+    pub network: bool,
+    // Further environment variables to pass through
+    pub env: Vec<String>,
+    pub timeout: Option<Duration>,
+}
+
+impl Sandbox {
+    // `false` to disable it, or a mapping of `network`, `env` and `timeout` in seconds
+    pub(crate) fn parse(value: &Value) -> Result<Self> {
+        if let Some(enabled) = value.as_bool() {
+            return Ok(Self {
+                disabled: !enabled,
+                ..Self::default()
+            });
+        }
+        ensure!(
+            value.is_mapping(),
+            "Expected `sandbox` to be a boolean or mapping"
+        );
+        let env = match value.get("env") {
+            None => vec![],
+            Some(Value::Sequence(s)) => s
+                .iter()
+                .map(|o| o.as_str().map(str::to_owned))
+                .collect::<Option<_>>()
+                .context("Expected `sandbox.env` to be a sequence of strings")?,
+            Some(_) => bail!("Expected `sandbox.env` to be a sequence of strings"),
+        };
+        Ok(Self {
+            disabled: false,
+            network: value
+                .get("network")
+                .map(|o| {
+                    o.as_bool()
+                        .context("Expected `sandbox.network` to be a boolean")
+                })
+                .transpose()?
+                .unwrap_or(false),
+            env,
+            timeout: value
+                .get("timeout")
+                .map(|o| {
+                    o.as_u64()
+                        .map(Duration::from_secs)
+                        .context("Expected `sandbox.timeout` to be a number of seconds")
+                })
+                .transpose()?,
+        })
+    }
+
+    // A shell running `command` in `dir`, with a filtered environment and, where `unshare` can,
+    // no network
+    pub(crate) fn command(&self, command: &str, dir: &Path) -> Command {
+        let mut c = if cfg!(windows) {
+            let mut c = Command::new("cmd");
+            c.arg("/C").arg(command);
+            c
+        } else if !self.disabled && !self.network && *UNSHARE {
+            let mut c = Command::new("unshare");
+            c.args(["--net", "--map-root-user", "sh", "-c"])
+                .arg(command);
+            c
+        } else {
+            let mut c = Command::new("sh");
+            c.arg("-c").arg(command);
+            c
+        };
+        c.current_dir(dir);
+        if !self.disabled {
+            c.env_clear();
+            for name in PASSED_ENV
+                .iter()
+                .copied()
+                .chain(self.env.iter().map(String::as_str))
+            {
+                if let Some(value) = std::env::var_os(name) {
+                    c.env(name, value);
+                }
+            }
+        }
+        c
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, File, OpenOptions},
+    path::PathBuf,
+};
+
+use anyhow::*;
+use serde_yaml::{from_reader, to_writer};
+use tempfile::NamedTempFile;
+
+use crate::config_dir;
+
+type Answers = BTreeMap<String, tera::Value>;
+
+fn path() -> PathBuf {
+    config_dir().join("answers.yml")
+}
+
+fn load_all() -> Result<BTreeMap<String, Answers>> {
+    let path = path();
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let value: serde_yaml::Value = from_reader(File::open(&path)?)
+        .with_context(|| format!("Invalid yaml in {}", path.display()))?;
+    if value.is_null() {
+        return Ok(BTreeMap::new());
+    }
+    serde_json::from_value(serde_json::to_value(value)?).with_context(|| {
+        format!(
+            "Expected {} to map templates to their answers",
+            path.display()
+        )
+    })
+}
+
+pub(crate) fn load(template: &str) -> Result<Answers> {
+    Ok(load_all()?.remove(template).unwrap_or_default())
+}
+
+pub(crate) fn save(template: &str, answers: Answers) -> Result<()> {
+    let path = path();
+    let dir = path
+        .parent()
+        .expect("Saved answers are in the config directory");
+    create_dir_all(dir)?;
+    // Runs at once take turns, so that none loses the answers another saved
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("yml.lock"))
+        .context("Failed to lock saved answers")?;
+    lock.lock().context("Failed to lock saved answers")?;
+    let mut all = load_all()?;
+    all.insert(template.to_owned(), answers);
+    // Moved over the old answers once written, so that they're never read half written
+    let mut file = NamedTempFile::new_in(dir).context("Failed to write saved answers")?;
+    to_writer(&mut file, &all).context("Failed to write saved answers")?;
+    file.persist(&path)
+        .context("Failed to write saved answers")?;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::{
+    path::Path,
+    process::{ExitStatus, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::*;
+use thiserror::Error;
+
+use crate::{generate_with, load_definition, quiet, Missing, Options, Registry, Sandbox};
+
+// How long verify commands and sandboxed hooks may run for, unless configured otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VerifyError {
+    #[error("Verify command `{0}` failed with {1}")]
+    Failed(String, ExitStatus),
+    #[error("Verify command `{0}` timed out after {1:?}")]
+    TimedOut(String, Duration),
+}
+
+pub(crate) fn run(command: &str, dir: &Path, timeout: Duration, sandbox: &Sandbox) -> Result<()> {
+    let mut child = sandbox
+        .command(command, dir)
+        // What went wrong is usually on stderr, which is kept
+        .stdout(if quiet() {
+            Stdio::null()
+        } else {
+            Stdio::inherit()
+        })
+        .spawn()
+        .with_context(|| format!("Failed to run verify command `{}`", command))?;
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            ensure!(
+                status.success(),
+                VerifyError::Failed(command.to_owned(), status)
+            );
+            return Ok(());
+        }
+        if start.elapsed() > timeout {
+            child.kill()?;
+            child.wait()?;
+            bail!(VerifyError::TimedOut(command.to_owned(), timeout));
+        }
+        sleep(Duration::from_millis(50));
+    }
+}
+
+pub fn smoke(template: impl AsRef<Path>, timeout: Duration) -> Result<()> {
+    let template = template.as_ref();
+    let def = load_definition(template)?;
+    let dir = tempfile::Builder::new()
+        .prefix("generator-smoke")
+        .tempdir()
+        .context("Failed to create temporary directory")?;
+    let destination = dir.path().join("project");
+    generate_with(
+        template,
+        &destination,
+        &Options {
+            missing: Missing::Fake,
+            skip_record: true,
+            ..Options::default()
+        },
+    )?;
+    let sandbox = Registry::load()?.sandbox()?;
+    for command in &def.verify {
+        run(command, &destination, timeout, &sandbox)?;
+    }
+    dir.close()
+        .context("Failed to clean up temporary directory")
+}
@@ -0,0 +1,77 @@
+use std::{fmt, io::IsTerminal};
+
+use once_cell::sync::Lazy;
+
+// Set to anything but empty to turn colors off, as at https://no-color.org. `--no-color` sets it
+pub const NO_COLOR_ENV: &str = "NO_COLOR";
+
+// Colors are only written to terminals, so piped and redirected output stays plain
+fn colors() -> bool {
+    std::env::var_os(NO_COLOR_ENV).is_none_or(|o| o.is_empty())
+        && std::env::var_os("TERM").is_none_or(|o| o != "dumb")
+}
+
+static STDOUT: Lazy<bool> = Lazy::new(|| colors() && std::io::stdout().is_terminal());
+static STDERR: Lazy<bool> = Lazy::new(|| colors() && std::io::stderr().is_terminal());
+
+// What a piece of output is, rather than how it looks, so the same things look the same everywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Heading,
+    // Names of variables, and the like, in prompts and listings
+    Name,
+    // Defaults, descriptions and other secondary detail
+    Muted,
+    Success,
+    Warning,
+    Error,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Heading => "1",
+            Style::Name => "36",
+            Style::Muted => "2",
+            Style::Success => "32",
+            Style::Warning => "33",
+            Style::Error => "1;31",
+        }
+    }
+
+    // `value` styled for printing to stdout
+    pub fn paint<T: fmt::Display>(self, value: T) -> Painted<T> {
+        Painted {
+            style: self,
+            value,
+            enabled: *STDOUT,
+        }
+    }
+
+    // `value` styled for printing to stderr
+    pub fn paint_err<T: fmt::Display>(self, value: T) -> Painted<T> {
+        Painted {
+            style: self,
+            value,
+            enabled: *STDERR,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Painted<T> {
+    style: Style,
+    value: T,
+    enabled: bool,
+}
+
+impl<T: fmt::Display> fmt::Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.enabled {
+            return self.value.fmt(f);
+        }
+        write!(f, "\x1b[{}m", self.style.code())?;
+        self.value.fmt(f)?;
+        write!(f, "\x1b[0m")
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fmt, path::PathBuf};
+
+use crate::Style;
+
+// What a generation did, printed once it's finished
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    // Files that weren't there before
+    pub files: usize,
+    // Files that were there before, written over
+    pub overwritten: usize,
+    // Of every file written
+    pub bytes: u64,
+    // Template paths left out, with why
+    pub skipped: Vec<(PathBuf, String)>,
+    // `hooks.pre` commands run before generating, all of which passed
+    pub hooks: Vec<String>,
+    // `verify` commands run on the result, all of which passed
+    pub verified: Vec<String>,
+    // The template's `post_message`, rendered
+    pub post_message: Option<String>,
+}
+
+fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        if self.overwritten == 0 {
+            writeln!(
+                f,
+                "{}",
+                Style::Success.paint(format_args!(
+                    "Created {} file{} ({})",
+                    self.files,
+                    plural(self.files),
+                    size(self.bytes)
+                ))
+            )?;
+        } else {
+            writeln!(
+                f,
+                "{}",
+                Style::Success.paint(format_args!(
+                    "Created {} file{} and overwrote {} ({})",
+                    self.files,
+                    plural(self.files),
+                    self.overwritten,
+                    size(self.bytes)
+                ))
+            )?;
+        }
+        if !self.skipped.is_empty() {
+            writeln!(f, "{}", Style::Heading.paint("Skipped:"))?;
+            for (path, reason) in &self.skipped {
+                writeln!(
+                    f,
+                    "  {} {}",
+                    path.display(),
+                    Style::Muted.paint(format_args!("({})", reason))
+                )?;
+            }
+        }
+        if !self.hooks.is_empty() {
+            writeln!(f, "{}", Style::Heading.paint("Hooks:"))?;
+            for command in &self.hooks {
+                writeln!(f, "  {} ... {}", command, Style::Success.paint("ok"))?;
+            }
+        }
+        if !self.verified.is_empty() {
+            writeln!(f, "{}", Style::Heading.paint("Verified:"))?;
+            for command in &self.verified {
+                writeln!(f, "  {} ... {}", command, Style::Success.paint("ok"))?;
+            }
+        }
+        if let Some(message) = &self.post_message {
+            writeln!(f, "\n{}", message.trim_end())?;
+        }
+        Ok(())
+    }
+}
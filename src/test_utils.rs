@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+use tempfile::TempDir;
+
+use crate::{generate_with, harness::diff, Config, Missing, Options, DIRECTORY_OVERRIDE};
+
+// Temporary config and cache directories used in place of the user's while alive, on the
+// thread that created it
+pub struct Sandbox {
+    dir: TempDir,
+    previous: Option<(PathBuf, PathBuf)>,
+}
+
+impl Sandbox {
+    pub fn new() -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("generator-sandbox")
+            .tempdir()
+            .context("Failed to create temporary directory")?;
+        let config = dir.path().join("config");
+        let cache = dir.path().join("cache");
+        std::fs::create_dir_all(&config)?;
+        std::fs::create_dir_all(&cache)?;
+        let previous = DIRECTORY_OVERRIDE.with(|o| o.replace(Some((config, cache))));
+        Ok(Self { dir, previous })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn set_default(&self, name: &str, value: &str) -> Result<()> {
+        let mut config = Config::load()?;
+        config.set(name, value)?;
+        config.save()
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        DIRECTORY_OVERRIDE.with(|o| *o.borrow_mut() = previous);
+    }
+}
+
+// Generates `template` in a sandbox with `answers`, failing on any unanswered variable, and
+// returns how the output differs from `expected`. The destination is named after the last
+// component of `expected`, which is what `basename` will be.
+pub fn check_generates<K: Into<String>, V: Into<tera::Value>>(
+    template: impl AsRef<Path>,
+    answers: impl IntoIterator<Item = (K, V)>,
+    expected: impl AsRef<Path>,
+) -> Result<Vec<String>> {
+    let expected = expected.as_ref();
+    let sandbox = Sandbox::new()?;
+    let destination = sandbox.path().join("output").join(
+        expected
+            .file_name()
+            .context("Expected directory has no name")?,
+    );
+    generate_with(
+        template,
+        &destination,
+        &Options {
+            missing: Missing::Fail,
+            answers: answers
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+            ignore_environment: true,
+            ..Options::default()
+        },
+    )?;
+    diff(expected, &destination)
+}
+
+pub fn assert_generates<K: Into<String>, V: Into<tera::Value>>(
+    template: impl AsRef<Path>,
+    answers: impl IntoIterator<Item = (K, V)>,
+    expected: impl AsRef<Path>,
+) {
+    let template = template.as_ref();
+    match check_generates(template, answers, expected) {
+        std::result::Result::Ok(failures) if failures.is_empty() => {}
+        std::result::Result::Ok(failures) => panic!(
+            "{} did not generate the expected output:\n    {}",
+            template.display(),
+            failures.join("\n    ")
+        ),
+        Err(e) => panic!("Failed to generate {}: {:#}", template.display(), e),
+    }
+}
+
+#[macro_export]
+macro_rules! assert_generates {
+    ($template:expr, $expected:expr) => {
+        $crate::test_utils::assert_generates(
+            $template,
+            ::std::iter::empty::<(String, String)>(),
+            $expected,
+        )
+    };
+    ($template:expr, $answers:expr, $expected:expr) => {
+        $crate::test_utils::assert_generates($template, $answers, $expected)
+    };
+}
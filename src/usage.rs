@@ -0,0 +1,274 @@
+use std::{collections::BTreeSet, fs::read_to_string, path::Path};
+
+use anyhow::*;
+
+use crate::{
+    builtins, engine::references, load_ignore, template_defaults, walk, warning::Warnings,
+    Coverage, Delimiters, Engine, FileDef, TemplateDef, VariableDef, WarningKind, VARS,
+};
+
+// The variables a template's files, names and rules refer to, to compare with those it declares
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Usage {
+    referenced: BTreeSet<String>,
+}
+
+impl Usage {
+    // Variable defaults, the rules' `rename` and `skip_if`, and every templated file and, with
+    // `template_paths`, name in each layer
+    pub(crate) fn collect(def: &TemplateDef, template: &Path) -> Result<Self> {
+        let mut usage = Self::rules(def);
+        // Rule hits are counted by generation itself
+        let mut coverage = Coverage::new(def);
+        for layer in def.layers(template) {
+            let ignore = load_ignore(&layer.template, def)?;
+            for path in walk(def, &layer.root, &ignore, &mut coverage) {
+                let rule = match def.position_for(&path) {
+                    Some(i) => &def.files[i],
+                    None => continue,
+                };
+                if def.template_paths {
+                    if let Some(name) = path.file_name().and_then(|o| o.to_str()) {
+                        usage.add(def, name, None);
+                    }
+                }
+                let input = layer.root.join(&path);
+                if input.is_dir() || !def.renders(rule, &path) {
+                    continue;
+                }
+                // Files that aren't UTF-8 are copied rather than rendered
+                if let std::result::Result::Ok(source) = read_to_string(&input) {
+                    let delimiters = rule.delimiters.as_ref().or(def.delimiters.as_ref());
+                    usage.add(def, &source, delimiters);
+                }
+            }
+        }
+        Ok(usage)
+    }
+
+    fn rules(def: &TemplateDef) -> Self {
+        let mut usage = Self::default();
+        for var in &def.variables {
+            if let Some(default) = &var.default {
+                usage.add(def, default, None);
+            }
+        }
+        for rule in def.files.iter().filter(|o| o.explicit()) {
+            usage.add_rule(def, rule);
+        }
+        usage
+    }
+
+    fn add_rule(&mut self, def: &TemplateDef, rule: &FileDef) {
+        for text in rule.rename.iter().chain(&rule.skip_if) {
+            self.add(def, text, None);
+        }
+    }
+
+    fn add(&mut self, def: &TemplateDef, source: &str, delimiters: Option<&Delimiters>) {
+        if def.engine != Engine::Tera {
+            return;
+        }
+        let references = references(&def.prepare(source, delimiters)).unwrap_or_default();
+        for reference in references {
+            let mut parts = reference.split(['.', '[']);
+            let root = parts.next().unwrap_or_default();
+            // `namespace.name` and `vars.name` refer to `name`
+            let name = match parts.next() {
+                Some(name) if root == VARS || def.namespace.as_deref() == Some(root) => name,
+                _ => root,
+            };
+            self.referenced.insert(name.to_owned());
+        }
+    }
+
+    // Referenced variables that are neither declared nor provided by generator or `defaults.yml`
+    pub(crate) fn undeclared(&self, def: &TemplateDef, template: &Path) -> Result<Vec<String>> {
+        // Other engines' templates aren't parsed, so nothing is known to be referenced
+        if def.engine != Engine::Tera {
+            return Ok(vec![]);
+        }
+        let mut known = builtins::context()
+            .into_json()
+            .as_object()
+            .map(|o| o.keys().cloned().collect::<BTreeSet<_>>())
+            .unwrap_or_default();
+        known.extend(
+            ["basename", "file", "captures", VARS]
+                .iter()
+                .map(|o| (*o).to_owned()),
+        );
+        known.extend(builtins::names("").iter().map(|(o, _)| (*o).to_owned()));
+        known.extend(def.namespace.clone());
+        known.extend(def.variables.iter().map(|o| root(&o.name).to_owned()));
+        known.extend(
+            def.hooks
+                .iter()
+                .flat_map(|o| &o.provides)
+                .map(|o| root(o).to_owned()),
+        );
+        for layer in def.layers(template) {
+            if let Some(defaults) = template_defaults(&layer.template)? {
+                if let Some(defaults) = defaults.as_mapping() {
+                    known.extend(
+                        defaults
+                            .iter()
+                            .filter_map(|(k, _)| k.as_str().map(str::to_owned)),
+                    );
+                }
+            }
+        }
+        Ok(self
+            .referenced
+            .iter()
+            .filter(|o| !known.contains(*o))
+            .cloned()
+            .collect())
+    }
+
+    // Warns of declared variables nothing refers to, and of undeclared ones that are referenced
+    pub(crate) fn warn(
+        &self,
+        def: &TemplateDef,
+        template: &Path,
+        warnings: &mut Warnings,
+    ) -> Result<()> {
+        if def.engine != Engine::Tera {
+            return Ok(());
+        }
+        for var in &def.variables {
+            // Iterating over `vars` uses every variable
+            if !var.implicit
+                && !self.referenced.contains(root(&var.name))
+                && !self.referenced.contains(VARS)
+            {
+                warnings.warn(
+                    WarningKind::UnusedVariable,
+                    format!("Variable {} is declared but never used", var.name),
+                );
+            }
+        }
+        // A single file's variables are all inferred from what it refers to
+        if def.single_file {
+            return Ok(());
+        }
+        for name in self.undeclared(def, template)? {
+            warnings.warn(
+                WarningKind::UndeclaredVariable,
+                format!("Variable {} is used but not declared in `variables`", name),
+            );
+        }
+        Ok(())
+    }
+}
+
+// Whether a reference, like `author` or `author.name`, is to the variable `name` or an object
+// it's a field of
+fn refers_to(reference: &str, name: &str) -> bool {
+    let within = |a: &str, b: &str| a.strip_prefix(b).is_some_and(|o| o.starts_with(['.', '[']));
+    reference == name || within(reference, name) || within(name, reference)
+}
+
+// The indices of the variables `text` refers to, through `vars` or the namespace too
+pub(crate) fn referenced(def: &TemplateDef, text: &str) -> Vec<usize> {
+    if def.engine != Engine::Tera {
+        return vec![];
+    }
+    let references = references(&def.prepare(text, None)).unwrap_or_default();
+    let references = references
+        .iter()
+        .map(|reference| {
+            let (root, rest) = reference.split_once('.').unwrap_or((reference, ""));
+            if !rest.is_empty() && (root == VARS || def.namespace.as_deref() == Some(root)) {
+                rest
+            } else {
+                reference
+            }
+        })
+        .collect::<Vec<_>>();
+    def.variables
+        .iter()
+        .enumerate()
+        .filter(|(_, o)| references.iter().any(|r| refers_to(r, &o.name)))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// The indices of the variables each variable's default refers to
+pub(crate) fn dependencies(def: &TemplateDef) -> Vec<Vec<usize>> {
+    def.variables
+        .iter()
+        .map(|var| match &var.default {
+            Some(default) => referenced(def, default)
+                .into_iter()
+                .filter(|&o| def.variables[o].name != var.name)
+                .collect(),
+            None => vec![],
+        })
+        .collect()
+}
+
+// Variables in the order they're resolved, each after those its default refers to, and otherwise
+// in the order they're declared
+pub(crate) fn resolution_order(def: &TemplateDef) -> Result<Vec<&VariableDef>> {
+    let dependencies = dependencies(def);
+    let mut order = vec![];
+    let mut visited = vec![false; def.variables.len()];
+    let mut path = vec![];
+    for i in 0..def.variables.len() {
+        visit(i, &dependencies, &mut visited, &mut path, &mut order).map_err(|cycle| {
+            anyhow!(
+                "Variables depend on each other in a cycle: {}",
+                cycle
+                    .iter()
+                    .map(|&o| def.variables[o].name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            )
+        })?;
+    }
+    Ok(order.into_iter().map(|i| &def.variables[i]).collect())
+}
+
+// Depth first, returning the cycle if `i` is reached again through its own dependencies
+fn visit(
+    i: usize,
+    dependencies: &[Vec<usize>],
+    visited: &mut [bool],
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> std::result::Result<(), Vec<usize>> {
+    if let Some(start) = path.iter().position(|&o| o == i) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(i);
+        return Err(cycle);
+    }
+    if visited[i] {
+        return std::result::Result::Ok(());
+    }
+    path.push(i);
+    for &dependency in &dependencies[i] {
+        visit(dependency, dependencies, visited, path, order)?;
+    }
+    path.pop();
+    visited[i] = true;
+    order.push(i);
+    std::result::Result::Ok(())
+}
+
+// The object a dotted variable like `author.name` is a field of, which is what templates refer to
+fn root(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+// The definition with undeclared variables its files use declared, so that they're resolved,
+// and prompted for, along with the rest before anything is rendered
+pub(crate) fn declaring(def: &TemplateDef, undeclared: Vec<String>) -> TemplateDef {
+    let mut def = def.clone();
+    def.variables
+        .extend(undeclared.into_iter().map(|name| VariableDef {
+            name,
+            ..VariableDef::default()
+        }));
+    def
+}
@@ -0,0 +1,167 @@
+use std::{
+    fmt,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+
+use crate::{
+    builtins, definition_path,
+    engine::RenderError,
+    file_context, insert_namespace, load_definition, load_ignore, nested, template_defaults,
+    usage::{self, Usage},
+    walk,
+    warning::Warnings,
+    Coverage, TemplateDef, Warning,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+fn dummy_context(def: &TemplateDef, template: &Path) -> Result<tera::Context> {
+    let mut context = builtins::context();
+    if let Some(defaults) = template_defaults(template)? {
+        context.extend(tera::Context::from_serialize(defaults)?);
+    }
+    context.insert("basename", "project");
+    for (name, value) in &builtins::names("project") {
+        context.insert(*name, value);
+    }
+    for var in &def.variables {
+        if !nested::contains(&context, &var.name) {
+            // Plain defaults are realistic values, like a license id a function expects
+            let value = match &var.default {
+                Some(default) if !def.has_variables(default) => default.clone(),
+                _ => var.name.clone(),
+            };
+            nested::insert(&mut context, &var.name, var.normalise(value.into()));
+        }
+    }
+    // Hooks aren't run to validate, so what they provide is stood in for by its name
+    for name in def.hooks.iter().flat_map(|o| &o.provides) {
+        if !nested::contains(&context, name) {
+            nested::insert(&mut context, name, name.clone().into());
+        }
+    }
+    insert_namespace(&mut context, def);
+    Ok(context)
+}
+
+// Problems with the definition are reported against whichever file it was read from
+fn definition_name(template: &Path) -> PathBuf {
+    definition_path(template)
+        .and_then(|o| o.file_name().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("template.yml"))
+}
+
+pub fn validate(template: impl AsRef<Path>) -> Result<Vec<Issue>> {
+    let template = template.as_ref();
+    let issue = |path: &Path, line, e: Error| Issue {
+        path: path.to_owned(),
+        line,
+        column: None,
+        message: format!("{:#}", e),
+    };
+    // Failures rendering a file's `rename`, `skip_if` or name are reported as generation reports
+    // them, without the engine's internal template name
+    let render_issue = |path: &Path, source: &str, e: Error, within: &str| {
+        let e = RenderError::new(path, source, &e);
+        issue(path, None, anyhow!(e.message).context(within.to_owned()))
+    };
+    let def = match load_definition(template) {
+        Ok(def) => def,
+        Err(e) => return Ok(vec![issue(&definition_name(template), None, e)]),
+    };
+    // Files rendered together, so that includes and parents are checked with them
+    let def = match def.for_generation(&def.layers(template)) {
+        Ok(def) => def,
+        Err(e) => return Ok(vec![issue(&definition_name(template), None, e)]),
+    };
+    let context = match dummy_context(&def, template) {
+        Ok(context) => context,
+        Err(e) => return Ok(vec![issue(Path::new("defaults.yml"), None, e)]),
+    };
+    let ignore = load_ignore(template, &def)?;
+    let root = def.source_root(template);
+    let mut coverage = Coverage::new(&def);
+    let mut issues = vec![];
+    if let Err(e) = usage::resolution_order(&def) {
+        issues.push(issue(&definition_name(template), None, e));
+    }
+    for path in walk(&def, &root, &ignore, &mut coverage) {
+        let rule = match def.position_for(&path) {
+            Some(i) => &def.files[i],
+            None => continue,
+        };
+        let context = file_context(&context, &path);
+        if let Some(rename) = &rule.rename {
+            if let Err(e) = def.render_rename(rule, rename, &path, &context) {
+                issues.push(render_issue(&path, rename, e, "In `rename`"));
+            }
+        }
+        if let Some(condition) = &rule.skip_if {
+            if let Err(e) = def.render(condition, &context) {
+                issues.push(render_issue(&path, condition, e, "In `skip_if`"));
+            }
+        }
+        let name = path.file_name().and_then(|o| o.to_str()).unwrap_or("");
+        if def.template_paths && def.has_variables(name) {
+            if let Err(e) = def.render(name, &context) {
+                issues.push(render_issue(&path, name, e, "In file name"));
+            }
+        }
+        let input = root.join(&path);
+        if input.is_dir() || !def.renders(rule, &path) {
+            continue;
+        }
+        // Non UTF-8 files are copied verbatim during generation, so there's nothing to check
+        let source = match read_to_string(&input) {
+            std::result::Result::Ok(source) => source,
+            Err(_) => continue,
+        };
+        let delimiters = rule.delimiters.as_ref().or(def.delimiters.as_ref());
+        if let Err(e) = def.render_with(&source, &context, delimiters) {
+            let e = RenderError::new(&path, &source, &e);
+            issues.push(Issue {
+                line: e.line,
+                column: e.column,
+                message: e.detail(),
+                path,
+            });
+        }
+    }
+    Ok(issues)
+}
+
+// Declared variables no file refers to, and referenced variables that aren't declared
+pub fn variable_warnings(template: impl AsRef<Path>) -> Result<Vec<Warning>> {
+    let template = template.as_ref();
+    // An invalid definition is reported by `validate`
+    let def = match load_definition(template) {
+        std::result::Result::Ok(def) => def,
+        Err(_) => return Ok(vec![]),
+    };
+    let usage = Usage::collect(&def, template)?;
+    let mut warnings = Warnings::new(def.allow.iter().copied());
+    usage.warn(&def, template, &mut warnings)?;
+    Ok(warnings.emitted)
+}
@@ -0,0 +1,105 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    DeprecatedVariable,
+    DefaultRule,
+    NonUtf8,
+    UnusedVariable,
+    UndeclaredVariable,
+    Ownership,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 6] = [
+        WarningKind::DeprecatedVariable,
+        WarningKind::DefaultRule,
+        WarningKind::NonUtf8,
+        WarningKind::UnusedVariable,
+        WarningKind::UndeclaredVariable,
+        WarningKind::Ownership,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            WarningKind::DeprecatedVariable => "W001",
+            WarningKind::DefaultRule => "W002",
+            WarningKind::NonUtf8 => "W003",
+            WarningKind::UnusedVariable => "W004",
+            WarningKind::UndeclaredVariable => "W005",
+            WarningKind::Ownership => "W006",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningKind::DeprecatedVariable => "deprecated-variable",
+            WarningKind::DefaultRule => "default-rule",
+            WarningKind::NonUtf8 => "non-utf8",
+            WarningKind::UnusedVariable => "unused-variable",
+            WarningKind::UndeclaredVariable => "undeclared-variable",
+            WarningKind::Ownership => "ownership",
+        }
+    }
+}
+
+impl FromStr for WarningKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|o| o.code().eq_ignore_ascii_case(s) || o.name() == s)
+            .with_context(|| format!("Unknown warning {}", s))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "warning[{}/{}]: {}",
+            self.kind.code(),
+            self.kind.name(),
+            self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Warnings {
+    allowed: Vec<WarningKind>,
+    pub(crate) emitted: Vec<Warning>,
+}
+
+impl Warnings {
+    pub(crate) fn new(allowed: impl IntoIterator<Item = WarningKind>) -> Self {
+        Self {
+            allowed: allowed.into_iter().collect(),
+            emitted: vec![],
+        }
+    }
+
+    // An empty set of warnings sharing the same allow list, for use on another thread
+    pub(crate) fn child(&self) -> Self {
+        Self::new(self.allowed.iter().copied())
+    }
+
+    pub(crate) fn warn(&mut self, kind: WarningKind, message: impl Into<String>) {
+        if !self.allowed.contains(&kind) {
+            self.emitted.push(Warning {
+                kind,
+                message: message.into(),
+            })
+        }
+    }
+}
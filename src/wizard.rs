@@ -0,0 +1,252 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+
+use crate::{
+    command_default, generate_with, initial_context, load_definition, nested, planned_files,
+    prompt::{self, ask},
+    split_list, usage, Input, Options, Report, VariableKind,
+};
+
+// Lines of the file tree shown, so that the question stays on screen for large templates
+const TREE_LINES: usize = 20;
+
+// Drawn on the terminal's alternate screen, so the shell is left as it was afterwards
+struct Screen {
+    active: bool,
+}
+
+impl Screen {
+    fn enter() -> Self {
+        let active = std::io::stdout().is_terminal();
+        if active {
+            print!("\x1b[?1049h");
+        }
+        Self { active }
+    }
+
+    fn draw(&self, text: &str) -> Result<()> {
+        if self.active {
+            print!("\x1b[2J\x1b[H");
+        }
+        print!("{}", text);
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        if self.active {
+            print!("\x1b[?1049l");
+            let _ = std::io::stdout().flush();
+        }
+    }
+}
+
+// Files as an indented tree, with each directory once above what's in it
+fn tree(files: &[PathBuf]) -> String {
+    let mut files = files.to_vec();
+    files.sort();
+    let mut lines = vec![];
+    let mut previous: Vec<String> = vec![];
+    for file in &files {
+        let components = file
+            .iter()
+            .map(|o| o.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        let (name, directories) = match components.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
+        let shared = directories
+            .iter()
+            .zip(&previous)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for (depth, directory) in directories.iter().enumerate().skip(shared) {
+            lines.push(format!("  {}{}/", "  ".repeat(depth), directory));
+        }
+        lines.push(format!("  {}{}", "  ".repeat(directories.len()), name));
+        previous = directories.to_vec();
+    }
+    let mut text = String::new();
+    for line in lines.iter().take(TREE_LINES) {
+        text.push_str(line);
+        text.push('\n');
+    }
+    if lines.len() > TREE_LINES {
+        text.push_str(&format!("  ... and {} more\n", lines.len() - TREE_LINES));
+    }
+    text
+}
+
+// A choice can be picked by its number as well as written out
+fn pick(choices: &[String], answer: &str) -> String {
+    answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|o| o.checked_sub(1))
+        .and_then(|o| choices.get(o))
+        .cloned()
+        .unwrap_or_else(|| answer.to_owned())
+}
+
+// Asks for each variable on a screen of its own, showing what it expects, the answers so far and
+// the files they would generate, and asks again until an answer passes validation. Generation
+// then previews the files and asks for confirmation as usual.
+pub fn wizard(
+    template: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    options: &Options,
+) -> Result<Report> {
+    let template = template.as_ref();
+    let destination = destination.as_ref();
+    let def = load_definition(template)?;
+    let layers = def.layers(template);
+    // Defaults are rendered with the template's plugins
+    let def = def.for_generation(&layers)?;
+    let mut options = options.clone();
+    // Starting from the values generating would, so that variables given by `defaults.yml`, the
+    // config or the environment aren't asked for, as they wouldn't be when prompting
+    let (mut context, _) = initial_context(&def, &layers, destination, &options, &BTreeMap::new())?;
+    let title = def.metadata.name.as_deref().unwrap_or("Template");
+    // Each after those its default refers to
+    let remaining = usage::resolution_order(&def)?
+        .into_iter()
+        .filter(|o| !nested::contains(&context, &o.name))
+        .collect::<Vec<_>>();
+    // Run before drawing, as they may ask whether to, and once rather than on every redraw
+    let command_defaults = remaining
+        .iter()
+        .map(|o| command_default(o))
+        .collect::<Result<Vec<_>>>()?;
+    {
+        let screen = Screen::enter();
+        let mut error = None;
+        let mut i = 0;
+        while let Some(var) = remaining.get(i) {
+            let default = match &command_defaults[i] {
+                Some(default) => Some(default.clone()),
+                None => var
+                    .default
+                    .as_ref()
+                    .map(|o| {
+                        if def.has_variables(o) {
+                            def.render(o, &context).with_context(|| {
+                                format!("Failed to render default for variable {}", var.name)
+                            })
+                        } else {
+                            Ok(o.clone())
+                        }
+                    })
+                    .transpose()?,
+            };
+            let mut text = String::new();
+            writeln!(
+                text,
+                "{} -> {}\n\nVariable {} of {}\n",
+                title,
+                destination.display(),
+                i + 1,
+                remaining.len()
+            )?;
+            for (name, value) in &options.answers {
+                let value = value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), str::to_owned);
+                writeln!(text, "  {} = {}", name, value)?;
+            }
+            match &var.section {
+                Some(section) => writeln!(text, "\n{}: {}", section, var.name)?,
+                None => writeln!(text, "\n{}", var.name)?,
+            }
+            if var.kind == VariableKind::List {
+                writeln!(text, "  A comma separated list")?;
+            }
+            if let Some(description) = &var.description {
+                writeln!(text, "  {}", description)?;
+            }
+            if let Some(validate) = &var.validate {
+                writeln!(text, "  Must match `{}`", validate)?;
+            }
+            for (n, choice) in var.choices.iter().enumerate() {
+                let marker = if default.as_ref() == Some(choice) {
+                    '>'
+                } else {
+                    ' '
+                };
+                writeln!(text, "  {} {}) {}", marker, n + 1, choice)?;
+            }
+            if let Some(error) = error.take() {
+                writeln!(text, "\n{}", error)?;
+            }
+            // The files as the answers so far would generate them, with what's left at its
+            // default, so the tree changes as answers do
+            let mut answers = options.answers.clone();
+            for (var, default) in remaining[i..].iter().zip(&command_defaults[i..]) {
+                if var.default_from_command.is_some() {
+                    let value = default.clone().unwrap_or_else(|| var.name.clone());
+                    answers.insert(var.name.clone(), var.normalise(value.into()));
+                }
+            }
+            let preview = Options {
+                answers,
+                remember: None,
+                ..options.clone()
+            };
+            writeln!(text, "\nFiles")?;
+            match planned_files(&def, template, destination, &preview) {
+                std::result::Result::Ok(files) => text.push_str(&tree(&files)),
+                Err(e) => writeln!(text, "  Not known yet: {:#}", e)?,
+            }
+            screen.draw(&text)?;
+            let question = match (&default, var.choices.is_empty()) {
+                (Some(default), _) => format!("\nValue [{}]", default),
+                (None, false) => format!("\nValue [1-{}]", var.choices.len()),
+                (None, true) => "\nValue".to_owned(),
+            };
+            let answer = match var.input {
+                Input::Line => ask(&question)?.map(|o| o.trim().to_owned()),
+                Input::Editor => Some(prompt::editor(
+                    &var.name,
+                    default.as_deref(),
+                    options.prompt_timeout,
+                )?),
+            };
+            let answer = match answer {
+                Some(answer) if answer.trim().is_empty() => match &default {
+                    Some(default) => default.clone(),
+                    None => continue,
+                },
+                Some(answer) => answer,
+                None => bail!("Wizard cancelled"),
+            };
+            let answer = match var.kind {
+                _ if var.choices.is_empty() => answer,
+                VariableKind::String => pick(&var.choices, answer.trim()),
+                VariableKind::List => split_list(&answer)
+                    .iter()
+                    .map(|o| pick(&var.choices, o))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            let value = var.normalise(tera::Value::String(answer));
+            match var.check(&value) {
+                std::result::Result::Ok(()) => {
+                    nested::insert(&mut context, &var.name, value.clone());
+                    options.answers.insert(var.name.clone(), value);
+                    i += 1;
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+    }
+    options.confirm = true;
+    generate_with(template, destination, &options)
+}
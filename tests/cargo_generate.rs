@@ -0,0 +1,9 @@
+use generator::assert_generates;
+
+#[test]
+fn placeholder_directories_hold_liquid_files() {
+    assert_generates!(
+        "tests/templates/cargo_generate",
+        "tests/expected/hello-rust"
+    );
+}
@@ -0,0 +1,71 @@
+// Each test file includes this, using what it needs of it
+#![allow(dead_code)]
+
+use std::{fs, path::Path, process::Command};
+
+use anyhow::*;
+use generator::{Missing, Options};
+
+// Generating without asking or reading the environment, failing on any unanswered variable
+pub fn options() -> Options {
+    Options {
+        missing: Missing::Fail,
+        ignore_environment: true,
+        ..Options::default()
+    }
+}
+
+// A template in a temporary directory, from `(path, contents)` pairs, with an empty
+// `template.yml` unless one is given
+pub fn template(files: &[(&str, &str)]) -> Result<tempfile::TempDir> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("template.yml"), "{}\n")?;
+    for (path, contents) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+    Ok(dir)
+}
+
+// Runs git in `dir` as a fixed author, returning what it printed
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()?;
+    ensure!(
+        out.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+    Ok(String::from_utf8(out.stdout)?.trim().to_owned())
+}
+
+// Commits `(path, contents)` pairs to the repository at `dir`, returning the new commit
+pub fn commit(dir: &Path, files: &[(&str, &str)]) -> Result<String> {
+    for (path, contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+    git(dir, &["add", "--all"])?;
+    git(dir, &["commit", "--quiet", "--allow-empty", "-m", "commit"])?;
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+// A template in a git repository of its own, from `(path, contents)` pairs as with `template`,
+// with the `file://` URL it's fetched from
+pub fn repository(files: &[(&str, &str)]) -> Result<(tempfile::TempDir, String)> {
+    let dir = template(files)?;
+    git(dir.path(), &["init", "--quiet"])?;
+    commit(dir.path(), &[])?;
+    let path = dir.path().to_string_lossy().replace('\\', "/");
+    // Windows paths start with their drive rather than a slash
+    let separator = if path.starts_with('/') { "" } else { "/" };
+    let url = format!("file://{}{}", separator, path);
+    Ok((dir, url))
+}
@@ -0,0 +1,10 @@
+use generator::assert_generates;
+
+#[test]
+fn cookiecutter_templates_generate() {
+    assert_generates!(
+        "tests/templates/cookiecutter",
+        vec![("project_name", "widget")],
+        "tests/expected/cookiecutter"
+    );
+}
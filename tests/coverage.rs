@@ -0,0 +1,67 @@
+// The template's `verify` command runs in a shell
+#![cfg(unix)]
+
+mod common;
+
+use anyhow::*;
+use common::template;
+use generator::{test_template, test_utils::Sandbox, Profile};
+
+// `verify` makes the safe profile one a test case should use
+const TEMPLATE: &str = "variables:
+  - name: name
+  - name: ci
+    default: \"false\"
+verify:
+  - \"true\"
+files:
+  - sources: ^ci\\.yml$
+    skip_if: \"{% if ci == 'false' %}true{% endif %}\"
+  - sources: ^never$
+  - sources: ^readme\\.md$
+";
+
+#[test]
+fn coverage_reports_untested_rules_branches_variables_and_profiles() -> Result<()> {
+    let _sandbox = Sandbox::new()?;
+    let mut files = vec![
+        ("template.yml", TEMPLATE),
+        ("ci.yml", "ci"),
+        ("readme.md", "{{ name }}"),
+        ("tests/basic/answers.yml", "name: a\n"),
+        ("tests/basic/expected/readme.md", "a"),
+    ];
+    let partial = template(&files)?;
+    let report = test_template(partial.path())?;
+    assert!(report.passed(), "{:?}", report.cases);
+    assert_eq!(
+        report.coverage.uncovered(),
+        vec![
+            "file rule `^never$` never matched a path",
+            "`skip_if` of file rule `^ci\\.yml$` never false",
+            "variable `ci` never given an explicit value",
+            "profile `safe` never used by a test case",
+        ]
+    );
+
+    files.extend([
+        ("tests/safe/profile", "safe\n"),
+        ("tests/safe/answers.yml", "name: b\nci: \"true\"\n"),
+        ("tests/safe/expected/ci.yml", "ci"),
+        ("tests/safe/expected/readme.md", "b"),
+    ]);
+    let full = template(&files)?;
+    let report = test_template(full.path())?;
+    assert!(report.passed(), "{:?}", report.cases);
+    assert_eq!(
+        report.coverage.uncovered(),
+        vec!["file rule `^never$` never matched a path"]
+    );
+    let safe = report
+        .coverage
+        .profiles
+        .iter()
+        .find(|o| o.profile == Profile::Safe);
+    assert_eq!(safe.map(|o| o.cases), Some(1));
+    Ok(())
+}
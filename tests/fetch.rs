@@ -0,0 +1,19 @@
+use generator::TemplateSource;
+
+#[test]
+fn remote_files_are_explicit() {
+    assert_eq!(
+        TemplateSource::parse("https://github.com/vercel/next.js"),
+        TemplateSource::Git("https://github.com/vercel/next.js".to_owned())
+    );
+    assert_eq!(
+        TemplateSource::parse("file+https://example.com/templates/readme.md"),
+        TemplateSource::File("https://example.com/templates/readme.md".to_owned())
+    );
+    assert_eq!(
+        TemplateSource::parse("https://raw.githubusercontent.com/user/repo/HEAD/readme.md"),
+        TemplateSource::File(
+            "https://raw.githubusercontent.com/user/repo/HEAD/readme.md".to_owned()
+        )
+    );
+}
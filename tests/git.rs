@@ -0,0 +1,26 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{commit, git};
+use generator::Repo;
+
+#[test]
+fn checkout_takes_revisions_not_options() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let origin = dir.path().join("origin");
+    fs::create_dir(&origin)?;
+    git(&origin, &["init", "--quiet"])?;
+    commit(&origin, &[])?;
+    git(&origin, &["checkout", "--quiet", "-b", "dev"])?;
+    let dev = commit(&origin, &[])?;
+    git(&origin, &["checkout", "--quiet", "-"])?;
+
+    let mut repo = Repo::clone(origin.to_str().unwrap(), dir.path().join("clone"))?;
+    repo.checkout("dev")?;
+    assert_eq!(repo.head()?, dev);
+    assert!(repo.checkout("--orphan=x").is_err());
+    assert_eq!(repo.head()?, dev);
+    Ok(())
+}
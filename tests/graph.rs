@@ -0,0 +1,55 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::template;
+use generator::{graph, test_utils::Sandbox, GraphFormat};
+
+#[test]
+fn graph_links_conditions_and_profiles_to_variables() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    fs::create_dir_all(sandbox.path().join("config"))?;
+    fs::write(
+        sandbox.path().join("config/defaults.work.yml"),
+        "author: Ada\n",
+    )?;
+    let template = template(&[
+        (
+            "template.yml",
+            "variables:
+  - name: author
+  - name: ci
+    default: \"false\"
+files:
+  - sources: ^ci\\.yml$
+    skip_if: \"{% if not ci %}true{% endif %}\"
+  - sources: ^readme\\.md$
+    rename: \"{{ author }}.md\"
+",
+        ),
+        ("ci.yml", ""),
+        ("readme.md", ""),
+    ])?;
+    let dot = graph(template.path(), GraphFormat::Dot)?;
+    assert!(
+        dot.contains("profile0 [shape=note, label=\"work\"];"),
+        "{}",
+        dot
+    );
+    assert!(
+        dot.contains("profile0 -> var0 [label=\"default\"];"),
+        "{}",
+        dot
+    );
+    assert!(
+        dot.contains("var1 -> rule0 [label=\"skip_if\"];"),
+        "{}",
+        dot
+    );
+    assert!(dot.contains("var0 -> rule1 [label=\"rename\"];"), "{}", dot);
+    let mermaid = graph(template.path(), GraphFormat::Mermaid)?;
+    assert!(mermaid.contains("profile0[[\"work\"]]"), "{}", mermaid);
+    assert!(mermaid.contains("var1 -->|skip_if| rule0"), "{}", mermaid);
+    Ok(())
+}
@@ -0,0 +1,57 @@
+// Hooks run in a shell
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{generate_with, test_utils::Sandbox, Options};
+
+#[test]
+fn hooks_provide_variables() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[
+        (
+            "template.yml",
+            "hooks:\n  pre:\n    - command: \"echo 'port: 8080'\"\n      provides: [port]\n",
+        ),
+        ("port.txt", "{{ port }}"),
+    ])?;
+    let destination = sandbox.path().join("output");
+    generate_with(template.path(), &destination, &options())?;
+    assert_eq!(fs::read_to_string(destination.join("port.txt"))?, "8080");
+    Ok(())
+}
+
+// A hook given more context than a pipe holds, that prints more than one holds before reading
+// it, finishes rather than blocking on either
+#[test]
+fn hooks_exchange_large_output() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    fs::write(
+        sandbox.path().join("config").join("registry.yml"),
+        "sandbox:\n  timeout: 30\n",
+    )?;
+    let template = template(&[
+        (
+            "template.yml",
+            "variables:\n  - name: big\nhooks:\n  pre:\n    - command: \"printf 'y: '; head -c 100000 /dev/zero | tr '\\\\0' a; echo; cat > /dev/null\"\n      provides: [y]\n",
+        ),
+        ("y.txt", "{{ y | length }}"),
+    ])?;
+    let destination = sandbox.path().join("output");
+    generate_with(
+        template.path(),
+        &destination,
+        &Options {
+            answers: vec![("big".to_owned(), "b".repeat(200_000).into())]
+                .into_iter()
+                .collect(),
+            ..options()
+        },
+    )?;
+    assert_eq!(fs::read_to_string(destination.join("y.txt"))?, "100000");
+    Ok(())
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use anyhow::*;
+use common::{git, repository, template};
+use generator::{digest, fetch, test_utils::Sandbox, IntegrityError, Registry};
+
+#[test]
+fn pinned_commits_are_verified() -> Result<()> {
+    let _sandbox = Sandbox::new()?;
+    let (template, url) = repository(&[("a.txt", "a")])?;
+    let head = git(template.path(), &["rev-parse", "HEAD"])?;
+    fetch(format!("{}@{}", url, &head[..12]))?;
+    let other = "0".repeat(40);
+    let error = fetch(format!("{}@{}", url, other)).unwrap_err();
+    assert!(
+        error.downcast_ref::<IntegrityError>().is_some(),
+        "unexpected error: {:#}",
+        error
+    );
+    Ok(())
+}
+
+#[test]
+fn pinned_digests_are_verified() -> Result<()> {
+    let _sandbox = Sandbox::new()?;
+    let template = template(&[("a.txt", "a")])?;
+    let path = template
+        .path()
+        .to_str()
+        .context("Temporary path is not a string")?;
+    let expected = digest(template.path())?;
+    fetch(format!("{}@sha256:{}", path, expected))?;
+    // Pins in an alias apply to what it expands to
+    let mut registry = Registry::load()?;
+    registry.add("tampered", format!("{}@sha256:{}", path, "0".repeat(64)));
+    registry.save()?;
+    let error = fetch("tampered".to_owned()).unwrap_err();
+    assert!(
+        error.downcast_ref::<IntegrityError>().is_some(),
+        "unexpected error: {:#}",
+        error
+    );
+    Ok(())
+}
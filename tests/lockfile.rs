@@ -0,0 +1,41 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{commit, git, repository};
+use generator::{fetch, fetch_pinned, test_utils::Sandbox, Lockfile, LOCKFILE};
+
+#[test]
+fn locked_fetches_use_the_pinned_commit() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let (template, url) = repository(&[("a.txt", "first")])?;
+    let mut lock = Lockfile::load(sandbox.path().join(LOCKFILE))?;
+    lock.set(url.clone(), git(template.path(), &["rev-parse", "HEAD"])?);
+    lock.save()?;
+    commit(template.path(), &[("a.txt", "second")])?;
+
+    let lock = Lockfile::load(sandbox.path().join(LOCKFILE))?;
+    let locked = fetch_pinned(url.clone(), Some(&lock), None)?;
+    assert_eq!(fs::read_to_string(locked.join("a.txt"))?, "first");
+    let fresh = tempfile::tempdir()?;
+    let locked = fetch_pinned(url.clone(), Some(&lock), Some(fresh.path()))?;
+    assert_eq!(fs::read_to_string(locked.join("a.txt"))?, "first");
+    let latest = fetch(url)?;
+    assert_eq!(fs::read_to_string(latest.join("a.txt"))?, "second");
+    Ok(())
+}
+
+#[test]
+fn locked_fetches_refuse_unlocked_templates() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let (_template, url) = repository(&[("a.txt", "a")])?;
+    let lock = Lockfile::load(sandbox.path().join(LOCKFILE))?;
+    let error = fetch_pinned(url, Some(&lock), None).unwrap_err();
+    assert!(
+        error.to_string().contains("is not in the lockfile"),
+        "unexpected error: {:#}",
+        error
+    );
+    Ok(())
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use std::{
+    fs,
+    process::{Command, Stdio},
+};
+
+use anyhow::*;
+use common::repository;
+
+// Runs sharing a cache, as in a CI matrix, each clone or update the template's entry in turn
+// rather than at once
+#[test]
+fn concurrent_runs_share_the_cache() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (_template, url) = repository(&[("a.txt", "a")])?;
+    let runs = (0..8)
+        .map(|i| {
+            Command::new(env!("CARGO_BIN_EXE_generator"))
+                .arg("--config")
+                .arg(dir.path().join("config"))
+                .arg("--cache-dir")
+                .arg(dir.path().join("cache"))
+                .arg("--quiet")
+                .arg(&url)
+                .arg(dir.path().join(format!("output{}", i)))
+                .stdin(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    for (i, run) in runs.into_iter().enumerate() {
+        let out = run.wait_with_output()?;
+        ensure!(
+            out.status.success(),
+            "run {} failed: {}",
+            i,
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let output = dir.path().join(format!("output{}", i));
+        assert_eq!(fs::read_to_string(output.join("a.txt"))?, "a");
+    }
+    Ok(())
+}
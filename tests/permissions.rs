@@ -0,0 +1,31 @@
+// Modes are only applied where the platform has them
+#![cfg(unix)]
+
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use anyhow::*;
+use common::{options, template};
+use generator::{generate_with, test_utils::Sandbox};
+
+#[test]
+fn permissions_apply_to_matched_files() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[
+        (
+            "template.yml",
+            "files:\n  - sources: ^run\\.sh$\n    mode: \"755\"\n",
+        ),
+        ("run.sh", "#!/bin/sh\n"),
+        ("data.txt", "data"),
+    ])?;
+    let destination = sandbox.path().join("output");
+    generate_with(template.path(), &destination, &options())?;
+    let mode = |path: &str| -> Result<u32> {
+        Ok(fs::metadata(destination.join(path))?.permissions().mode() & 0o777)
+    };
+    assert_eq!(mode("run.sh")?, 0o755);
+    assert_ne!(mode("data.txt")?, 0o755);
+    Ok(())
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{assert_generates, render_file, test_utils::Sandbox};
+
+#[test]
+fn plugin_filters() {
+    assert_generates!("tests/templates/plugin", "tests/expected/plugin");
+}
+
+#[test]
+fn plugin_output_out_of_bounds() -> Result<()> {
+    let _sandbox = Sandbox::new()?;
+    let template = template(&[
+        (
+            "template.yml",
+            "plugins:\n  - plugin.wasm\nfiles:\n  - sources: ^plugin\\.wasm$\n    include: false\n  - sources: ^huge\\.txt$\n",
+        ),
+        ("huge.txt", "{{ \"anything\" | huge }}\n"),
+    ])?;
+    fs::copy(
+        "tests/templates/plugin/plugin.wasm",
+        template.path().join("plugin.wasm"),
+    )?;
+    let destination = tempfile::tempdir()?;
+    let error = render_file(
+        template.path(),
+        "huge.txt",
+        destination.path().join("out"),
+        &options(),
+    )
+    .unwrap_err();
+    assert!(
+        format!("{:#}", error).contains("out of bounds"),
+        "unexpected error: {:#}",
+        error
+    );
+    Ok(())
+}
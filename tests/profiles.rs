@@ -0,0 +1,64 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{generate_with, test_utils::Sandbox, Options, Profile};
+
+#[test]
+fn safe_profile_refuses_non_empty_destinations() -> Result<()> {
+    let _sandbox = Sandbox::new()?;
+    let template = template(&[("a.txt", "new")])?;
+    let destination = tempfile::tempdir()?;
+    fs::write(destination.path().join("a.txt"), "old")?;
+    let error = generate_with(
+        template.path(),
+        destination.path(),
+        &Options {
+            profile: Profile::Safe,
+            ..options()
+        },
+    )
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("new or empty destination"),
+        "unexpected error: {:#}",
+        error
+    );
+    assert_eq!(fs::read_to_string(destination.path().join("a.txt"))?, "old");
+    assert_eq!(fs::read_dir(destination.path())?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn fast_profile_generates_what_default_does() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[
+        ("template.yml", "variables:\n  - name: name\n"),
+        ("a.txt", "{{ name }}"),
+        ("dir/b.txt", "b"),
+    ])?;
+    let generate = |profile, name: &str| -> Result<_> {
+        let destination = sandbox.path().join(name);
+        generate_with(
+            template.path(),
+            &destination,
+            &Options {
+                profile,
+                answers: vec![("name".to_owned(), "Ada".into())]
+                    .into_iter()
+                    .collect(),
+                ..options()
+            },
+        )?;
+        Ok(destination)
+    };
+    let default = generate(Profile::Default, "default")?;
+    let fast = generate(Profile::Fast, "fast")?;
+    for path in ["a.txt", "dir/b.txt"] {
+        assert_eq!(fs::read(default.join(path))?, fs::read(fast.join(path))?);
+    }
+    assert_eq!(fs::read_to_string(fast.join("a.txt"))?, "Ada");
+    Ok(())
+}
@@ -0,0 +1,10 @@
+use generator::assert_generates;
+
+#[test]
+fn renames_nested_in_renamed_directories_follow_them() {
+    assert_generates!(
+        "tests/templates/nested_rename",
+        vec![("module", "foo")],
+        "tests/expected/nested_rename"
+    );
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{assert_generates, render_file, test_utils::Sandbox, Options};
+
+#[test]
+fn skipped_files_may_not_render() {
+    assert_generates!(
+        "tests/templates/skipped_broken",
+        "tests/expected/skipped_broken"
+    );
+}
+
+#[test]
+fn files_include_other_template_files() -> Result<()> {
+    let _sandbox = Sandbox::new()?;
+    let template = template(&[
+        (
+            "template.yml",
+            "files:\n  - sources: ^partials/\n    include: false\n",
+        ),
+        ("partials/header.txt", "# {{ title }}"),
+        ("readme.md", "{% include \"partials/header.txt\" %}\nbody"),
+    ])?;
+    let destination = tempfile::tempdir()?;
+    let rendered = render_file(
+        template.path(),
+        "readme.md",
+        destination.path().join("demo"),
+        &Options {
+            answers: vec![("title".to_owned(), "Demo".into())]
+                .into_iter()
+                .collect(),
+            ..options()
+        },
+    )?;
+    assert_eq!(rendered, "# Demo\nbody");
+    Ok(())
+}
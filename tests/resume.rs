@@ -0,0 +1,33 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{generate_with, resume, test_utils::Sandbox, Options};
+
+#[test]
+fn resume_finishes_partial_generation() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[
+        (
+            "template.yml",
+            "files:\n  - sources: ^gone\\.txt$\n    include: false\n",
+        ),
+        ("a.txt", "a"),
+        ("b.txt", "{% include \"gone.txt\" %}"),
+    ])?;
+    let destination = sandbox.path().join("output");
+    let options = Options {
+        keep_partial: true,
+        ..options()
+    };
+    assert!(generate_with(template.path(), &destination, &options).is_err());
+    assert!(destination.exists());
+    fs::write(template.path().join("gone.txt"), "b")?;
+    resume(&destination, &options)?;
+    assert_eq!(fs::read_to_string(destination.join("a.txt"))?, "a");
+    assert_eq!(fs::read_to_string(destination.join("b.txt"))?, "b");
+    assert!(!destination.join("gone.txt").exists());
+    Ok(())
+}
@@ -0,0 +1,61 @@
+// Signs with a key made by ssh-keygen
+#![cfg(unix)]
+
+mod common;
+
+use std::{fs, process::Command};
+
+use anyhow::*;
+use common::{git, repository};
+use generator::{fetch, test_utils::Sandbox};
+
+// Unsigned templates are refused once the registry requires signatures, and signed ones are
+// verified against git's allowed signers
+#[test]
+fn required_signatures_are_verified() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    fs::write(
+        sandbox.path().join("config").join("registry.yml"),
+        "require_signed: true\n",
+    )?;
+    let (template, url) = repository(&[("a.txt", "a")])?;
+    let error = fetch(url.clone()).unwrap_err();
+    assert!(
+        error.to_string().contains("valid signature"),
+        "unexpected error: {:#}",
+        error
+    );
+    // Local templates are the user's own
+    fetch(template.path().to_str().unwrap().to_owned())?;
+
+    let key = sandbox.path().join("key");
+    let status = Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-C", "test", "-f"])
+        .arg(&key)
+        .status()?;
+    ensure!(status.success(), "ssh-keygen failed");
+    let public = fs::read_to_string(key.with_extension("pub"))?;
+    let signers = sandbox.path().join("allowed_signers");
+    fs::write(&signers, format!("test@example.com {}", public))?;
+    git(
+        template.path(),
+        &[
+            "-c",
+            "gpg.format=ssh",
+            "-c",
+            &format!("user.signingkey={}", key.display()),
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-S",
+            "-m",
+            "signed",
+        ],
+    )?;
+    // Read by every git command, as a user's global config would be
+    std::env::set_var("GIT_CONFIG_COUNT", "1");
+    std::env::set_var("GIT_CONFIG_KEY_0", "gpg.ssh.allowedSignersFile");
+    std::env::set_var("GIT_CONFIG_VALUE_0", &signers);
+    fetch(url)?;
+    Ok(())
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{generate_with, test_utils::Sandbox};
+
+#[test]
+fn failed_generation_leaves_no_destination() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[("a.txt", "a"), ("b.txt", "{% include \"gone.txt\" %}")])?;
+    let destination = sandbox.path().join("output");
+    let error = generate_with(template.path(), &destination, &options()).unwrap_err();
+    assert!(
+        format!("{:#}", error).contains("gone.txt"),
+        "unexpected error: {:#}",
+        error
+    );
+    assert!(!destination.exists());
+    Ok(())
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{generate_with, test_utils::Sandbox, Options};
+
+#[test]
+fn overwritten_files_are_counted() -> Result<()> {
+    let _sandbox = Sandbox::new()?;
+    let template = template(&[("a.txt", "new"), ("b.txt", "b")])?;
+    let destination = tempfile::tempdir()?;
+    fs::write(destination.path().join("a.txt"), "old")?;
+    let report = generate_with(template.path(), destination.path(), &options())?;
+    assert_eq!(report.summary.overwritten, 1);
+    assert_eq!(fs::read_to_string(destination.path().join("a.txt"))?, "new");
+    Ok(())
+}
+
+#[test]
+fn summary_reports_skipped_files_and_post_message() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[
+        (
+            "template.yml",
+            "variables:\n  - name: name\npost_message: \"cd {{ name }}\"\nfiles:\n  - sources: ^old\\.txt$\n    skip_if: \"true\"\n",
+        ),
+        ("a.txt", "{{ name }}"),
+        ("old.txt", "old"),
+    ])?;
+    let destination = sandbox.path().join("output");
+    let report = generate_with(
+        template.path(),
+        &destination,
+        &Options {
+            answers: vec![("name".to_owned(), "demo".into())]
+                .into_iter()
+                .collect(),
+            ..options()
+        },
+    )?;
+    assert_eq!(report.summary.files, 1);
+    assert_eq!(report.summary.bytes, 4);
+    assert_eq!(
+        report.summary.skipped,
+        vec![("old.txt".into(), "`skip_if` is true".to_owned())]
+    );
+    assert_eq!(report.summary.post_message.as_deref(), Some("cd demo"));
+    Ok(())
+}
@@ -0,0 +1 @@
+print("{{ cookiecutter.project_name }}")
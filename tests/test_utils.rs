@@ -0,0 +1,51 @@
+mod common;
+
+use std::fs;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{
+    assert_generates, config_dir, generate_with, test_utils::check_generates, test_utils::Sandbox,
+};
+
+#[test]
+fn generates_expected_output() {
+    assert_generates!(
+        "tests/templates/hello",
+        vec![("name", "Ada")],
+        "tests/expected/hello"
+    );
+}
+
+#[test]
+fn reports_differences_from_expected_output() -> Result<()> {
+    let failures = check_generates(
+        "tests/templates/hello",
+        vec![("name", "Grace")],
+        "tests/expected/hello",
+    )?;
+    assert_eq!(failures.len(), 1, "{:?}", failures);
+    Ok(())
+}
+
+#[test]
+fn sandbox_keeps_defaults_apart() -> Result<()> {
+    let user = config_dir();
+    {
+        let sandbox = Sandbox::new()?;
+        assert_eq!(config_dir(), sandbox.path().join("config"));
+        sandbox.set_default("name", "Ada")?;
+        let template = template(&[
+            ("template.yml", "variables:\n  - name: name\n"),
+            ("greeting.txt", "Hello, {{ name }}!"),
+        ])?;
+        let destination = sandbox.path().join("output");
+        generate_with(template.path(), &destination, &options())?;
+        assert_eq!(
+            fs::read_to_string(destination.join("greeting.txt"))?,
+            "Hello, Ada!"
+        );
+    }
+    assert_eq!(config_dir(), user);
+    Ok(())
+}
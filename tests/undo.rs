@@ -0,0 +1,22 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use anyhow::*;
+use common::{options, template};
+use generator::{generate_with, test_utils::Sandbox, undo};
+
+#[test]
+fn undo_removes_unmodified_files() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[("a.txt", "a"), ("b.txt", "b")])?;
+    let destination = sandbox.path().join("output");
+    generate_with(template.path(), &destination, &options())?;
+    fs::write(destination.join("b.txt"), "changed")?;
+    let report = undo(&destination)?;
+    assert_eq!(report.removed, vec![Path::new("a.txt").to_owned()]);
+    assert_eq!(report.modified, vec![Path::new("b.txt").to_owned()]);
+    assert!(!destination.join("a.txt").exists());
+    assert_eq!(fs::read_to_string(destination.join("b.txt"))?, "changed");
+    Ok(())
+}
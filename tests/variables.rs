@@ -0,0 +1,27 @@
+mod common;
+
+use anyhow::*;
+use common::{options, template};
+use generator::{assert_generates, generate_with, test_utils::Sandbox};
+
+#[test]
+fn defaults_refer_to_later_variables() {
+    assert_generates!("tests/templates/ordering", "tests/expected/ordering");
+}
+
+#[test]
+fn defaults_referring_to_each_other_fail() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let template = template(&[(
+        "template.yml",
+        "variables:\n  - name: a\n    default: \"{{ b }}\"\n  - name: b\n    default: \"{{ a }}\"\n",
+    )])?;
+    let error =
+        generate_with(template.path(), sandbox.path().join("output"), &options()).unwrap_err();
+    assert!(
+        format!("{:#}", error).contains("cycle: a -> b -> a"),
+        "unexpected error: {:#}",
+        error
+    );
+    Ok(())
+}